@@ -0,0 +1,280 @@
+//! `#[derive(DdbEntity)]` for ddb_util, use it through the `ddb_util::DdbEntity` re-export
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::punctuated::Punctuated;
+use syn::{
+    parse_macro_input, Data, DeriveInput, Error, Expr, ExprLit, Fields, Lit, LitStr, Meta, Token,
+};
+
+/// A key field of the entity
+struct KeyField {
+    ident: syn::Ident,
+    ty: syn::Type,
+    /// Attribute name in DynamoDB, the field name as serde renames it
+    name: String,
+}
+
+struct Entity {
+    table: LitStr,
+    pk: KeyField,
+    sk: Option<KeyField>,
+}
+
+/// # Derive DdbEntity
+/// Implements `ItemKey` and `DdbEntity` for a struct, which brings the typed `get`, `put`,
+/// `delete`, `query` and `query_builder` methods, and generates a `<Name>Key` struct holding
+/// only the key fields, convertible into a `ddb_util::Key`.
+/// The table is given with `#[ddb(table = "...")]` on the struct, the partition key field
+/// with `#[ddb(pk)]` and the optional sort key field with `#[ddb(sk)]`. Key attribute names
+/// follow `#[serde(rename)]` on the field and `#[serde(rename_all)]` on the struct.
+#[proc_macro_derive(DdbEntity, attributes(ddb))]
+pub fn derive_ddb_entity(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match parse_entity(&input) {
+        Ok(entity) => expand(&input, &entity).into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn parse_entity(input: &DeriveInput) -> Result<Entity, Error> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(&input.generics, "DdbEntity does not support generics"));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(Error::new_spanned(input, "DdbEntity needs named fields")),
+        },
+        _ => return Err(Error::new_spanned(input, "DdbEntity can only be derived for structs")),
+    };
+    let mut table = None;
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("ddb")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("table") {
+                table = Some(meta.value()?.parse::<LitStr>()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `table = \"...\"`"))
+            }
+        })?;
+    }
+    let table = table.ok_or_else(|| {
+        Error::new(Span::call_site(), "missing #[ddb(table = \"...\")] on the struct")
+    })?;
+    let rename_all = rename_all(input)?;
+    let (mut pk, mut sk) = (None, None);
+    for field in fields {
+        for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("ddb")) {
+            attr.parse_nested_meta(|meta| {
+                let slot = if meta.path.is_ident("pk") {
+                    &mut pk
+                } else if meta.path.is_ident("sk") {
+                    &mut sk
+                } else {
+                    return Err(meta.error("expected `pk` or `sk`"));
+                };
+                if slot.is_some() {
+                    return Err(meta.error("the key field is declared twice"));
+                }
+                *slot = Some(KeyField {
+                    ident: field.ident.clone().expect("named field"),
+                    ty: field.ty.clone(),
+                    name: attribute_name(field, rename_all)?,
+                });
+                Ok(())
+            })?;
+        }
+    }
+    let pk = pk.ok_or_else(|| Error::new(Span::call_site(), "missing #[ddb(pk)] field"))?;
+    Ok(Entity { table, pk, sk })
+}
+
+/// The serde case convention of the struct, from `#[serde(rename_all = "...")]`
+fn rename_all(input: &DeriveInput) -> Result<Option<RenameRule>, Error> {
+    let mut rule = None;
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        let metas = attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
+        for meta in metas.iter().filter(|meta| meta.path().is_ident("rename_all")) {
+            let name = match meta {
+                Meta::NameValue(nv) => match &nv.value {
+                    Expr::Lit(ExprLit { lit: Lit::Str(name), .. }) => name,
+                    value => return Err(Error::new_spanned(value, "expected a string")),
+                },
+                _ => {
+                    return Err(Error::new_spanned(
+                        meta,
+                        "DdbEntity only supports `rename_all = \"...\"` for both directions",
+                    ))
+                }
+            };
+            rule = Some(RenameRule::parse(name)?);
+        }
+    }
+    Ok(rule)
+}
+
+/// The case conventions of `#[serde(rename_all)]`
+#[derive(Clone, Copy)]
+enum RenameRule {
+    Lower,
+    Upper,
+    Pascal,
+    Camel,
+    ScreamingSnake,
+    Kebab,
+    ScreamingKebab,
+}
+
+impl RenameRule {
+    fn parse(name: &LitStr) -> Result<RenameRule, Error> {
+        Ok(match name.value().as_str() {
+            "lowercase" | "snake_case" => RenameRule::Lower,
+            "UPPERCASE" => RenameRule::Upper,
+            "PascalCase" => RenameRule::Pascal,
+            "camelCase" => RenameRule::Camel,
+            "SCREAMING_SNAKE_CASE" => RenameRule::ScreamingSnake,
+            "kebab-case" => RenameRule::Kebab,
+            "SCREAMING-KEBAB-CASE" => RenameRule::ScreamingKebab,
+            _ => return Err(Error::new_spanned(name, "unknown serde rename_all rule")),
+        })
+    }
+
+    /// Renames a snake case field name the way serde does
+    fn apply(self, field: &str) -> String {
+        match self {
+            RenameRule::Lower => field.to_string(),
+            RenameRule::Upper | RenameRule::ScreamingSnake => field.to_ascii_uppercase(),
+            RenameRule::Pascal | RenameRule::Camel => {
+                let mut name = String::new();
+                let mut capitalize = matches!(self, RenameRule::Pascal);
+                for c in field.chars() {
+                    if c == '_' {
+                        capitalize = true;
+                    } else if capitalize {
+                        name.push(c.to_ascii_uppercase());
+                        capitalize = false;
+                    } else {
+                        name.push(c);
+                    }
+                }
+                name
+            }
+            RenameRule::Kebab => field.replace('_', "-"),
+            RenameRule::ScreamingKebab => field.replace('_', "-").to_ascii_uppercase(),
+        }
+    }
+}
+
+/// The `#[serde(rename = "...")]` of a field, or its name after `rename_all`
+fn attribute_name(field: &syn::Field, rename_all: Option<RenameRule>) -> Result<String, Error> {
+    for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        let metas = attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
+        for meta in metas {
+            if let Meta::NameValue(nv) = meta {
+                if let (true, Expr::Lit(lit)) = (nv.path.is_ident("rename"), &nv.value) {
+                    if let Lit::Str(name) = &lit.lit {
+                        return Ok(name.value());
+                    }
+                }
+            }
+        }
+    }
+    let name = field.ident.as_ref().expect("named field").unraw().to_string();
+    Ok(match rename_all {
+        Some(rule) => rule.apply(&name),
+        None => name,
+    })
+}
+
+fn expand(input: &DeriveInput, entity: &Entity) -> TokenStream2 {
+    let ident = &input.ident;
+    let vis = &input.vis;
+    let key_ident = format_ident!("{}Key", ident);
+    let table = &entity.table;
+    let keys: Vec<&KeyField> = std::iter::once(&entity.pk).chain(&entity.sk).collect();
+    let idents: Vec<&syn::Ident> = keys.iter().map(|key| &key.ident).collect();
+    let types: Vec<&syn::Type> = keys.iter().map(|key| &key.ty).collect();
+    let names: Vec<&String> = keys.iter().map(|key| &key.name).collect();
+    let pk_name = &entity.pk.name;
+    let sk_name = match &entity.sk {
+        Some(sk) => {
+            let name = &sk.name;
+            quote!(::std::option::Option::Some(#name))
+        }
+        None => quote!(::std::option::Option::None),
+    };
+    let typed_key = match &entity.sk {
+        Some(sk) => {
+            let (pk_ident, sk_ident, sk_name) = (&entity.pk.ident, &sk.ident, &sk.name);
+            quote! {
+                ::ddb_util::Key::hash_range(
+                    #pk_name,
+                    ::std::clone::Clone::clone(&key.#pk_ident),
+                    #sk_name,
+                    ::std::clone::Clone::clone(&key.#sk_ident),
+                )
+            }
+        }
+        None => {
+            let pk_ident = &entity.pk.ident;
+            quote!(::ddb_util::Key::hash(#pk_name, ::std::clone::Clone::clone(&key.#pk_ident)))
+        }
+    };
+    let key_doc = format!("The key fields of `{}`", ident);
+    let extract_key = quote! {
+        fn key(&self) -> ::ddb_util::DdbMap {
+            let mut key = ::ddb_util::DdbMap::new();
+            #(
+                key.insert(
+                    #names.to_string(),
+                    ::ddb_util::IntoAttributeValue::into_attribute_value(
+                        ::std::clone::Clone::clone(&self.#idents),
+                    ),
+                );
+            )*
+            key
+        }
+    };
+    quote! {
+        #[doc = #key_doc]
+        #[derive(Clone, Debug, PartialEq)]
+        #vis struct #key_ident {
+            #(pub #idents: #types,)*
+        }
+
+        impl ::ddb_util::ItemKey for #key_ident {
+            #extract_key
+        }
+
+        impl ::std::convert::From<&#ident> for #key_ident {
+            fn from(item: &#ident) -> Self {
+                #key_ident {
+                    #(#idents: ::std::clone::Clone::clone(&item.#idents),)*
+                }
+            }
+        }
+
+        impl ::std::convert::From<&#key_ident> for ::ddb_util::Key {
+            fn from(key: &#key_ident) -> Self {
+                #typed_key
+            }
+        }
+
+        impl ::ddb_util::ItemKey for #ident {
+            #extract_key
+        }
+
+        impl ::ddb_util::DdbEntity for #ident {
+            type Key = #key_ident;
+            const TABLE: &'static str = #table;
+            const PARTITION_KEY: &'static str = #pk_name;
+            const SORT_KEY: ::std::option::Option<&'static str> = #sk_name;
+
+            fn to_key(&self) -> #key_ident {
+                #key_ident::from(self)
+            }
+        }
+    }
+}
@@ -0,0 +1,599 @@
+use crate::{from_item, to_item, DdbClient, DdbError, DdbMap, ItemKey, RetryPolicy};
+use futures::stream::{self, StreamExt};
+use itertools::Itertools;
+use rusoto_dynamodb::{
+    BatchGetItemInput, BatchWriteItemInput, DeleteRequest, KeysAndAttributes, PutRequest,
+    WriteRequest,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+/// Outcome of a batch write
+#[derive(Debug, Default)]
+pub struct BatchWriteResult {
+    /// Writes that were processed
+    pub succeeded: usize,
+    /// Writes that came back unprocessed, or were throttled, at least once and were resubmitted
+    pub retried: usize,
+    /// Writes that were still unprocessed when the retry policy was used up
+    pub unprocessed: Vec<WriteRequest>,
+    /// Chunks that failed with an error which was not retried, or not retried away
+    pub errors: Vec<ChunkError>,
+}
+
+/// The error of a failed chunk together with the writes that were not processed
+#[derive(Debug)]
+pub struct ChunkError {
+    pub error: DdbError,
+    pub requests: Vec<WriteRequest>,
+}
+
+impl BatchWriteResult {
+    /// Number of writes that finally failed, either unprocessed or in a failed chunk
+    pub fn failed(&self) -> usize {
+        self.unprocessed.len() + self.errors.iter().map(|e| e.requests.len()).sum::<usize>()
+    }
+
+    fn merge(&mut self, other: BatchWriteResult) {
+        self.succeeded += other.succeeded;
+        self.retried += other.retried;
+        self.unprocessed.extend(other.unprocessed);
+        self.errors.extend(other.errors);
+    }
+}
+
+/// # Dynamodb batch write builder
+/// Sends puts and deletes for one table in chunks of 25, up to `concurrency` chunks at a time.
+/// Unprocessed items and throttled chunks are resubmitted with jittered exponential backoff
+/// according to the `RetryPolicy`, `RetryPolicy::default()` unless another one is given. A
+/// chunk that fails does not stop the other chunks, its error is reported in the result.
+/// Typed items are serialized when they are added, the first serialization error is returned
+/// by `send` before anything is written.
+/// ```
+/// # use rusoto_core::Region;
+/// # use rusoto_dynamodb::DynamoDbClient;
+/// # use std::collections::HashMap;
+/// # use std::time::Duration;
+/// # use ddb_util::*;
+///
+/// # #[cfg(feature = "local")]
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// # let local = match LocalDynamoDb::from_env()? {
+/// #     Some(local) => local,
+/// #     None => return Ok(()),
+/// # };
+/// # let relations = local.fixture("fixtures/relations.json").await?;
+/// # let relations = relations.as_str();
+/// let client = DynamoDbClient::new(Region::EuWest1);
+/// # let client = local.client().clone();
+/// let mut item: DdbMap = HashMap::new();
+/// set_kv(&mut item, "pk".to_string(), "c4c".to_string());
+/// set_kv(&mut item, "sk".to_string(), "dataset#1".to_string());
+/// let res = BatchWrite::new(relations)
+///     .put(item)
+///     .concurrency(8)
+///     .retry(RetryPolicy {
+///         max_attempts: 5,
+///         time_budget: Some(Duration::from_secs(10)),
+///         ..Default::default()
+///     })
+///     .send(&client)
+///     .await?;
+/// assert_eq!(res.failed(), 0);
+/// # local.teardown().await?;
+/// #     Ok(())
+/// # }
+/// # #[cfg(not(feature = "local"))]
+/// # fn main() {}
+/// ```
+#[derive(Debug)]
+pub struct BatchWrite {
+    table: String,
+    requests: Vec<WriteRequest>,
+    retry: RetryPolicy,
+    concurrency: usize,
+    error: Option<DdbError>,
+}
+
+impl BatchWrite {
+    pub fn new(table: &str) -> BatchWrite {
+        BatchWrite {
+            table: table.to_string(),
+            requests: Vec::new(),
+            retry: RetryPolicy::default(),
+            concurrency: 1,
+            error: None,
+        }
+    }
+
+    pub fn put(mut self, item: DdbMap) -> Self {
+        self.requests.push(WriteRequest {
+            delete_request: None,
+            put_request: Some(PutRequest { item }),
+        });
+        self
+    }
+
+    pub fn put_all(self, items: impl IntoIterator<Item = DdbMap>) -> Self {
+        items.into_iter().fold(self, BatchWrite::put)
+    }
+
+    pub fn delete(mut self, key: DdbMap) -> Self {
+        self.requests.push(WriteRequest {
+            delete_request: Some(DeleteRequest { key }),
+            put_request: None,
+        });
+        self
+    }
+
+    pub fn delete_all(self, keys: impl IntoIterator<Item = DdbMap>) -> Self {
+        keys.into_iter().fold(self, BatchWrite::delete)
+    }
+
+    /// Puts `item` serialized with `to_item`
+    pub fn put_typed<T: Serialize>(mut self, item: &T) -> Self {
+        match to_item(item) {
+            Ok(item) => self.put(item),
+            Err(e) => {
+                self.error.get_or_insert(DdbError::Serde(e));
+                self
+            }
+        }
+    }
+
+    pub fn put_all_typed<'a, T: Serialize + 'a>(
+        self, items: impl IntoIterator<Item = &'a T>,
+    ) -> Self {
+        items.into_iter().fold(self, BatchWrite::put_typed)
+    }
+
+    /// Deletes the item with the key of `item`
+    pub fn delete_typed<K: ItemKey>(self, item: &K) -> Self {
+        self.delete(item.key())
+    }
+
+    pub fn delete_all_typed<'a, K: ItemKey + 'a>(
+        self, items: impl IntoIterator<Item = &'a K>,
+    ) -> Self {
+        items.into_iter().fold(self, BatchWrite::delete_typed)
+    }
+
+    /// Deletes the items with the keys that `key` extracts from `items`
+    pub fn delete_all_by<T>(
+        self, items: impl IntoIterator<Item = T>, key: impl Fn(&T) -> DdbMap,
+    ) -> Self {
+        items.into_iter().fold(self, |batch, item| batch.delete(key(&item)))
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Number of chunks in flight at the same time, 1 by default
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub async fn send(self, client: &impl DdbClient) -> Result<BatchWriteResult, DdbError> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        let table = &self.table;
+        let retry = &self.retry;
+        let chunks = self.requests.into_iter().chunks(25);
+        let mut results = stream::iter(chunks.into_iter().map(|chunk| chunk.collect()))
+            .map(|chunk| write_chunk(client, table, chunk, retry))
+            .buffer_unordered(self.concurrency);
+        let mut result = BatchWriteResult::default();
+        while let Some(res) = results.next().await {
+            result.merge(res);
+        }
+        Ok(result)
+    }
+}
+
+async fn write_chunk(
+    client: &impl DdbClient, table: &str, chunk: Vec<WriteRequest>, retry: &RetryPolicy,
+) -> BatchWriteResult {
+    let start = Instant::now();
+    let total = chunk.len();
+    let mut pending = chunk;
+    let mut retried = 0;
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let mut m = HashMap::new();
+        m.insert(table.to_string(), pending.clone());
+        let input = BatchWriteItemInput {
+            request_items: m,
+            ..Default::default()
+        };
+        let unprocessed = match client.batch_write_item(input).await {
+            Ok(res) => res
+                .unprocessed_items
+                .and_then(|mut m| m.remove(table))
+                .unwrap_or_default(),
+            Err(e) => {
+                let error = DdbError::from(e);
+                match retry.backoff(attempts, start).filter(|_| error.is_retryable()) {
+                    Some(delay) => {
+                        retried = retried.max(pending.len());
+                        tokio::time::sleep(delay).await;
+                        continue;
+                    }
+                    None => {
+                        return BatchWriteResult {
+                            succeeded: total - pending.len(),
+                            retried,
+                            unprocessed: Vec::new(),
+                            errors: vec![ChunkError {
+                                error,
+                                requests: pending,
+                            }],
+                        }
+                    }
+                }
+            }
+        };
+        if unprocessed.is_empty() {
+            return BatchWriteResult {
+                succeeded: total,
+                retried,
+                ..Default::default()
+            };
+        }
+        match retry.backoff(attempts, start) {
+            Some(delay) => {
+                retried = retried.max(unprocessed.len());
+                tokio::time::sleep(delay).await;
+                pending = unprocessed;
+            }
+            None => {
+                return BatchWriteResult {
+                    succeeded: total - unprocessed.len(),
+                    retried,
+                    unprocessed,
+                    errors: Vec::new(),
+                }
+            }
+        }
+    }
+}
+
+/// Outcome of a batch get
+///
+/// Items are kept per table in the order the keys were added, with `None` for keys that have
+/// no item. Keys that were still unprocessed when the retry policy was used up are also `None`
+/// there, and listed in `unprocessed`.
+#[derive(Debug, Default)]
+pub struct BatchGetResult {
+    items: HashMap<String, Vec<Option<DdbMap>>>,
+    /// Keys per table that were still unprocessed when the retry policy was used up
+    pub unprocessed: HashMap<String, Vec<DdbMap>>,
+}
+
+impl BatchGetResult {
+    /// Raw items of `table`, one per key in the order the keys were added
+    pub fn items(&self, table: &str) -> &[Option<DdbMap>] {
+        self.items.get(table).map_or(&[], Vec::as_slice)
+    }
+
+    /// Removes the items of `table` from the result and deserializes them into `T`
+    pub fn take<T: DeserializeOwned>(&mut self, table: &str) -> Result<Vec<Option<T>>, DdbError> {
+        self.items
+            .remove(table)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|item| item.map(from_item).transpose())
+            .collect::<Result<_, _>>()
+            .map_err(DdbError::from)
+    }
+}
+
+/// # Dynamodb batch get builder
+/// Reads keys from one or more tables in chunks of 100. The keys of one table have to consist
+/// of the same attributes. Duplicate keys are requested once, and unprocessed keys are
+/// resubmitted with jittered exponential backoff according to the `RetryPolicy`. The items come
+/// back per table in the order of the keys, so `items[i]` belongs to the `i`th key added for
+/// that table.
+/// ```
+/// # use rusoto_core::Region;
+/// # use rusoto_dynamodb::DynamoDbClient;
+/// # use serde::Deserialize;
+/// # use std::collections::HashMap;
+/// # use ddb_util::*;
+///
+/// # #[derive(Debug, Deserialize)]
+/// # struct Dataset {
+/// #     pk: String,
+/// #     sk: String,
+/// # }
+///
+/// # #[cfg(feature = "local")]
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// # let local = match LocalDynamoDb::from_env()? {
+/// #     Some(local) => local,
+/// #     None => return Ok(()),
+/// # };
+/// # let relations = local.fixture("fixtures/relations.json").await?;
+/// # let relations = relations.as_str();
+/// # let relations_archive = local.fixture("fixtures/relations_archive.json").await?;
+/// # let relations_archive = relations_archive.as_str();
+/// let client = DynamoDbClient::new(Region::EuWest1);
+/// # let client = local.client().clone();
+/// let mut key: DdbMap = HashMap::new();
+/// set_kv(&mut key, "pk".to_string(), "c4c".to_string());
+/// set_kv(&mut key, "sk".to_string(), "dataset#1".to_string());
+/// let mut res = BatchGet::new()
+///     .key(relations, key.clone())
+///     .key(relations_archive, key)
+///     .consistent_read(true)
+///     .send(&client)
+///     .await?;
+/// let current: Vec<Option<Dataset>> = res.take(relations)?;
+/// let archived: Vec<Option<Dataset>> = res.take(relations_archive)?;
+/// # local.teardown().await?;
+/// #     Ok(())
+/// # }
+/// # #[cfg(not(feature = "local"))]
+/// # fn main() {}
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct BatchGet {
+    keys: Vec<(String, DdbMap)>,
+    consistent_read: Option<bool>,
+    retry: RetryPolicy,
+}
+
+impl BatchGet {
+    pub fn new() -> BatchGet {
+        BatchGet::default()
+    }
+
+    pub fn key(mut self, table: &str, key: DdbMap) -> Self {
+        self.keys.push((table.to_string(), key));
+        self
+    }
+
+    pub fn keys(self, table: &str, keys: impl IntoIterator<Item = DdbMap>) -> Self {
+        keys.into_iter().fold(self, |get, key| get.key(table, key))
+    }
+
+    /// Strongly consistent reads for every table
+    pub fn consistent_read(mut self, consistent_read: bool) -> Self {
+        self.consistent_read = Some(consistent_read);
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub async fn send(self, client: &impl DdbClient) -> Result<BatchGetResult, DdbError> {
+        let mut key_names: HashMap<&str, Vec<String>> = HashMap::new();
+        let mut requested: HashSet<(&str, KeyId)> = HashSet::new();
+        let mut unique: Vec<(String, DdbMap)> = Vec::new();
+        for (table, key) in &self.keys {
+            let mut names: Vec<String> = key.keys().cloned().collect();
+            names.sort();
+            let table_names = key_names.entry(table).or_insert_with(|| names.clone());
+            if *table_names != names {
+                return Err(DdbError::InvalidKey(format!(
+                    "keys of {} have different attributes, {} and {}",
+                    table,
+                    table_names.join(", "),
+                    names.join(", ")
+                )));
+            }
+            if requested.insert((table, key_id(key, &names))) {
+                unique.push((table.clone(), key.clone()));
+            }
+        }
+
+        let mut found: HashMap<(String, KeyId), DdbMap> = HashMap::new();
+        let mut unprocessed: HashMap<String, Vec<DdbMap>> = HashMap::new();
+        for chunk in &unique.into_iter().chunks(100) {
+            let mut request_items: HashMap<String, KeysAndAttributes> = HashMap::new();
+            for (table, key) in chunk {
+                request_items
+                    .entry(table)
+                    .or_insert_with(|| KeysAndAttributes {
+                        consistent_read: self.consistent_read,
+                        ..Default::default()
+                    })
+                    .keys
+                    .push(key);
+            }
+            let (responses, left) = get_chunk(client, request_items, &self.retry).await?;
+            for (table, items) in responses {
+                let names = key_names.get(table.as_str()).map_or(&[][..], Vec::as_slice);
+                for item in items {
+                    found.insert((table.clone(), key_id(&item, names)), item);
+                }
+            }
+            for (table, keys) in left {
+                unprocessed.entry(table).or_default().extend(keys.keys);
+            }
+        }
+
+        let mut result = BatchGetResult {
+            unprocessed,
+            ..Default::default()
+        };
+        for (table, key) in &self.keys {
+            let id = key_id(key, &key_names[table.as_str()]);
+            let item = found.get(&(table.clone(), id)).cloned();
+            result.items.entry(table.clone()).or_default().push(item);
+        }
+        Ok(result)
+    }
+}
+
+/// The string, number or binary value of each key attribute, in the order of the sorted
+/// attribute names, so that keys can be hashed regardless of the order of their attributes
+type KeyId = Vec<(Option<String>, Option<String>, Option<Vec<u8>>)>;
+
+/// The key attributes `names` of `item`, equal for an item and the key it was requested with
+fn key_id(item: &DdbMap, names: &[String]) -> KeyId {
+    names
+        .iter()
+        .map(|name| match item.get(name) {
+            Some(value) => (value.s.clone(), value.n.clone(), value.b.as_ref().map(|b| b.to_vec())),
+            None => (None, None, None),
+        })
+        .collect()
+}
+
+/// Sends one chunk until every key is processed or the retry policy is used up, returning the
+/// items read per table and the keys left unprocessed
+async fn get_chunk(
+    client: &impl DdbClient, mut request_items: HashMap<String, KeysAndAttributes>,
+    retry: &RetryPolicy,
+) -> Result<(HashMap<String, Vec<DdbMap>>, HashMap<String, KeysAndAttributes>), DdbError> {
+    let start = Instant::now();
+    let mut responses: HashMap<String, Vec<DdbMap>> = HashMap::new();
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let input = BatchGetItemInput {
+            request_items: request_items.clone(),
+            ..Default::default()
+        };
+        match client.batch_get_item(input).await {
+            Ok(res) => {
+                for (table, items) in res.responses.unwrap_or_default() {
+                    responses.entry(table).or_default().extend(items);
+                }
+                request_items = res.unprocessed_keys.unwrap_or_default();
+                request_items.retain(|_, keys| !keys.keys.is_empty());
+                if request_items.is_empty() {
+                    return Ok((responses, request_items));
+                }
+                match retry.backoff(attempts, start) {
+                    Some(delay) => tokio::time::sleep(delay).await,
+                    None => return Ok((responses, request_items)),
+                }
+            }
+            Err(e) => {
+                let error = DdbError::from(e);
+                match retry.backoff(attempts, start).filter(|_| error.is_retryable()) {
+                    Some(delay) => tokio::time::sleep(delay).await,
+                    None => return Err(error),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::key_id;
+    use crate::memory::probe::Probe;
+    use crate::*;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    fn datasets(n: usize) -> Vec<DdbMap> {
+        (0..n).map(|i| ddb_map! { "pk" => "c4c", "sk" => format!("dataset#{}", i) }).collect()
+    }
+
+    fn quick_retry() -> RetryPolicy {
+        RetryPolicy { base_delay: Duration::from_millis(1), ..Default::default() }
+    }
+
+    #[test]
+    fn key_id_ignores_attribute_order_and_non_key_attributes() {
+        let mut key: DdbMap = HashMap::new();
+        set_kv(&mut key, "sk".to_string(), "dataset#1".to_string());
+        set_kv(&mut key, "pk".to_string(), "c4c".to_string());
+        let mut item = key.clone();
+        set_kv(&mut item, "itemtype".to_string(), "dataset".to_string());
+        let names = ["pk".to_string(), "sk".to_string()];
+        assert_eq!(key_id(&item, &names), key_id(&key, &names));
+        set_kv(&mut item, "sk".to_string(), "dataset#2".to_string());
+        assert_ne!(key_id(&item, &names), key_id(&key, &names));
+    }
+
+    #[tokio::test]
+    async fn serialization_errors_are_returned_before_writing() {
+        let client = MemoryDb::new().table("relations", "pk", Some("sk"));
+        let res = BatchWrite::new("relations")
+            .put_all(datasets(1))
+            .put_typed(&"not a map")
+            .send(&client)
+            .await;
+        assert!(matches!(res, Err(DdbError::Serde(_))));
+        assert!(client.items("relations").is_empty());
+    }
+
+    #[tokio::test]
+    async fn chunks_are_written_concurrently() -> Result<(), DdbError> {
+        let client = Probe::new(MemoryDb::new().table("relations", "pk", Some("sk")));
+        let res = BatchWrite::new("relations")
+            .put_all(datasets(100))
+            .concurrency(3)
+            .send(&client)
+            .await?;
+        assert_eq!((res.succeeded, res.retried, res.failed()), (100, 0, 0));
+        assert_eq!(client.state().batch_writes, 4);
+        assert_eq!(client.state().max_in_flight, 3);
+        assert_eq!(client.db.items("relations").len(), 100);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unprocessed_items_are_resubmitted() -> Result<(), DdbError> {
+        let client = Probe::new(MemoryDb::new().table("relations", "pk", Some("sk")));
+        let client = client.unprocessed(30);
+        let res = BatchWrite::new("relations")
+            .put_all(datasets(50))
+            .retry(quick_retry())
+            .send(&client)
+            .await?;
+        assert_eq!((res.succeeded, res.retried, res.failed()), (50, 25, 0));
+        assert_eq!(client.state().batch_writes, 4);
+        assert_eq!(client.db.items("relations").len(), 50);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unprocessed_items_are_returned_when_the_policy_is_used_up() -> Result<(), DdbError> {
+        let client = Probe::new(MemoryDb::new().table("relations", "pk", Some("sk")));
+        let client = client.unprocessed(5);
+        let items = datasets(30);
+        let res = BatchWrite::new("relations")
+            .put_all(items.clone())
+            .retry(RetryPolicy::none())
+            .send(&client)
+            .await?;
+        assert_eq!((res.succeeded, res.failed()), (25, 5));
+        let unprocessed: Vec<DdbMap> =
+            res.unprocessed.into_iter().map(|r| r.put_request.unwrap().item).collect();
+        assert_eq!(unprocessed, items[..5]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failed_chunks_keep_their_error_and_requests() -> Result<(), DdbError> {
+        let client = MemoryDb::new().table("relations", "pk", Some("sk"));
+        let mut items = datasets(40);
+        items[30].remove("sk");
+        let res = BatchWrite::new("relations").put_all(items.clone()).send(&client).await?;
+        assert_eq!((res.succeeded, res.retried, res.failed()), (25, 0, 15));
+        let chunk = &res.errors[0];
+        assert!(matches!(chunk.error, DdbError::BatchWriteItem(_)));
+        assert!(!chunk.error.is_retryable());
+        let requests: Vec<DdbMap> =
+            chunk.requests.iter().map(|r| r.put_request.clone().unwrap().item).collect();
+        assert_eq!(requests, items[25..]);
+        assert_eq!(client.items("relations").len(), 25);
+        Ok(())
+    }
+}
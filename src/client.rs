@@ -0,0 +1,169 @@
+use crate::key::{describe_key_schema, KeySchema};
+use crate::DdbError;
+use async_trait::async_trait;
+use rusoto_core::RusotoError;
+use rusoto_dynamodb::{
+    BatchGetItemError, BatchGetItemInput, BatchGetItemOutput, BatchWriteItemError,
+    BatchWriteItemInput, BatchWriteItemOutput, DeleteItemError, DeleteItemInput, DeleteItemOutput,
+    DescribeTableError, DescribeTableInput, DescribeTableOutput, DynamoDb, GetItemError,
+    GetItemInput, GetItemOutput, PutItemError, PutItemInput, PutItemOutput, QueryError, QueryInput,
+    QueryOutput, ScanError, ScanInput, ScanOutput, TransactGetItemsError, TransactGetItemsInput,
+    TransactGetItemsOutput, TransactWriteItemsError, TransactWriteItemsInput,
+    TransactWriteItemsOutput, UpdateItemError, UpdateItemInput, UpdateItemOutput,
+};
+
+/// The DynamoDB operations used by ddb_util
+///
+/// Every function and builder takes a `&impl DdbClient`. It is implemented for every
+/// `rusoto_dynamodb::DynamoDb`, so `DynamoDbClient`, `rusoto_mock` clients and wrappers around
+/// them work as they are. Test doubles and instrumented clients only have to implement the
+/// operations listed here instead of the whole `DynamoDb` trait. `key_schema` describes the
+/// table on every call, wrap the client in a `KeySchemaCache` to describe each table once.
+#[async_trait]
+pub trait DdbClient: Send + Sync {
+    async fn get_item(
+        &self, input: GetItemInput,
+    ) -> Result<GetItemOutput, RusotoError<GetItemError>>;
+
+    async fn put_item(
+        &self, input: PutItemInput,
+    ) -> Result<PutItemOutput, RusotoError<PutItemError>>;
+
+    async fn update_item(
+        &self, input: UpdateItemInput,
+    ) -> Result<UpdateItemOutput, RusotoError<UpdateItemError>>;
+
+    async fn delete_item(
+        &self, input: DeleteItemInput,
+    ) -> Result<DeleteItemOutput, RusotoError<DeleteItemError>>;
+
+    async fn query(&self, input: QueryInput) -> Result<QueryOutput, RusotoError<QueryError>>;
+
+    async fn scan(&self, input: ScanInput) -> Result<ScanOutput, RusotoError<ScanError>>;
+
+    async fn batch_get_item(
+        &self, input: BatchGetItemInput,
+    ) -> Result<BatchGetItemOutput, RusotoError<BatchGetItemError>>;
+
+    async fn batch_write_item(
+        &self, input: BatchWriteItemInput,
+    ) -> Result<BatchWriteItemOutput, RusotoError<BatchWriteItemError>>;
+
+    async fn transact_get_items(
+        &self, input: TransactGetItemsInput,
+    ) -> Result<TransactGetItemsOutput, RusotoError<TransactGetItemsError>>;
+
+    async fn transact_write_items(
+        &self, input: TransactWriteItemsInput,
+    ) -> Result<TransactWriteItemsOutput, RusotoError<TransactWriteItemsError>>;
+
+    async fn describe_table(
+        &self, input: DescribeTableInput,
+    ) -> Result<DescribeTableOutput, RusotoError<DescribeTableError>>;
+
+    /// The key schema of `table`, used by `Key::validated`
+    async fn key_schema(&self, table: &str) -> Result<KeySchema, DdbError> {
+        describe_key_schema(self, table).await
+    }
+}
+
+#[async_trait]
+impl<D: DynamoDb> DdbClient for D {
+    async fn get_item(
+        &self, input: GetItemInput,
+    ) -> Result<GetItemOutput, RusotoError<GetItemError>> {
+        DynamoDb::get_item(self, input).await
+    }
+
+    async fn put_item(
+        &self, input: PutItemInput,
+    ) -> Result<PutItemOutput, RusotoError<PutItemError>> {
+        DynamoDb::put_item(self, input).await
+    }
+
+    async fn update_item(
+        &self, input: UpdateItemInput,
+    ) -> Result<UpdateItemOutput, RusotoError<UpdateItemError>> {
+        DynamoDb::update_item(self, input).await
+    }
+
+    async fn delete_item(
+        &self, input: DeleteItemInput,
+    ) -> Result<DeleteItemOutput, RusotoError<DeleteItemError>> {
+        DynamoDb::delete_item(self, input).await
+    }
+
+    async fn query(&self, input: QueryInput) -> Result<QueryOutput, RusotoError<QueryError>> {
+        DynamoDb::query(self, input).await
+    }
+
+    async fn scan(&self, input: ScanInput) -> Result<ScanOutput, RusotoError<ScanError>> {
+        DynamoDb::scan(self, input).await
+    }
+
+    async fn batch_get_item(
+        &self, input: BatchGetItemInput,
+    ) -> Result<BatchGetItemOutput, RusotoError<BatchGetItemError>> {
+        DynamoDb::batch_get_item(self, input).await
+    }
+
+    async fn batch_write_item(
+        &self, input: BatchWriteItemInput,
+    ) -> Result<BatchWriteItemOutput, RusotoError<BatchWriteItemError>> {
+        DynamoDb::batch_write_item(self, input).await
+    }
+
+    async fn transact_get_items(
+        &self, input: TransactGetItemsInput,
+    ) -> Result<TransactGetItemsOutput, RusotoError<TransactGetItemsError>> {
+        DynamoDb::transact_get_items(self, input).await
+    }
+
+    async fn transact_write_items(
+        &self, input: TransactWriteItemsInput,
+    ) -> Result<TransactWriteItemsOutput, RusotoError<TransactWriteItemsError>> {
+        DynamoDb::transact_write_items(self, input).await
+    }
+
+    async fn describe_table(
+        &self, input: DescribeTableInput,
+    ) -> Result<DescribeTableOutput, RusotoError<DescribeTableError>> {
+        DynamoDb::describe_table(self, input).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Dataset {
+        pk: String,
+        sk: String,
+    }
+
+    /// Only knows the client as a `DdbClient`, like application code that is tested offline
+    async fn read_back(
+        client: &impl DdbClient,
+    ) -> Result<(Option<Dataset>, Vec<Dataset>), DdbError> {
+        let key = Key::hash_range("pk", "c4c", "sk", "dataset#1");
+        let dataset = get_item(client, "relations", key, true, None).await?;
+        let datasets =
+            Query::new("relations").key_condition(attr("pk").eq("c4c")).send(client).await?;
+        Ok((dataset, datasets))
+    }
+
+    #[tokio::test]
+    async fn memory_db_is_a_ddb_client() -> Result<(), DdbError> {
+        let client = MemoryDb::new().table("relations", "pk", Some("sk"));
+        for sk in &["dataset#1", "dataset#2"] {
+            put_item(&client, "relations", ddb_map! { "pk" => "c4c", "sk" => *sk }).await?;
+        }
+        let (dataset, datasets) = read_back(&client).await?;
+        assert_eq!(dataset.unwrap().sk, "dataset#1");
+        let sks: Vec<String> = datasets.into_iter().map(|d| d.sk).collect();
+        assert_eq!(sks, vec!["dataset#1", "dataset#2"]);
+        Ok(())
+    }
+}
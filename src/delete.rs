@@ -0,0 +1,133 @@
+use crate::expression::hand_written_attributes;
+use crate::item::from_attributes;
+use crate::{DdbClient, DdbError, DdbMap, Expression, ExpressionAttributes};
+use rusoto_dynamodb::DeleteItemInput;
+use serde::de::DeserializeOwned;
+
+/// # Dynamodb delete_item builder
+/// Deletes the item with `key`, optionally guarded by a condition. With `return_values`
+/// `ALL_OLD` the deleted item is deserialized into `T`, `send` returns `None` when nothing was
+/// deleted or nothing is returned. A failed condition is `DdbError::ConditionalCheckFailed`.
+/// ```
+/// # use rusoto_core::Region;
+/// # use rusoto_dynamodb::DynamoDbClient;
+/// # use serde::Deserialize;
+/// # use std::collections::HashMap;
+/// # use ddb_util::*;
+///
+/// # #[derive(Debug, Deserialize)]
+/// # struct Dataset {
+/// #     pk: String,
+/// #     sk: String,
+/// # }
+///
+/// # #[cfg(feature = "local")]
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// # let local = match LocalDynamoDb::from_env()? {
+/// #     Some(local) => local,
+/// #     None => return Ok(()),
+/// # };
+/// # let relations = local.fixture("fixtures/relations.json").await?;
+/// # let relations = relations.as_str();
+/// let client = DynamoDbClient::new(Region::EuWest1);
+/// # let client = local.client().clone();
+/// let mut key: DdbMap = HashMap::new();
+/// set_kv(&mut key, "pk".to_string(), "c4c".to_string());
+/// set_kv(&mut key, "sk".to_string(), "dataset#1".to_string());
+/// let res = DeleteItem::new(relations, key)
+///     .condition(attr("status").eq("archived"))
+///     .return_values("ALL_OLD")
+///     .send::<Dataset>(&client)
+///     .await;
+/// match res {
+///     Ok(deleted) => println!("{:?}", deleted),
+///     Err(DdbError::ConditionalCheckFailed(_)) => println!("not archived"),
+///     Err(e) => return Err(e),
+/// }
+/// # local.teardown().await?;
+/// #     Ok(())
+/// # }
+/// # #[cfg(not(feature = "local"))]
+/// # fn main() {}
+/// ```
+#[derive(Clone, Debug)]
+pub struct DeleteItem {
+    input: DeleteItemInput,
+    attrs: ExpressionAttributes,
+}
+
+impl DeleteItem {
+    pub fn new(table: &str, key: DdbMap) -> DeleteItem {
+        DeleteItem {
+            input: DeleteItemInput {
+                table_name: table.to_string(),
+                key,
+                ..Default::default()
+            },
+            attrs: ExpressionAttributes::default(),
+        }
+    }
+
+    pub fn condition(mut self, condition_exp: impl Expression) -> Self {
+        self.input.condition_expression = Some(condition_exp.render(&mut self.attrs));
+        self
+    }
+
+    hand_written_attributes!();
+
+    /// `NONE` or `ALL_OLD`
+    pub fn return_values(mut self, return_values: &str) -> Self {
+        self.input.return_values = Some(return_values.to_string());
+        self
+    }
+
+    pub async fn send<T: DeserializeOwned>(
+        self, client: &impl DdbClient,
+    ) -> Result<Option<T>, DdbError> {
+        let input = self.attrs.apply(self.input)?;
+        Ok(from_attributes(client.delete_item(input).await?.attributes)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Dataset {
+        pk: String,
+        sk: String,
+        status: String,
+    }
+
+    fn key() -> DdbMap {
+        ddb_map! { "pk" => "c4c", "sk" => "dataset#1" }
+    }
+
+    #[tokio::test]
+    async fn deletes_on_matching_conditions_and_returns_the_old_item() -> Result<(), DdbError> {
+        let client = MemoryDb::new().table("relations", "pk", Some("sk"));
+        let mut item = key();
+        set_kv(&mut item, "status".to_string(), "open".to_string());
+        put_item(&client, "relations", item).await?;
+        let res = DeleteItem::new("relations", key())
+            .condition(attr("status").eq("archived"))
+            .send::<Dataset>(&client)
+            .await;
+        assert!(res.unwrap_err().is_conditional_check_failed());
+        assert_eq!(client.items("relations").len(), 1);
+        let deleted: Option<Dataset> = DeleteItem::new("relations", key())
+            .condition(attr("status").eq("open"))
+            .return_values("ALL_OLD")
+            .send(&client)
+            .await?;
+        assert_eq!(deleted.unwrap().status, "open");
+        assert!(client.items("relations").is_empty());
+        let deleted: Option<Dataset> =
+            DeleteItem::new("relations", key()).return_values("ALL_OLD").send(&client).await?;
+        assert_eq!(deleted, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+use crate::item::from_attributes;
+use crate::{
+    attr, put_typed_item, DdbClient, DdbError, DeleteItem, IntoAttributeValue, ItemKey, Query,
+};
+use async_trait::async_trait;
+use rusoto_dynamodb::GetItemInput;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// # Dynamodb entity
+/// The table and key schema of a type stored in DynamoDB, implemented with
+/// `#[derive(DdbEntity)]`. `#[ddb(table = "...")]` on the struct names the table, `#[ddb(pk)]`
+/// and `#[ddb(sk)]` mark the partition and sort key fields, whose attribute names follow
+/// `#[serde(rename)]` and `#[serde(rename_all)]`. The derive also implements `ItemKey` and
+/// generates a `<Name>Key` struct with only the key fields. The typed `get`, `put`, `delete`,
+/// `query` and `query_builder` methods on the table are provided by the trait, so methods of
+/// the same name on the type itself take precedence.
+/// ```
+/// # use serde::{Deserialize, Serialize};
+/// # use ddb_util::*;
+///
+/// #[derive(Debug, Deserialize, Serialize, PartialEq, DdbEntity)]
+/// #[ddb(table = "relations")]
+/// struct Dataset {
+///     #[ddb(pk)]
+///     pk: String,
+///     #[ddb(sk)]
+///     sk: String,
+///     itemtype: String,
+///     created: Option<u64>,
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// # let client = MemoryDb::new().table("relations", "pk", Some("sk"));
+/// let dataset = Dataset {
+///     pk: "c4c".to_string(),
+///     sk: "dataset#1".to_string(),
+///     itemtype: "dataset".to_string(),
+///     created: None,
+/// };
+/// dataset.put(&client).await?;
+/// let key = dataset.to_key();
+/// assert_eq!(Dataset::get(&client, &key).await?.as_ref(), Some(&dataset));
+/// let datasets = Dataset::query_builder("c4c")
+///     .filter(attr("itemtype").eq("dataset"))
+///     .send::<Dataset>(&client)
+///     .await?;
+/// assert_eq!(datasets, vec![dataset]);
+/// Dataset::delete(&client, &key).await?;
+/// #     Ok(())
+/// # }
+/// ```
+#[async_trait]
+pub trait DdbEntity: ItemKey + Sized + Send + Sync {
+    /// The generated struct with only the key fields
+    type Key: ItemKey + Sync;
+    const TABLE: &'static str;
+    const PARTITION_KEY: &'static str;
+    const SORT_KEY: Option<&'static str>;
+
+    fn to_key(&self) -> Self::Key;
+
+    /// Gets the item with `key`, `None` when it does not exist
+    async fn get<C: DdbClient>(client: &C, key: &Self::Key) -> Result<Option<Self>, DdbError>
+    where
+        Self: DeserializeOwned,
+    {
+        let input = GetItemInput {
+            table_name: Self::TABLE.to_string(),
+            key: key.key(),
+            ..Default::default()
+        };
+        Ok(from_attributes(client.get_item(input).await?.item)?)
+    }
+
+    /// Puts the item, replacing an item with the same key
+    async fn put<C: DdbClient>(&self, client: &C) -> Result<(), DdbError>
+    where
+        Self: Serialize,
+    {
+        put_typed_item(client, Self::TABLE, self).await.map(|_| ())
+    }
+
+    /// Deletes the item with `key` and returns it, `None` when it did not exist
+    async fn delete<C: DdbClient>(client: &C, key: &Self::Key) -> Result<Option<Self>, DdbError>
+    where
+        Self: DeserializeOwned,
+    {
+        DeleteItem::new(Self::TABLE, key.key()).return_values("ALL_OLD").send(client).await
+    }
+
+    /// Every item with the partition key `partition`
+    async fn query<C: DdbClient, V: IntoAttributeValue + Send>(
+        client: &C, partition: V,
+    ) -> Result<Vec<Self>, DdbError>
+    where
+        Self: DeserializeOwned,
+    {
+        Self::query_builder(partition).send(client).await
+    }
+
+    /// A query on the partition key `partition`, to add filters, a projection or a limit to
+    fn query_builder(partition: impl IntoAttributeValue) -> Query {
+        Query::new(Self::TABLE).key_condition(attr(Self::PARTITION_KEY).eq(partition))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, DdbEntity)]
+    #[ddb(table = "relations")]
+    struct Dataset {
+        #[ddb(pk)]
+        #[serde(rename = "PK")]
+        owner: String,
+        #[ddb(sk)]
+        version: u64,
+        name: String,
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq, DdbEntity)]
+    #[ddb(table = "owners")]
+    #[serde(rename_all = "camelCase")]
+    struct Owner {
+        #[ddb(pk)]
+        owner_id: String,
+        #[ddb(sk)]
+        #[serde(rename = "SK")]
+        created_at: u64,
+        display_name: String,
+    }
+
+    impl Owner {
+        /// Shares its name with a method of `DdbEntity`
+        fn get(&self) -> &str {
+            &self.display_name
+        }
+    }
+
+    fn dataset(owner: &str, version: u64) -> Dataset {
+        Dataset {
+            owner: owner.to_string(),
+            version,
+            name: format!("{}-{}", owner, version),
+        }
+    }
+
+    #[test]
+    fn keys_use_the_attribute_names() {
+        let key = dataset("c4c", 2).key();
+        assert_eq!(key.len(), 2);
+        assert_eq!(key["PK"].s.as_deref(), Some("c4c"));
+        assert_eq!(key["version"].n.as_deref(), Some("2"));
+        assert_eq!(DatasetKey::from(&dataset("c4c", 2)).key(), key);
+        assert_eq!(Dataset::PARTITION_KEY, "PK");
+        assert_eq!(Dataset::SORT_KEY, Some("version"));
+    }
+
+    #[tokio::test]
+    async fn keys_follow_rename_all() -> Result<(), DdbError> {
+        assert_eq!(Owner::PARTITION_KEY, "ownerId");
+        assert_eq!(Owner::SORT_KEY, Some("SK"));
+        let client = MemoryDb::new().table("owners", "ownerId", Some("SK"));
+        let owner = Owner {
+            owner_id: "c4c".to_string(),
+            created_at: 1,
+            display_name: "C4C".to_string(),
+        };
+        owner.put(&client).await?;
+        assert_eq!(owner.get(), "C4C");
+        let key = owner.to_key();
+        assert_eq!(<Owner as DdbEntity>::get(&client, &key).await?, Some(owner));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn typed_methods_use_the_entity_table() -> Result<(), DdbError> {
+        let client = MemoryDb::new().table("relations", "PK", Some("version"));
+        for version in 1..=3 {
+            dataset("c4c", version).put(&client).await?;
+        }
+        dataset("c4d", 1).put(&client).await?;
+        let key = DatasetKey {
+            owner: "c4c".to_string(),
+            version: 2,
+        };
+        assert_eq!(Dataset::get(&client, &key).await?, Some(dataset("c4c", 2)));
+        assert_eq!(Dataset::delete(&client, &key).await?, Some(dataset("c4c", 2)));
+        assert_eq!(Dataset::get(&client, &key).await?, None);
+        let versions: Vec<u64> =
+            Dataset::query(&client, "c4c").await?.into_iter().map(|d| d.version).collect();
+        assert_eq!(versions, vec![1, 3]);
+        Ok(())
+    }
+}
@@ -0,0 +1,578 @@
+use crate::transaction::cancellation_reasons;
+use crate::{CancellationReason, ItemError};
+use rusoto_core::request::BufferedHttpResponse;
+use rusoto_core::RusotoError;
+use rusoto_dynamodb::{
+    BatchGetItemError, BatchWriteItemError, CreateTableError, DeleteItemError, DeleteTableError,
+    DescribeTableError, GetItemError, PutItemError, QueryError, ScanError, TransactGetItemsError,
+    TransactWriteItemsError, UpdateItemError,
+};
+use std::error::Error;
+use std::fmt;
+
+/// Error returned by every ddb_util function
+///
+/// Failed requests keep the rusoto error of their operation, so nothing is lost compared to
+/// calling rusoto directly. The other variants are raised by ddb_util itself before or after a
+/// request. Use `is_throttling`, `is_conditional_check_failed` and `is_retryable` to branch on
+/// the cause without matching rusoto internals.
+#[derive(Debug)]
+pub enum DdbError {
+    /// A failed GetItem request
+    GetItem(RusotoError<GetItemError>),
+    /// A failed Query request
+    Query(RusotoError<QueryError>),
+    /// A failed Scan request
+    Scan(RusotoError<ScanError>),
+    /// A failed PutItem request
+    PutItem(RusotoError<PutItemError>),
+    /// A failed UpdateItem request
+    UpdateItem(RusotoError<UpdateItemError>),
+    /// A failed DeleteItem request
+    DeleteItem(RusotoError<DeleteItemError>),
+    /// A put, update or delete whose condition expression evaluated to false
+    ConditionalCheckFailed(String),
+    /// A failed BatchWriteItem request
+    BatchWriteItem(RusotoError<BatchWriteItemError>),
+    /// A failed BatchGetItem request
+    BatchGetItem(RusotoError<BatchGetItemError>),
+    /// A failed TransactWriteItems request that was not canceled
+    TransactWriteItems(RusotoError<TransactWriteItemsError>),
+    /// A failed TransactGetItems request
+    TransactGetItems(RusotoError<TransactGetItemsError>),
+    /// A canceled transaction, with one optional reason per operation in request order
+    TransactionCanceled(Vec<Option<CancellationReason>>),
+    /// A failed CreateTable request of the DynamoDB Local harness
+    CreateTable(RusotoError<CreateTableError>),
+    /// A failed DeleteTable request of the DynamoDB Local harness
+    DeleteTable(RusotoError<DeleteTableError>),
+    /// A failed DescribeTable request, e.g. while reading a table's key schema
+    DescribeTable(RusotoError<DescribeTableError>),
+    /// An item that could not be converted by `to_item` or `from_item`
+    Serde(ItemError),
+    /// A page token that could not be decoded
+    PageToken(serde_json::Error),
+    /// A DynamoDB Local harness that could not be set up, or a fixture that could not be read
+    Fixture(String),
+    /// An invalid `KeyTemplate` pattern, or a key that does not match its template
+    KeyTemplate(String),
+    /// A `Key` that does not match the key schema of its table
+    InvalidKey(String),
+    /// A `FromAttributeValue` conversion from an attribute of another type
+    Conversion(String),
+    /// An expression that cannot be sent, e.g. an empty `IN` list or a hand written
+    /// placeholder that is already used by a typed expression
+    Expression(String),
+    /// A transaction with more operations than the 100 DynamoDB accepts, with their number
+    TransactionTooLarge(usize),
+}
+
+impl DdbError {
+    /// True when DynamoDB rejected the request because of provisioned throughput or request limits
+    pub fn is_throttling(&self) -> bool {
+        match self {
+            DdbError::GetItem(e) => throttled(e, |e| {
+                matches!(
+                    e,
+                    GetItemError::ProvisionedThroughputExceeded(_) | GetItemError::RequestLimitExceeded(_)
+                )
+            }),
+            DdbError::Query(e) => throttled(e, |e| {
+                matches!(
+                    e,
+                    QueryError::ProvisionedThroughputExceeded(_) | QueryError::RequestLimitExceeded(_)
+                )
+            }),
+            DdbError::Scan(e) => throttled(e, |e| {
+                matches!(
+                    e,
+                    ScanError::ProvisionedThroughputExceeded(_) | ScanError::RequestLimitExceeded(_)
+                )
+            }),
+            DdbError::PutItem(e) => throttled(e, |e| {
+                matches!(
+                    e,
+                    PutItemError::ProvisionedThroughputExceeded(_) | PutItemError::RequestLimitExceeded(_)
+                )
+            }),
+            DdbError::UpdateItem(e) => throttled(e, |e| {
+                matches!(
+                    e,
+                    UpdateItemError::ProvisionedThroughputExceeded(_)
+                        | UpdateItemError::RequestLimitExceeded(_)
+                )
+            }),
+            DdbError::DeleteItem(e) => throttled(e, |e| {
+                matches!(
+                    e,
+                    DeleteItemError::ProvisionedThroughputExceeded(_)
+                        | DeleteItemError::RequestLimitExceeded(_)
+                )
+            }),
+            DdbError::BatchWriteItem(e) => throttled(e, |e| {
+                matches!(
+                    e,
+                    BatchWriteItemError::ProvisionedThroughputExceeded(_)
+                        | BatchWriteItemError::RequestLimitExceeded(_)
+                )
+            }),
+            DdbError::BatchGetItem(e) => throttled(e, |e| {
+                matches!(
+                    e,
+                    BatchGetItemError::ProvisionedThroughputExceeded(_)
+                        | BatchGetItemError::RequestLimitExceeded(_)
+                )
+            }),
+            DdbError::TransactWriteItems(e) => throttled(e, |e| {
+                matches!(
+                    e,
+                    TransactWriteItemsError::ProvisionedThroughputExceeded(_)
+                        | TransactWriteItemsError::RequestLimitExceeded(_)
+                )
+            }),
+            DdbError::TransactGetItems(e) => throttled(e, |e| {
+                matches!(
+                    e,
+                    TransactGetItemsError::ProvisionedThroughputExceeded(_)
+                        | TransactGetItemsError::RequestLimitExceeded(_)
+                )
+            }),
+            DdbError::TransactionCanceled(reasons) => reasons.iter().flatten().any(|r| {
+                matches!(
+                    r,
+                    CancellationReason::ProvisionedThroughputExceeded
+                        | CancellationReason::ThrottlingError
+                )
+            }),
+            DdbError::CreateTable(e) => {
+                throttled(e, |e| matches!(e, CreateTableError::LimitExceeded(_)))
+            }
+            DdbError::DeleteTable(e) => {
+                throttled(e, |e| matches!(e, DeleteTableError::LimitExceeded(_)))
+            }
+            DdbError::DescribeTable(e) => throttled(e, |_| false),
+            DdbError::ConditionalCheckFailed(_)
+            | DdbError::Serde(_)
+            | DdbError::PageToken(_)
+            | DdbError::Fixture(_)
+            | DdbError::KeyTemplate(_)
+            | DdbError::InvalidKey(_)
+            | DdbError::Conversion(_)
+            | DdbError::Expression(_)
+            | DdbError::TransactionTooLarge(_) => false,
+        }
+    }
+
+    /// True when a write was rejected because its condition expression evaluated to false
+    pub fn is_conditional_check_failed(&self) -> bool {
+        match self {
+            DdbError::ConditionalCheckFailed(_) => true,
+            DdbError::TransactionCanceled(reasons) => reasons
+                .iter()
+                .any(|r| r == &Some(CancellationReason::ConditionalCheckFailed)),
+            _ => false,
+        }
+    }
+
+    /// True when the same request may succeed if it is sent again later
+    ///
+    /// This covers throttling, internal server errors, 5xx responses and failures to dispatch
+    /// the http request.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            DdbError::GetItem(e) => transient(e, |e| {
+                matches!(
+                    e,
+                    GetItemError::InternalServerError(_)
+                        | GetItemError::ProvisionedThroughputExceeded(_)
+                        | GetItemError::RequestLimitExceeded(_)
+                )
+            }),
+            DdbError::Query(e) => transient(e, |e| {
+                matches!(
+                    e,
+                    QueryError::InternalServerError(_)
+                        | QueryError::ProvisionedThroughputExceeded(_)
+                        | QueryError::RequestLimitExceeded(_)
+                )
+            }),
+            DdbError::Scan(e) => transient(e, |e| {
+                matches!(
+                    e,
+                    ScanError::InternalServerError(_)
+                        | ScanError::ProvisionedThroughputExceeded(_)
+                        | ScanError::RequestLimitExceeded(_)
+                )
+            }),
+            DdbError::PutItem(e) => transient(e, |e| {
+                matches!(
+                    e,
+                    PutItemError::InternalServerError(_)
+                        | PutItemError::ProvisionedThroughputExceeded(_)
+                        | PutItemError::RequestLimitExceeded(_)
+                        | PutItemError::TransactionConflict(_)
+                )
+            }),
+            DdbError::UpdateItem(e) => transient(e, |e| {
+                matches!(
+                    e,
+                    UpdateItemError::InternalServerError(_)
+                        | UpdateItemError::ProvisionedThroughputExceeded(_)
+                        | UpdateItemError::RequestLimitExceeded(_)
+                        | UpdateItemError::TransactionConflict(_)
+                )
+            }),
+            DdbError::DeleteItem(e) => transient(e, |e| {
+                matches!(
+                    e,
+                    DeleteItemError::InternalServerError(_)
+                        | DeleteItemError::ProvisionedThroughputExceeded(_)
+                        | DeleteItemError::RequestLimitExceeded(_)
+                        | DeleteItemError::TransactionConflict(_)
+                )
+            }),
+            DdbError::BatchWriteItem(e) => transient(e, |e| {
+                matches!(
+                    e,
+                    BatchWriteItemError::InternalServerError(_)
+                        | BatchWriteItemError::ProvisionedThroughputExceeded(_)
+                        | BatchWriteItemError::RequestLimitExceeded(_)
+                )
+            }),
+            DdbError::BatchGetItem(e) => transient(e, |e| {
+                matches!(
+                    e,
+                    BatchGetItemError::InternalServerError(_)
+                        | BatchGetItemError::ProvisionedThroughputExceeded(_)
+                        | BatchGetItemError::RequestLimitExceeded(_)
+                )
+            }),
+            DdbError::TransactWriteItems(e) => transient(e, |e| {
+                matches!(
+                    e,
+                    TransactWriteItemsError::InternalServerError(_)
+                        | TransactWriteItemsError::ProvisionedThroughputExceeded(_)
+                        | TransactWriteItemsError::RequestLimitExceeded(_)
+                        | TransactWriteItemsError::TransactionInProgress(_)
+                )
+            }),
+            DdbError::TransactGetItems(e) => transient(e, |e| {
+                matches!(
+                    e,
+                    TransactGetItemsError::InternalServerError(_)
+                        | TransactGetItemsError::ProvisionedThroughputExceeded(_)
+                        | TransactGetItemsError::RequestLimitExceeded(_)
+                )
+            }),
+            DdbError::TransactionCanceled(reasons) => {
+                reasons.iter().any(Option::is_some)
+                    && reasons.iter().flatten().all(|r| {
+                        matches!(
+                            r,
+                            CancellationReason::TransactionConflict
+                                | CancellationReason::ProvisionedThroughputExceeded
+                                | CancellationReason::ThrottlingError
+                        )
+                    })
+            }
+            DdbError::CreateTable(e) => transient(e, |e| {
+                matches!(
+                    e,
+                    CreateTableError::InternalServerError(_) | CreateTableError::LimitExceeded(_)
+                )
+            }),
+            DdbError::DeleteTable(e) => transient(e, |e| {
+                matches!(
+                    e,
+                    DeleteTableError::InternalServerError(_)
+                        | DeleteTableError::LimitExceeded(_)
+                        | DeleteTableError::ResourceInUse(_)
+                )
+            }),
+            DdbError::DescribeTable(e) => {
+                transient(e, |e| matches!(e, DescribeTableError::InternalServerError(_)))
+            }
+            DdbError::ConditionalCheckFailed(_)
+            | DdbError::Serde(_)
+            | DdbError::PageToken(_)
+            | DdbError::Fixture(_)
+            | DdbError::KeyTemplate(_)
+            | DdbError::InvalidKey(_)
+            | DdbError::Conversion(_)
+            | DdbError::Expression(_)
+            | DdbError::TransactionTooLarge(_) => false,
+        }
+    }
+}
+
+/// Throttling errors that are not modelled by rusoto_dynamodb end up as unknown responses
+fn throttling_response(res: &BufferedHttpResponse) -> bool {
+    let body = res.body_as_str();
+    body.contains("ThrottlingException") || body.contains("ProvisionedThroughputExceededException")
+}
+
+fn throttled<E>(err: &RusotoError<E>, service: impl Fn(&E) -> bool) -> bool {
+    match err {
+        RusotoError::Service(e) => service(e),
+        RusotoError::Unknown(res) => throttling_response(res),
+        _ => false,
+    }
+}
+
+fn transient<E>(err: &RusotoError<E>, service: impl Fn(&E) -> bool) -> bool {
+    match err {
+        RusotoError::Service(e) => service(e),
+        RusotoError::HttpDispatch(_) => true,
+        RusotoError::Unknown(res) => res.status.is_server_error() || throttling_response(res),
+        _ => false,
+    }
+}
+
+impl fmt::Display for DdbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DdbError::GetItem(e) => write!(f, "get_item failed: {}", e),
+            DdbError::Query(e) => write!(f, "query failed: {}", e),
+            DdbError::Scan(e) => write!(f, "scan failed: {}", e),
+            DdbError::PutItem(e) => write!(f, "put_item failed: {}", e),
+            DdbError::UpdateItem(e) => write!(f, "update_item failed: {}", e),
+            DdbError::DeleteItem(e) => write!(f, "delete_item failed: {}", e),
+            DdbError::ConditionalCheckFailed(msg) => write!(f, "conditional check failed: {}", msg),
+            DdbError::BatchWriteItem(e) => write!(f, "batch_write_item failed: {}", e),
+            DdbError::BatchGetItem(e) => write!(f, "batch_get_item failed: {}", e),
+            DdbError::TransactWriteItems(e) => write!(f, "transact_write_items failed: {}", e),
+            DdbError::TransactGetItems(e) => write!(f, "transact_get_items failed: {}", e),
+            DdbError::TransactionCanceled(reasons) => {
+                let reasons: Vec<String> = reasons
+                    .iter()
+                    .map(|r| r.as_ref().map_or("None".to_string(), ToString::to_string))
+                    .collect();
+                write!(f, "transaction canceled: [{}]", reasons.join(", "))
+            }
+            DdbError::CreateTable(e) => write!(f, "create_table failed: {}", e),
+            DdbError::DeleteTable(e) => write!(f, "delete_table failed: {}", e),
+            DdbError::DescribeTable(e) => write!(f, "describe_table failed: {}", e),
+            DdbError::Serde(e) => write!(f, "item conversion failed: {}", e),
+            DdbError::PageToken(e) => write!(f, "invalid page token: {}", e),
+            DdbError::Fixture(msg) => write!(f, "DynamoDB Local setup failed: {}", msg),
+            DdbError::KeyTemplate(msg) => write!(f, "key template error: {}", msg),
+            DdbError::InvalidKey(msg) => write!(f, "invalid key: {}", msg),
+            DdbError::Conversion(msg) => write!(f, "attribute conversion failed: {}", msg),
+            DdbError::Expression(msg) => write!(f, "invalid expression: {}", msg),
+            DdbError::TransactionTooLarge(n) => {
+                write!(f, "transaction has {} operations, at most 100 are allowed", n)
+            }
+        }
+    }
+}
+
+impl Error for DdbError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DdbError::GetItem(e) => Some(e),
+            DdbError::Query(e) => Some(e),
+            DdbError::Scan(e) => Some(e),
+            DdbError::PutItem(e) => Some(e),
+            DdbError::UpdateItem(e) => Some(e),
+            DdbError::DeleteItem(e) => Some(e),
+            DdbError::ConditionalCheckFailed(_) => None,
+            DdbError::BatchWriteItem(e) => Some(e),
+            DdbError::BatchGetItem(e) => Some(e),
+            DdbError::TransactWriteItems(e) => Some(e),
+            DdbError::TransactGetItems(e) => Some(e),
+            DdbError::TransactionCanceled(_) => None,
+            DdbError::CreateTable(e) => Some(e),
+            DdbError::DeleteTable(e) => Some(e),
+            DdbError::DescribeTable(e) => Some(e),
+            DdbError::Serde(e) => Some(e),
+            DdbError::PageToken(e) => Some(e),
+            DdbError::Fixture(_)
+            | DdbError::KeyTemplate(_)
+            | DdbError::InvalidKey(_)
+            | DdbError::Conversion(_)
+            | DdbError::Expression(_)
+            | DdbError::TransactionTooLarge(_) => None,
+        }
+    }
+}
+
+impl From<RusotoError<GetItemError>> for DdbError {
+    fn from(e: RusotoError<GetItemError>) -> Self {
+        DdbError::GetItem(e)
+    }
+}
+
+impl From<RusotoError<QueryError>> for DdbError {
+    fn from(e: RusotoError<QueryError>) -> Self {
+        DdbError::Query(e)
+    }
+}
+
+impl From<RusotoError<ScanError>> for DdbError {
+    fn from(e: RusotoError<ScanError>) -> Self {
+        DdbError::Scan(e)
+    }
+}
+
+impl From<RusotoError<PutItemError>> for DdbError {
+    fn from(e: RusotoError<PutItemError>) -> Self {
+        match e {
+            RusotoError::Service(PutItemError::ConditionalCheckFailed(msg)) => {
+                DdbError::ConditionalCheckFailed(msg)
+            }
+            e => DdbError::PutItem(e),
+        }
+    }
+}
+
+impl From<RusotoError<UpdateItemError>> for DdbError {
+    fn from(e: RusotoError<UpdateItemError>) -> Self {
+        match e {
+            RusotoError::Service(UpdateItemError::ConditionalCheckFailed(msg)) => {
+                DdbError::ConditionalCheckFailed(msg)
+            }
+            e => DdbError::UpdateItem(e),
+        }
+    }
+}
+
+impl From<RusotoError<DeleteItemError>> for DdbError {
+    fn from(e: RusotoError<DeleteItemError>) -> Self {
+        match e {
+            RusotoError::Service(DeleteItemError::ConditionalCheckFailed(msg)) => {
+                DdbError::ConditionalCheckFailed(msg)
+            }
+            e => DdbError::DeleteItem(e),
+        }
+    }
+}
+
+impl From<RusotoError<BatchWriteItemError>> for DdbError {
+    fn from(e: RusotoError<BatchWriteItemError>) -> Self {
+        DdbError::BatchWriteItem(e)
+    }
+}
+
+impl From<RusotoError<BatchGetItemError>> for DdbError {
+    fn from(e: RusotoError<BatchGetItemError>) -> Self {
+        DdbError::BatchGetItem(e)
+    }
+}
+
+impl From<RusotoError<TransactWriteItemsError>> for DdbError {
+    fn from(e: RusotoError<TransactWriteItemsError>) -> Self {
+        match &e {
+            RusotoError::Service(TransactWriteItemsError::TransactionCanceled(message)) => {
+                match cancellation_reasons(message) {
+                    Some(reasons) => DdbError::TransactionCanceled(reasons),
+                    None => DdbError::TransactWriteItems(e),
+                }
+            }
+            _ => DdbError::TransactWriteItems(e),
+        }
+    }
+}
+
+impl From<RusotoError<TransactGetItemsError>> for DdbError {
+    fn from(e: RusotoError<TransactGetItemsError>) -> Self {
+        match &e {
+            RusotoError::Service(TransactGetItemsError::TransactionCanceled(message)) => {
+                match cancellation_reasons(message) {
+                    Some(reasons) => DdbError::TransactionCanceled(reasons),
+                    None => DdbError::TransactGetItems(e),
+                }
+            }
+            _ => DdbError::TransactGetItems(e),
+        }
+    }
+}
+
+impl From<RusotoError<CreateTableError>> for DdbError {
+    fn from(e: RusotoError<CreateTableError>) -> Self {
+        DdbError::CreateTable(e)
+    }
+}
+
+impl From<RusotoError<DeleteTableError>> for DdbError {
+    fn from(e: RusotoError<DeleteTableError>) -> Self {
+        DdbError::DeleteTable(e)
+    }
+}
+
+impl From<RusotoError<DescribeTableError>> for DdbError {
+    fn from(e: RusotoError<DescribeTableError>) -> Self {
+        DdbError::DescribeTable(e)
+    }
+}
+
+impl From<ItemError> for DdbError {
+    fn from(e: ItemError) -> Self {
+        DdbError::Serde(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusoto_core::credential::CredentialsError;
+    use rusoto_core::request::HttpDispatchError;
+
+    fn unknown<E>(status: u16, body: &str) -> RusotoError<E> {
+        RusotoError::Unknown(BufferedHttpResponse {
+            status: http::StatusCode::from_u16(status).unwrap(),
+            body: body.to_string().into(),
+            headers: Default::default(),
+        })
+    }
+
+    fn svc<E>(e: E) -> RusotoError<E> {
+        RusotoError::Service(e)
+    }
+
+    /// `expected` is `[is_throttling, is_conditional_check_failed, is_retryable]`
+    fn check(err: impl Into<DdbError>, expected: [bool; 3]) {
+        let err = err.into();
+        let actual = [err.is_throttling(), err.is_conditional_check_failed(), err.is_retryable()];
+        assert_eq!(actual, expected, "{:?}", err);
+    }
+
+    #[test]
+    fn helpers_classify_service_and_http_errors() {
+        let m = || "msg".to_string();
+        check(svc(GetItemError::ProvisionedThroughputExceeded(m())), [true, false, true]);
+        check(svc(GetItemError::ResourceNotFound(m())), [false, false, false]);
+        check(svc(QueryError::RequestLimitExceeded(m())), [true, false, true]);
+        check(svc(ScanError::InternalServerError(m())), [false, false, true]);
+        check(svc(PutItemError::ConditionalCheckFailed(m())), [false, true, false]);
+        check(svc(PutItemError::TransactionConflict(m())), [false, false, true]);
+        check(svc(UpdateItemError::ConditionalCheckFailed(m())), [false, true, false]);
+        check(svc(DeleteItemError::ItemCollectionSizeLimitExceeded(m())), [false, false, false]);
+        check(svc(BatchWriteItemError::RequestLimitExceeded(m())), [true, false, true]);
+        check(svc(BatchGetItemError::ResourceNotFound(m())), [false, false, false]);
+        check(svc(TransactWriteItemsError::TransactionInProgress(m())), [false, false, true]);
+        check(svc(TransactGetItemsError::InternalServerError(m())), [false, false, true]);
+        check(svc(DescribeTableError::InternalServerError(m())), [false, false, true]);
+        check(svc(CreateTableError::LimitExceeded(m())), [true, false, true]);
+        check(svc(DeleteTableError::ResourceInUse(m())), [false, false, true]);
+
+        let dispatch = RusotoError::HttpDispatch(HttpDispatchError::new(m()));
+        check(DdbError::GetItem(dispatch), [false, false, true]);
+        let credentials = RusotoError::Credentials(CredentialsError::new("expired"));
+        check(DdbError::Query(credentials), [false, false, false]);
+        check(DdbError::Scan(RusotoError::Validation(m())), [false, false, false]);
+        check(DdbError::PutItem(RusotoError::ParseError(m())), [false, false, false]);
+        check(DdbError::UpdateItem(RusotoError::Blocking), [false, false, false]);
+        check(DdbError::DeleteItem(unknown(500, "")), [false, false, true]);
+        check(DdbError::BatchGetItem(unknown(400, "ThrottlingException")), [true, false, true]);
+        check(DdbError::BatchWriteItem(unknown(400, "ValidationException")), [false, false, false]);
+
+        let reasons = vec![None, Some(CancellationReason::ConditionalCheckFailed)];
+        check(DdbError::TransactionCanceled(reasons), [false, true, false]);
+        let reasons = vec![Some(CancellationReason::ThrottlingError), None];
+        check(DdbError::TransactionCanceled(reasons), [true, false, true]);
+        let reasons = vec![Some(CancellationReason::TransactionConflict)];
+        check(DdbError::TransactionCanceled(reasons), [false, false, true]);
+        check(DdbError::TransactionCanceled(vec![None]), [false, false, false]);
+        check(DdbError::InvalidKey(m()), [false, false, false]);
+    }
+}
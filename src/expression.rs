@@ -0,0 +1,718 @@
+use crate::{DdbError, DdbMap, IntoAttributeValue};
+use rusoto_dynamodb::{
+    AttributeValue, ConditionCheck, Delete, DeleteItemInput, Put, PutItemInput, QueryInput,
+    ScanInput, Update, UpdateItemInput,
+};
+use std::collections::{HashMap, HashSet};
+use std::ops;
+
+/// Expression attribute names and values collected while rendering expressions
+///
+/// Every attribute name is replaced by a `#n<i>` placeholder and every value by a `:v<i>`
+/// placeholder, so reserved words like `name`, `status` or `ttl` need no special care.
+/// Placeholders that are already taken, e.g. by hand written values, are skipped. Render all
+/// the expressions of one request into the same `ExpressionAttributes`, and take the maps out
+/// with `into_maps`, which fails on expressions that DynamoDB would reject.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExpressionAttributes {
+    pub names: HashMap<String, String>,
+    pub values: DdbMap,
+    generated: HashSet<String>,
+    errors: Vec<String>,
+}
+
+impl ExpressionAttributes {
+    /// Returns the placeholder for an attribute name, reusing it if the name was seen before
+    pub fn name(&mut self, name: &str) -> String {
+        if let Some((placeholder, _)) = self.names.iter().find(|(_, n)| *n == name) {
+            return placeholder.clone();
+        }
+        let placeholder = (self.names.len()..)
+            .map(|i| format!("#n{}", i))
+            .find(|p| !self.names.contains_key(p))
+            .unwrap();
+        self.names.insert(placeholder.clone(), name.to_string());
+        self.generated.insert(placeholder.clone());
+        placeholder
+    }
+
+    /// Returns a new placeholder bound to `value`
+    pub fn value(&mut self, value: AttributeValue) -> String {
+        let placeholder = (self.values.len()..)
+            .map(|i| format!(":v{}", i))
+            .find(|p| !self.values.contains_key(p))
+            .unwrap();
+        self.values.insert(placeholder.clone(), value);
+        self.generated.insert(placeholder.clone());
+        placeholder
+    }
+
+    /// Renders a document path such as `address.city` or `items[0].price`
+    pub fn path(&mut self, path: &str) -> String {
+        path.split('.')
+            .map(|segment| match segment.find('[') {
+                Some(i) => format!("{}{}", self.name(&segment[..i]), &segment[i..]),
+                None => self.name(segment),
+            })
+            .collect::<Vec<String>>()
+            .join(".")
+    }
+
+    /// Adds values for hand written expressions, a placeholder that was already generated for
+    /// a typed expression is reported by `into_maps`
+    pub fn add_values(&mut self, values: DdbMap) {
+        for (placeholder, value) in values {
+            if self.generated.contains(&placeholder) {
+                self.error(format!("{} is already used by a typed expression", placeholder));
+            } else {
+                self.values.insert(placeholder, value);
+            }
+        }
+    }
+
+    /// Adds names for hand written expressions, see `add_values`
+    pub fn add_names(&mut self, names: HashMap<String, String>) {
+        for (placeholder, name) in names {
+            if self.generated.contains(&placeholder) {
+                self.error(format!("{} is already used by a typed expression", placeholder));
+            } else {
+                self.names.insert(placeholder, name);
+            }
+        }
+    }
+
+    /// Records an expression that cannot be sent, it is returned by `into_maps`
+    pub fn error(&mut self, message: impl ToString) {
+        self.errors.push(message.to_string());
+    }
+
+    /// The names and values as the optional maps used by the rusoto inputs
+    ///
+    /// `expressions` are the rendered expressions of the request. Generated placeholders none
+    /// of them refers to, e.g. those of an expression that was set twice, are left out, because
+    /// DynamoDB rejects unused placeholders.
+    pub fn into_maps(mut self, expressions: &[Option<&str>]) -> Result<AttributeMaps, DdbError> {
+        if !self.errors.is_empty() {
+            return Err(DdbError::Expression(self.errors.join(", ")));
+        }
+        let used: HashSet<&str> =
+            expressions.iter().flatten().flat_map(|e| placeholders(e)).collect();
+        let generated = self.generated;
+        let stale = |p: &String| generated.contains(p) && !used.contains(p.as_str());
+        self.names.retain(|p, _| !stale(p));
+        self.values.retain(|p, _| !stale(p));
+        let names = if self.names.is_empty() { None } else { Some(self.names) };
+        let values = if self.values.is_empty() { None } else { Some(self.values) };
+        Ok((names, values))
+    }
+
+    /// Sets the names and values of `input` to the maps `into_maps` returns for its expressions
+    pub(crate) fn apply<I: ExpressionInput>(self, mut input: I) -> Result<I, DdbError> {
+        let maps = self.into_maps(&input.expressions())?;
+        input.set_attributes(maps);
+        Ok(input)
+    }
+}
+
+type AttributeMaps = (Option<HashMap<String, String>>, Option<DdbMap>);
+
+/// A rusoto input with expressions and the expression attribute maps they refer to
+pub(crate) trait ExpressionInput {
+    fn expressions(&self) -> Vec<Option<&str>>;
+
+    fn set_attributes(&mut self, maps: AttributeMaps);
+}
+
+macro_rules! expression_input {
+    ($($input:ty => |$i:ident| [$($expression:expr),*],)*) => {$(
+        impl ExpressionInput for $input {
+            fn expressions(&self) -> Vec<Option<&str>> {
+                let $i = self;
+                vec![$($expression),*]
+            }
+
+            fn set_attributes(&mut self, (names, values): AttributeMaps) {
+                self.expression_attribute_names = names;
+                self.expression_attribute_values = values;
+            }
+        }
+    )*};
+}
+
+expression_input! {
+    PutItemInput => |i| [i.condition_expression.as_deref()],
+    UpdateItemInput => |i| [i.update_expression.as_deref(), i.condition_expression.as_deref()],
+    DeleteItemInput => |i| [i.condition_expression.as_deref()],
+    QueryInput => |i| [
+        i.key_condition_expression.as_deref(),
+        i.filter_expression.as_deref(),
+        i.projection_expression.as_deref()
+    ],
+    ScanInput => |i| [i.filter_expression.as_deref(), i.projection_expression.as_deref()],
+    Put => |i| [i.condition_expression.as_deref()],
+    Update => |i| [Some(i.update_expression.as_str()), i.condition_expression.as_deref()],
+    Delete => |i| [i.condition_expression.as_deref()],
+    ConditionCheck => |i| [Some(i.condition_expression.as_str())],
+}
+
+/// The `values` and `names` methods of a builder that renders its expressions into `attrs`
+macro_rules! hand_written_attributes {
+    () => {
+        /// Adds expression attribute values for hand written expressions, e.g. `:status`
+        pub fn values(mut self, exp_attr_vals: $crate::DdbMap) -> Self {
+            self.attrs.add_values(exp_attr_vals);
+            self
+        }
+
+        /// Adds expression attribute names for hand written expressions, e.g. `#status`
+        pub fn names(mut self, exp_attr_names: std::collections::HashMap<String, String>) -> Self {
+            self.attrs.add_names(exp_attr_names);
+            self
+        }
+    };
+}
+
+pub(crate) use hand_written_attributes;
+
+/// The `#name` and `:value` placeholders an expression refers to
+fn placeholders(expression: &str) -> impl Iterator<Item = &str> {
+    let word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    expression.match_indices(['#', ':']).filter_map(move |(i, _)| {
+        let len = expression[i + 1..].find(|c| !word(c)).unwrap_or(expression.len() - i - 1);
+        if len == 0 {
+            None
+        } else {
+            Some(&expression[i..i + 1 + len])
+        }
+    })
+}
+
+/// Anything that can be rendered as a DynamoDB expression string
+///
+/// Plain strings are used as they are, so hand written expressions keep working next to the
+/// typed ones.
+pub trait Expression {
+    fn render(&self, attrs: &mut ExpressionAttributes) -> String;
+}
+
+impl Expression for &str {
+    fn render(&self, _attrs: &mut ExpressionAttributes) -> String {
+        self.to_string()
+    }
+}
+
+impl Expression for String {
+    fn render(&self, _attrs: &mut ExpressionAttributes) -> String {
+        self.clone()
+    }
+}
+
+/// Starts a condition on the attribute at `path`
+/// ```
+/// # use ddb_util::*;
+/// let cond = attr("itemtype").eq("dataset").and(attr("created").gt(1633046400));
+/// let mut attrs = ExpressionAttributes::default();
+/// assert_eq!(cond.render(&mut attrs), "#n0 = :v0 AND #n1 > :v1");
+/// assert_eq!(attrs.names["#n1"], "created");
+/// ```
+pub fn attr(path: &str) -> Attr {
+    Attr(path.to_string())
+}
+
+/// Negates a condition
+pub fn not(cond: Condition) -> Condition {
+    Condition(Node::Not(Box::new(cond.0)))
+}
+
+/// Projection of a list of attributes
+pub fn projection(paths: &[&str]) -> Projection {
+    Projection(paths.iter().map(|p| p.to_string()).collect())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Operand {
+    Path(String),
+    Size(String),
+    Value(Box<AttributeValue>),
+}
+
+impl Operand {
+    fn render(&self, attrs: &mut ExpressionAttributes) -> String {
+        match self {
+            Operand::Path(path) => attrs.path(path),
+            Operand::Size(path) => format!("size({})", attrs.path(path)),
+            Operand::Value(value) => attrs.value(*value.clone()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Compare(Operand, &'static str, Operand),
+    Between(Operand, Operand, Operand),
+    In(Operand, Vec<Operand>),
+    Function(&'static str, Vec<Operand>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+}
+
+impl Node {
+    fn render(&self, attrs: &mut ExpressionAttributes) -> String {
+        match self {
+            Node::Compare(l, op, r) => format!("{} {} {}", l.render(attrs), op, r.render(attrs)),
+            Node::Between(v, lo, hi) => format!(
+                "{} BETWEEN {} AND {}",
+                v.render(attrs),
+                lo.render(attrs),
+                hi.render(attrs)
+            ),
+            Node::In(v, list) => {
+                if list.is_empty() {
+                    attrs.error("IN needs at least one value");
+                }
+                let v = v.render(attrs);
+                let list: Vec<String> = list.iter().map(|o| o.render(attrs)).collect();
+                format!("{} IN ({})", v, list.join(", "))
+            }
+            Node::Function(f, args) => {
+                let args: Vec<String> = args.iter().map(|o| o.render(attrs)).collect();
+                format!("{}({})", f, args.join(", "))
+            }
+            Node::And(l, r) => format!("{} AND {}", l.render_nested(attrs), r.render_nested(attrs)),
+            Node::Or(l, r) => format!("{} OR {}", l.render_nested(attrs), r.render_nested(attrs)),
+            Node::Not(c) => format!("NOT {}", c.render_nested(attrs)),
+        }
+    }
+
+    /// Compound operands are parenthesized so precedence never depends on NOT > AND > OR
+    fn render_nested(&self, attrs: &mut ExpressionAttributes) -> String {
+        match self {
+            Node::And(..) | Node::Or(..) | Node::Not(..) => format!("({})", self.render(attrs)),
+            _ => self.render(attrs),
+        }
+    }
+}
+
+/// A condition usable as key condition, filter or condition expression
+#[derive(Clone, Debug, PartialEq)]
+pub struct Condition(Node);
+
+impl Condition {
+    pub fn and(self, other: Condition) -> Condition {
+        Condition(Node::And(Box::new(self.0), Box::new(other.0)))
+    }
+
+    pub fn or(self, other: Condition) -> Condition {
+        Condition(Node::Or(Box::new(self.0), Box::new(other.0)))
+    }
+}
+
+impl ops::Not for Condition {
+    type Output = Condition;
+
+    fn not(self) -> Condition {
+        not(self)
+    }
+}
+
+impl Expression for Condition {
+    fn render(&self, attrs: &mut ExpressionAttributes) -> String {
+        self.0.render(attrs)
+    }
+}
+
+fn value(v: impl IntoAttributeValue) -> Operand {
+    Operand::Value(Box::new(v.into_attribute_value()))
+}
+
+fn compare(l: Operand, op: &'static str, r: impl IntoAttributeValue) -> Condition {
+    Condition(Node::Compare(l, op, value(r)))
+}
+
+/// Attribute path on the left hand side of a condition, see `attr`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Attr(String);
+
+impl Attr {
+    pub fn eq(self, value: impl IntoAttributeValue) -> Condition {
+        compare(Operand::Path(self.0), "=", value)
+    }
+
+    pub fn ne(self, value: impl IntoAttributeValue) -> Condition {
+        compare(Operand::Path(self.0), "<>", value)
+    }
+
+    pub fn lt(self, value: impl IntoAttributeValue) -> Condition {
+        compare(Operand::Path(self.0), "<", value)
+    }
+
+    pub fn le(self, value: impl IntoAttributeValue) -> Condition {
+        compare(Operand::Path(self.0), "<=", value)
+    }
+
+    pub fn gt(self, value: impl IntoAttributeValue) -> Condition {
+        compare(Operand::Path(self.0), ">", value)
+    }
+
+    pub fn ge(self, value: impl IntoAttributeValue) -> Condition {
+        compare(Operand::Path(self.0), ">=", value)
+    }
+
+    pub fn between(self, lo: impl IntoAttributeValue, hi: impl IntoAttributeValue) -> Condition {
+        Condition(Node::Between(
+            Operand::Path(self.0),
+            value(lo),
+            value(hi),
+        ))
+    }
+
+    pub fn in_list<V: IntoAttributeValue>(self, values: impl IntoIterator<Item = V>) -> Condition {
+        let values = values
+            .into_iter()
+            .map(value)
+            .collect();
+        Condition(Node::In(Operand::Path(self.0), values))
+    }
+
+    pub fn begins_with(self, prefix: impl IntoAttributeValue) -> Condition {
+        Condition(Node::Function("begins_with", vec![Operand::Path(self.0), value(prefix)]))
+    }
+
+    pub fn contains(self, operand: impl IntoAttributeValue) -> Condition {
+        Condition(Node::Function("contains", vec![Operand::Path(self.0), value(operand)]))
+    }
+
+    pub fn attribute_exists(self) -> Condition {
+        Condition(Node::Function("attribute_exists", vec![Operand::Path(self.0)]))
+    }
+
+    pub fn attribute_not_exists(self) -> Condition {
+        Condition(Node::Function("attribute_not_exists", vec![Operand::Path(self.0)]))
+    }
+
+    /// `attr_type` is one of the type descriptors `S`, `SS`, `N`, `NS`, `B`, `BS`, `BOOL`,
+    /// `NULL`, `L` or `M`
+    pub fn attribute_type(self, attr_type: &str) -> Condition {
+        Condition(Node::Function("attribute_type", vec![Operand::Path(self.0), value(attr_type)]))
+    }
+
+    /// The size of the attribute, for use in comparisons like `attr("tags").size().gt(3)`
+    pub fn size(self) -> Size {
+        Size(self.0)
+    }
+}
+
+/// `size(path)` on the left hand side of a condition, see `Attr::size`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Size(String);
+
+impl Size {
+    pub fn eq(self, value: impl IntoAttributeValue) -> Condition {
+        compare(Operand::Size(self.0), "=", value)
+    }
+
+    pub fn ne(self, value: impl IntoAttributeValue) -> Condition {
+        compare(Operand::Size(self.0), "<>", value)
+    }
+
+    pub fn lt(self, value: impl IntoAttributeValue) -> Condition {
+        compare(Operand::Size(self.0), "<", value)
+    }
+
+    pub fn le(self, value: impl IntoAttributeValue) -> Condition {
+        compare(Operand::Size(self.0), "<=", value)
+    }
+
+    pub fn gt(self, value: impl IntoAttributeValue) -> Condition {
+        compare(Operand::Size(self.0), ">", value)
+    }
+
+    pub fn ge(self, value: impl IntoAttributeValue) -> Condition {
+        compare(Operand::Size(self.0), ">=", value)
+    }
+
+    pub fn between(self, lo: impl IntoAttributeValue, hi: impl IntoAttributeValue) -> Condition {
+        Condition(Node::Between(
+            Operand::Size(self.0),
+            value(lo),
+            value(hi),
+        ))
+    }
+}
+
+/// Projection expression listing attribute paths, see `projection`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Projection(Vec<String>);
+
+impl Expression for Projection {
+    fn render(&self, attrs: &mut ExpressionAttributes) -> String {
+        let paths: Vec<String> = self.0.iter().map(|p| attrs.path(p)).collect();
+        paths.join(", ")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum SetValue {
+    Value(Box<AttributeValue>),
+    IfNotExists(Box<AttributeValue>),
+    Plus(Box<AttributeValue>),
+    Minus(Box<AttributeValue>),
+    PlusOr(Box<AttributeValue>, Box<AttributeValue>),
+    ListAppend(Box<AttributeValue>),
+}
+
+/// Update expression with SET, REMOVE, ADD and DELETE clauses
+///
+/// Paths and values get placeholders like in conditions, so the expression can be rendered
+/// into the same `ExpressionAttributes` as the condition of the update.
+/// ```
+/// # use ddb_util::*;
+/// # use std::collections::HashSet;
+/// let tags: HashSet<String> = vec!["archived".to_string()].into_iter().collect();
+/// let update = UpdateExpression::new()
+///     .set("status", "done")
+///     .set_if_not_exists("created", 1633046400)
+///     .increment_or("version", 1, 0)
+///     .remove("lock")
+///     .add("tags", tags);
+/// let mut attrs = ExpressionAttributes::default();
+/// assert_eq!(
+///     update.render(&mut attrs),
+///     "SET #n0 = :v0, #n1 = if_not_exists(#n1, :v1), #n2 = if_not_exists(#n2, :v2) + :v3 \
+///      REMOVE #n3 ADD #n4 :v4"
+/// );
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UpdateExpression {
+    set: Vec<(String, SetValue)>,
+    remove: Vec<String>,
+    add: Vec<(String, AttributeValue)>,
+    delete: Vec<(String, AttributeValue)>,
+}
+
+impl UpdateExpression {
+    pub fn new() -> UpdateExpression {
+        UpdateExpression::default()
+    }
+
+    /// `SET path = value`
+    pub fn set(mut self, path: &str, value: impl IntoAttributeValue) -> Self {
+        let value = SetValue::Value(Box::new(value.into_attribute_value()));
+        self.set.push((path.to_string(), value));
+        self
+    }
+
+    /// `SET path = if_not_exists(path, value)`, only sets attributes that do not exist yet
+    pub fn set_if_not_exists(mut self, path: &str, value: impl IntoAttributeValue) -> Self {
+        let value = SetValue::IfNotExists(Box::new(value.into_attribute_value()));
+        self.set.push((path.to_string(), value));
+        self
+    }
+
+    /// `SET path = path + by`, the attribute has to exist
+    pub fn increment(mut self, path: &str, by: impl IntoAttributeValue) -> Self {
+        let value = SetValue::Plus(Box::new(by.into_attribute_value()));
+        self.set.push((path.to_string(), value));
+        self
+    }
+
+    /// `SET path = path - by`, the attribute has to exist
+    pub fn decrement(mut self, path: &str, by: impl IntoAttributeValue) -> Self {
+        let value = SetValue::Minus(Box::new(by.into_attribute_value()));
+        self.set.push((path.to_string(), value));
+        self
+    }
+
+    /// `SET path = if_not_exists(path, initial) + by`
+    pub fn increment_or(
+        mut self, path: &str, by: impl IntoAttributeValue, initial: impl IntoAttributeValue,
+    ) -> Self {
+        let value = SetValue::PlusOr(
+            Box::new(by.into_attribute_value()),
+            Box::new(initial.into_attribute_value()),
+        );
+        self.set.push((path.to_string(), value));
+        self
+    }
+
+    /// `SET path = list_append(path, list)`
+    pub fn list_append(mut self, path: &str, list: impl IntoAttributeValue) -> Self {
+        let value = SetValue::ListAppend(Box::new(list.into_attribute_value()));
+        self.set.push((path.to_string(), value));
+        self
+    }
+
+    /// `REMOVE path`
+    pub fn remove(mut self, path: &str) -> Self {
+        self.remove.push(path.to_string());
+        self
+    }
+
+    /// `ADD path value`, adds to a number or a set and creates the attribute if it is missing
+    pub fn add(mut self, path: &str, value: impl IntoAttributeValue) -> Self {
+        self.add.push((path.to_string(), value.into_attribute_value()));
+        self
+    }
+
+    /// `DELETE path value`, removes the elements of `value` from a set
+    pub fn delete(mut self, path: &str, value: impl IntoAttributeValue) -> Self {
+        self.delete.push((path.to_string(), value.into_attribute_value()));
+        self
+    }
+}
+
+impl Expression for UpdateExpression {
+    fn render(&self, attrs: &mut ExpressionAttributes) -> String {
+        let mut clauses = Vec::new();
+        if !self.set.is_empty() {
+            let actions: Vec<String> = self
+                .set
+                .iter()
+                .map(|(path, value)| {
+                    let p = attrs.path(path);
+                    let v = match value {
+                        SetValue::Value(v) => attrs.value(*v.clone()),
+                        SetValue::IfNotExists(v) => {
+                            format!("if_not_exists({}, {})", p, attrs.value(*v.clone()))
+                        }
+                        SetValue::Plus(v) => format!("{} + {}", p, attrs.value(*v.clone())),
+                        SetValue::Minus(v) => format!("{} - {}", p, attrs.value(*v.clone())),
+                        SetValue::PlusOr(by, initial) => {
+                            let initial = attrs.value(*initial.clone());
+                            let by = attrs.value(*by.clone());
+                            format!("if_not_exists({}, {}) + {}", p, initial, by)
+                        }
+                        SetValue::ListAppend(v) => {
+                            format!("list_append({}, {})", p, attrs.value(*v.clone()))
+                        }
+                    };
+                    format!("{} = {}", p, v)
+                })
+                .collect();
+            clauses.push(format!("SET {}", actions.join(", ")));
+        }
+        if !self.remove.is_empty() {
+            let paths: Vec<String> = self.remove.iter().map(|p| attrs.path(p)).collect();
+            clauses.push(format!("REMOVE {}", paths.join(", ")));
+        }
+        for (keyword, actions) in [("ADD", &self.add), ("DELETE", &self.delete)] {
+            if !actions.is_empty() {
+                let actions: Vec<String> = actions
+                    .iter()
+                    .map(|(path, v)| format!("{} {}", attrs.path(path), attrs.value(v.clone())))
+                    .collect();
+                clauses.push(format!("{} {}", keyword, actions.join(", ")));
+            }
+        }
+        clauses.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn s(v: &str) -> AttributeValue {
+        v.into_attribute_value()
+    }
+
+    #[test]
+    fn renders_placeholders_for_names_and_values() {
+        let mut attrs = ExpressionAttributes::default();
+        let cond = attr("status")
+            .in_list(vec!["active", "pending"])
+            .and(attr("ttl").attribute_not_exists().or(attr("ttl").gt(10)));
+        assert_eq!(
+            cond.render(&mut attrs),
+            "#n0 IN (:v0, :v1) AND (attribute_not_exists(#n1) OR #n1 > :v2)"
+        );
+        assert_eq!(attrs.names.len(), 2);
+        assert_eq!(attrs.names["#n0"], "status");
+        assert_eq!(attrs.names["#n1"], "ttl");
+        assert_eq!(attrs.values[":v1"], s("pending"));
+        assert_eq!(attrs.values[":v2"], 10.into_attribute_value());
+    }
+
+    #[test]
+    fn renders_paths_functions_and_negation() {
+        let mut attrs = ExpressionAttributes::default();
+        let cond = !attr("address.city").begins_with("Cop").and(attr("items[1]").size().between(1, 3));
+        assert_eq!(
+            cond.render(&mut attrs),
+            "NOT (begins_with(#n0.#n1, :v0) AND size(#n2[1]) BETWEEN :v1 AND :v2)"
+        );
+        assert_eq!(projection(&["name", "address.city"]).render(&mut attrs), "#n3, #n0.#n1");
+    }
+
+    #[test]
+    fn skips_placeholders_already_in_use() {
+        let mut attrs = ExpressionAttributes::default();
+        attrs.values.insert(":v0".to_string(), s("dataset"));
+        attrs.names.insert("#n0".to_string(), "itemtype".to_string());
+        assert_eq!(attr("pk").eq("c4c").render(&mut attrs), "#n1 = :v1");
+        assert_eq!(attr("itemtype").eq("x").render(&mut attrs), "#n0 = :v2");
+    }
+
+    #[test]
+    fn renders_update_clauses_with_shared_placeholders() {
+        let mut attrs = ExpressionAttributes::default();
+        let cond = attr("version").eq(3);
+        assert_eq!(cond.render(&mut attrs), "#n0 = :v0");
+        let update = UpdateExpression::new()
+            .increment("version", 1)
+            .list_append("history", AttributeValue {
+                l: Some(vec![s("done")]),
+                ..Default::default()
+            })
+            .delete("tags", AttributeValue {
+                ss: Some(vec!["draft".to_string()]),
+                ..Default::default()
+            });
+        assert_eq!(
+            update.render(&mut attrs),
+            "SET #n0 = #n0 + :v1, #n1 = list_append(#n1, :v2) DELETE #n2 :v3"
+        );
+        assert_eq!(attrs.values[":v1"], 1.into_attribute_value());
+    }
+
+    #[test]
+    fn rejects_hand_written_placeholders_taken_by_typed_expressions() {
+        let mut attrs = ExpressionAttributes::default();
+        let cond = Some(attr("pk").eq("c4c").render(&mut attrs));
+        let mut values: DdbMap = HashMap::new();
+        set_kv(&mut values, ":v0".to_string(), "c4d");
+        set_kv(&mut values, ":pk".to_string(), "c4d");
+        attrs.add_values(values);
+        assert_eq!(attrs.values[":v0"], s("c4c"));
+        let err = attrs.into_maps(&[cond.as_deref()]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid expression: :v0 is already used by a typed expression"
+        );
+    }
+
+    #[test]
+    fn leaves_out_placeholders_of_replaced_expressions() {
+        let mut attrs = ExpressionAttributes::default();
+        attr("status").eq("done").render(&mut attrs);
+        let filter = Some(attr("size").gt(3).render(&mut attrs));
+        let projection = Some(projection(&["status"]).render(&mut attrs));
+        let mut names = HashMap::new();
+        names.insert("#own".to_string(), "own".to_string());
+        attrs.add_names(names);
+        let (names, values) = attrs.into_maps(&[filter.as_deref(), projection.as_deref()]).unwrap();
+        let mut names: Vec<String> = names.unwrap().into_keys().collect();
+        names.sort();
+        assert_eq!(names, vec!["#n0", "#n1", "#own"]);
+        assert_eq!(values.unwrap().keys().collect::<Vec<_>>(), vec![":v1"]);
+    }
+
+    #[test]
+    fn rejects_empty_in_lists() {
+        let mut attrs = ExpressionAttributes::default();
+        let cond = Some(attr("status").in_list(Vec::<String>::new()).render(&mut attrs));
+        let err = attrs.into_maps(&[cond.as_deref()]).unwrap_err();
+        assert_eq!(err.to_string(), "invalid expression: IN needs at least one value");
+    }
+}
@@ -0,0 +1,286 @@
+use super::{field_path, index_path, ItemError};
+use rusoto_dynamodb::AttributeValue;
+use serde::de::{self, DeserializeSeed, IntoDeserializer, Unexpected, Visitor};
+use serde::forward_to_deserialize_any;
+
+/// Deserializes one attribute value, `path` is where the value is in the item
+pub(super) struct Deserializer {
+    value: AttributeValue,
+    path: String,
+}
+
+impl Deserializer {
+    pub(super) fn new(value: AttributeValue, path: String) -> Deserializer {
+        Deserializer { value, path }
+    }
+
+    /// The elements of an `L`, `SS`, `NS` or `BS`
+    fn elements(value: AttributeValue) -> Option<Vec<AttributeValue>> {
+        fn wrap<T>(items: Vec<T>, f: impl Fn(T) -> AttributeValue) -> Vec<AttributeValue> {
+            items.into_iter().map(f).collect()
+        }
+        match value {
+            AttributeValue { l: Some(l), .. } => Some(l),
+            AttributeValue { ss: Some(ss), .. } => {
+                Some(wrap(ss, |s| AttributeValue { s: Some(s), ..Default::default() }))
+            }
+            AttributeValue { ns: Some(ns), .. } => {
+                Some(wrap(ns, |n| AttributeValue { n: Some(n), ..Default::default() }))
+            }
+            AttributeValue { bs: Some(bs), .. } => {
+                Some(wrap(bs, |b| AttributeValue { b: Some(b), ..Default::default() }))
+            }
+            _ => None,
+        }
+    }
+
+    /// Integers are visited as integers and any other number as an `f64`, so buffered content,
+    /// e.g. of flattened structs and tagged enums, still deserializes into float fields. Read
+    /// the exact digits with `#[serde(with = "ddb_util::number")]`.
+    fn number<'de, V: Visitor<'de>>(n: &str, visitor: V) -> Result<V::Value, ItemError> {
+        if let Ok(v) = n.parse::<i64>() {
+            visitor.visit_i64(v)
+        } else if let Ok(v) = n.parse::<u64>() {
+            visitor.visit_u64(v)
+        } else if let Ok(v) = n.parse::<i128>() {
+            visitor.visit_i128(v)
+        } else if let Ok(v) = n.parse::<u128>() {
+            visitor.visit_u128(v)
+        } else if let Ok(v) = n.parse::<f64>() {
+            visitor.visit_f64(v)
+        } else {
+            Err(de::Error::invalid_value(Unexpected::Other(n), &"a number"))
+        }
+    }
+}
+
+macro_rules! deserialize_number {
+    ($($method:ident => $visit:ident($t:ty)),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ItemError> {
+                match &self.value.n {
+                    Some(n) => match n.parse::<$t>() {
+                        Ok(v) => visitor.$visit(v),
+                        Err(_) => Err(de::Error::invalid_value(
+                            Unexpected::Other(&format!("number {}", n)),
+                            &visitor,
+                        )),
+                    },
+                    None => self.deserialize_any(visitor),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = ItemError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ItemError> {
+        let path = self.path;
+        match self.value {
+            AttributeValue { s: Some(s), .. } => visitor.visit_string(s),
+            AttributeValue { n: Some(n), .. } => Deserializer::number(&n, visitor),
+            AttributeValue { b: Some(b), .. } => visitor.visit_byte_buf(b.to_vec()),
+            AttributeValue { bool: Some(b), .. } => visitor.visit_bool(b),
+            AttributeValue { null: Some(_), .. } => visitor.visit_unit(),
+            AttributeValue { m: Some(m), .. } => {
+                visitor.visit_map(MapAccess { entries: m.into_iter(), value: None, path })
+            }
+            value => match Deserializer::elements(value) {
+                Some(elements) => visitor
+                    .visit_seq(SeqAccess { elements: elements.into_iter().enumerate(), path }),
+                None => Err(ItemError::at(&path, "the attribute has no value")),
+            },
+        }
+    }
+
+    deserialize_number!(
+        deserialize_i8 => visit_i8(i8),
+        deserialize_i16 => visit_i16(i16),
+        deserialize_i32 => visit_i32(i32),
+        deserialize_i64 => visit_i64(i64),
+        deserialize_i128 => visit_i128(i128),
+        deserialize_u8 => visit_u8(u8),
+        deserialize_u16 => visit_u16(u16),
+        deserialize_u32 => visit_u32(u32),
+        deserialize_u64 => visit_u64(u64),
+        deserialize_u128 => visit_u128(u128),
+        deserialize_f32 => visit_f32(f32),
+        deserialize_f64 => visit_f64(f64)
+    );
+
+    /// Numbers are passed as their exact digits
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ItemError> {
+        match self.value.n {
+            Some(n) => visitor.visit_string(n),
+            None => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ItemError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ItemError> {
+        match self.value.null {
+            Some(true) => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self, _name: &'static str, visitor: V,
+    ) -> Result<V::Value, ItemError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self, _name: &'static str, _variants: &'static [&'static str], visitor: V,
+    ) -> Result<V::Value, ItemError> {
+        match self.value {
+            AttributeValue { s: Some(variant), .. } => {
+                visitor.visit_enum(EnumAccess { variant, value: None, path: self.path })
+            }
+            AttributeValue { m: Some(m), .. } if m.len() == 1 => {
+                let (variant, value) = m.into_iter().next().expect("one entry");
+                visitor.visit_enum(EnumAccess { variant, value: Some(value), path: self.path })
+            }
+            _ => Err(ItemError::at(
+                &self.path,
+                "an enum has to be a variant name or a map with one variant",
+            )),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ItemError> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool char bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier
+    }
+}
+
+struct SeqAccess {
+    elements: std::iter::Enumerate<std::vec::IntoIter<AttributeValue>>,
+    path: String,
+}
+
+impl<'de> de::SeqAccess<'de> for SeqAccess {
+    type Error = ItemError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self, seed: T,
+    ) -> Result<Option<T::Value>, ItemError> {
+        match self.elements.next() {
+            Some((index, value)) => {
+                let path = index_path(&self.path, index);
+                let value = seed.deserialize(Deserializer::new(value, path.clone()));
+                value.map(Some).map_err(|e| e.or_at(&path))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.elements.len())
+    }
+}
+
+struct MapAccess {
+    entries: std::collections::hash_map::IntoIter<String, AttributeValue>,
+    value: Option<(String, AttributeValue)>,
+    path: String,
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess {
+    type Error = ItemError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self, seed: K,
+    ) -> Result<Option<K::Value>, ItemError> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some((key.clone(), value));
+                seed.deserialize(key.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, ItemError> {
+        let (key, value) =
+            self.value.take().expect("next_value_seed is called after next_key_seed");
+        let path = field_path(&self.path, &key);
+        seed.deserialize(Deserializer::new(value, path.clone())).map_err(|e| e.or_at(&path))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+struct EnumAccess {
+    variant: String,
+    value: Option<AttributeValue>,
+    path: String,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess {
+    type Error = ItemError;
+    type Variant = VariantAccess;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self, seed: V,
+    ) -> Result<(V::Value, VariantAccess), ItemError> {
+        let path = field_path(&self.path, &self.variant);
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, VariantAccess { value: self.value, path }))
+    }
+}
+
+/// The value of an enum variant, `None` for a unit variant stored as its name
+struct VariantAccess {
+    value: Option<AttributeValue>,
+    path: String,
+}
+
+impl VariantAccess {
+    fn value(self) -> Result<(Deserializer, String), ItemError> {
+        match self.value {
+            Some(value) => Ok((Deserializer::new(value, self.path.clone()), self.path)),
+            None => Err(ItemError::at(&self.path, "expected a map with the variant fields")),
+        }
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for VariantAccess {
+    type Error = ItemError;
+
+    fn unit_variant(self) -> Result<(), ItemError> {
+        match self.value {
+            None => Ok(()),
+            Some(_) => Err(ItemError::at(&self.path, "expected a unit variant")),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, ItemError> {
+        let (deserializer, path) = self.value()?;
+        seed.deserialize(deserializer).map_err(|e| e.or_at(&path))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self, _len: usize, visitor: V,
+    ) -> Result<V::Value, ItemError> {
+        let (deserializer, path) = self.value()?;
+        de::Deserializer::deserialize_seq(deserializer, visitor).map_err(|e| e.or_at(&path))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self, _fields: &'static [&'static str], visitor: V,
+    ) -> Result<V::Value, ItemError> {
+        let (deserializer, path) = self.value()?;
+        de::Deserializer::deserialize_map(deserializer, visitor).map_err(|e| e.or_at(&path))
+    }
+}
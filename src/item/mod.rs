@@ -0,0 +1,463 @@
+use crate::DdbMap;
+use rusoto_dynamodb::AttributeValue;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+
+mod de;
+mod ser;
+
+// Newtype struct names the `with` helpers serialize through, so the serializer can tell a
+// number or a set apart from a plain string or list
+const NUMBER: &str = "$ddb_util::N";
+const STRING_SET: &str = "$ddb_util::SS";
+const NUMBER_SET: &str = "$ddb_util::NS";
+const BINARY_SET: &str = "$ddb_util::BS";
+
+/// Serializes `value` into an item, `value` has to serialize to a map or a struct
+///
+/// Strings become `S`, numbers `N`, booleans `BOOL`, `None` and `()` `NULL`, sequences `L` and
+/// maps and structs `M`. Bytes serialized with `serialize_bytes`, e.g. through `serde_bytes`,
+/// become `B`. Unit enum variants are stored as their name, other variants as a map from the
+/// variant name to its value. Fields with `#[serde(with = "ddb_util::string_set")]`,
+/// `number_set` or `binary_set` become `SS`, `NS` and `BS` sets, and fields with
+/// `#[serde(with = "ddb_util::number")]` are stored as `N` with every digit of their `Display`
+/// form, e.g. 38 digit decimals.
+/// ```
+/// # use serde::{Deserialize, Serialize};
+/// # use std::collections::BTreeSet;
+/// # use ddb_util::*;
+///
+/// #[derive(Debug, Deserialize, Serialize, PartialEq)]
+/// struct Dataset {
+///     pk: String,
+///     sk: String,
+///     #[serde(with = "ddb_util::string_set")]
+///     tags: BTreeSet<String>,
+///     #[serde(with = "ddb_util::number")]
+///     size: u128,
+/// }
+///
+/// # fn main() -> Result<(), DdbError> {
+/// let dataset = Dataset {
+///     pk: "c4c".to_string(),
+///     sk: "dataset#1".to_string(),
+///     tags: vec!["public".to_string()].into_iter().collect(),
+///     size: 12345678901234567890123456789012345678,
+/// };
+/// let item = to_item(&dataset)?;
+/// assert_eq!(item["tags"].ss, Some(vec!["public".to_string()]));
+/// assert_eq!(item.get_s("size"), None);
+/// assert_eq!(from_item::<Dataset>(item)?, dataset);
+///
+/// let item = ddb_map! { "pk" => "c4c", "sk" => "dataset#1", "tags" => vec!["a"], "size" => "a" };
+/// let err = from_item::<Dataset>(item).unwrap_err();
+/// assert_eq!(err.path(), "size");
+/// #     Ok(())
+/// # }
+/// ```
+pub fn to_item<T: Serialize + ?Sized>(value: &T) -> Result<DdbMap, ItemError> {
+    match value.serialize(ser::Serializer::new(String::new()))? {
+        AttributeValue { m: Some(item), .. } => Ok(item),
+        _ => Err(ItemError::at("", "an item has to serialize to a map")),
+    }
+}
+
+/// Deserializes an item, see `to_item`
+///
+/// `N` values are parsed into the numeric type of the field, and are passed with every digit
+/// to fields that deserialize from a string, e.g. with `#[serde(with = "ddb_util::number")]`.
+/// `SS`, `NS` and `BS` sets deserialize as sequences, so they can be read into any collection.
+pub fn from_item<T: DeserializeOwned>(item: DdbMap) -> Result<T, ItemError> {
+    let item = AttributeValue { m: Some(item), ..Default::default() };
+    T::deserialize(de::Deserializer::new(item, String::new()))
+}
+
+/// Deserializes the attributes a write returned, `None` when it returned none
+pub(crate) fn from_attributes<T: DeserializeOwned>(
+    attributes: Option<DdbMap>,
+) -> Result<Option<T>, ItemError> {
+    attributes.filter(|attributes| !attributes.is_empty()).map(from_item).transpose()
+}
+
+/// Error of `to_item` and `from_item`, with the path of the attribute that failed, like
+/// `history[1].version`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ItemError {
+    path: Option<String>,
+    message: String,
+}
+
+impl ItemError {
+    fn at(path: &str, message: impl fmt::Display) -> ItemError {
+        ItemError { path: Some(path.to_string()), message: message.to_string() }
+    }
+
+    /// Sets the path unless an attribute nested deeper already set it
+    fn or_at(mut self, path: &str) -> ItemError {
+        self.path.get_or_insert_with(|| path.to_string());
+        self
+    }
+
+    /// Path of the failing attribute, empty when the item itself failed
+    pub fn path(&self) -> &str {
+        self.path.as_deref().unwrap_or_default()
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for ItemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.path() {
+            "" => write!(f, "{}", self.message),
+            path => write!(f, "{}: {}", path, self.message),
+        }
+    }
+}
+
+impl std::error::Error for ItemError {}
+
+impl serde::ser::Error for ItemError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        ItemError { path: None, message: msg.to_string() }
+    }
+}
+
+impl serde::de::Error for ItemError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        ItemError { path: None, message: msg.to_string() }
+    }
+}
+
+/// Path of the attribute `name` of the map at `path`
+fn field_path(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", path, name)
+    }
+}
+
+/// Path of element `index` of the list at `path`
+fn index_path(path: &str, index: usize) -> String {
+    format!("{}[{}]", path, index)
+}
+
+/// `#[serde(with = "ddb_util::number")]` stores a `Display` and `FromStr` value, like `u128`
+/// or a decimal type, as an `N` without losing digits
+pub mod number {
+    use serde::de::{self, Visitor};
+    use serde::{Deserializer, Serializer};
+    use std::fmt::{self, Display};
+    use std::str::FromStr;
+
+    pub fn serialize<T: Display, S: Serializer>(
+        value: &T, serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(super::NUMBER, &value.to_string())
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        let n = deserializer.deserialize_str(NumberVisitor)?;
+        n.parse().map_err(|e| de::Error::custom(format!("{} is not a valid number: {}", n, e)))
+    }
+
+    /// The digits of a number, also from deserializers that only have numeric types
+    struct NumberVisitor;
+
+    impl<'de> Visitor<'de> for NumberVisitor {
+        type Value = String;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a number")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<String, E> {
+            Ok(v.to_string())
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<String, E> {
+            Ok(v.to_string())
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<String, E> {
+            Ok(v.to_string())
+        }
+
+        fn visit_i128<E: de::Error>(self, v: i128) -> Result<String, E> {
+            Ok(v.to_string())
+        }
+
+        fn visit_u128<E: de::Error>(self, v: u128) -> Result<String, E> {
+            Ok(v.to_string())
+        }
+
+        fn visit_f64<E: de::Error>(self, v: f64) -> Result<String, E> {
+            Ok(v.to_string())
+        }
+    }
+}
+
+/// `#[serde(with = "ddb_util::string_set")]` stores a collection of strings as an `SS`
+///
+/// DynamoDB has no empty sets, skip empty collections with `skip_serializing_if`.
+pub mod string_set {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::iter::FromIterator;
+
+    pub fn serialize<'a, T, E, S>(set: &'a T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        &'a T: IntoIterator<Item = &'a E>,
+        E: AsRef<str> + 'a,
+        S: Serializer,
+    {
+        let items: Vec<&str> = set.into_iter().map(AsRef::as_ref).collect();
+        serializer.serialize_newtype_struct(super::STRING_SET, &items)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromIterator<String>,
+        D: Deserializer<'de>,
+    {
+        Vec::<String>::deserialize(deserializer).map(|items| items.into_iter().collect())
+    }
+}
+
+/// `#[serde(with = "ddb_util::number_set")]` stores a collection of `Display` and `FromStr`
+/// numbers as an `NS`
+///
+/// DynamoDB has no empty sets, skip empty collections with `skip_serializing_if`.
+pub mod number_set {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::fmt::Display;
+    use std::iter::FromIterator;
+    use std::str::FromStr;
+
+    pub fn serialize<'a, T, E, S>(set: &'a T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        &'a T: IntoIterator<Item = &'a E>,
+        E: Display + 'a,
+        S: Serializer,
+    {
+        let items: Vec<String> = set.into_iter().map(ToString::to_string).collect();
+        serializer.serialize_newtype_struct(super::NUMBER_SET, &items)
+    }
+
+    pub fn deserialize<'de, T, E, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromIterator<E>,
+        E: FromStr,
+        E::Err: Display,
+        D: Deserializer<'de>,
+    {
+        Vec::<String>::deserialize(deserializer)?
+            .into_iter()
+            .map(|n| {
+                n.parse().map_err(|e| Error::custom(format!("{} is not a valid number: {}", n, e)))
+            })
+            .collect()
+    }
+}
+
+/// `#[serde(with = "ddb_util::binary_set")]` stores a collection of byte vectors as a `BS`
+///
+/// DynamoDB has no empty sets, skip empty collections with `skip_serializing_if`.
+pub mod binary_set {
+    use serde::de::{self, SeqAccess, Visitor};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::fmt;
+    use std::iter::FromIterator;
+
+    struct RawBytes<'a>(&'a [u8]);
+
+    impl Serialize for RawBytes<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+
+    struct ByteBuf(Vec<u8>);
+
+    impl<'de> Deserialize<'de> for ByteBuf {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_byte_buf(ByteBufVisitor)
+        }
+    }
+
+    struct ByteBufVisitor;
+
+    impl<'de> Visitor<'de> for ByteBufVisitor {
+        type Value = ByteBuf;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("bytes")
+        }
+
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<ByteBuf, E> {
+            Ok(ByteBuf(v.to_vec()))
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<ByteBuf, E> {
+            Ok(ByteBuf(v))
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ByteBuf, A::Error> {
+            let mut bytes = Vec::new();
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+            Ok(ByteBuf(bytes))
+        }
+    }
+
+    pub fn serialize<'a, T, E, S>(set: &'a T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        &'a T: IntoIterator<Item = &'a E>,
+        E: AsRef<[u8]> + 'a,
+        S: Serializer,
+    {
+        let items: Vec<RawBytes> = set.into_iter().map(|b| RawBytes(b.as_ref())).collect();
+        serializer.serialize_newtype_struct(super::BINARY_SET, &items)
+    }
+
+    pub fn deserialize<'de, T, E, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromIterator<E>,
+        E: From<Vec<u8>>,
+        D: Deserializer<'de>,
+    {
+        let items = Vec::<ByteBuf>::deserialize(deserializer)?;
+        Ok(items.into_iter().map(|b| E::from(b.0)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use serde::{Deserialize, Serialize};
+    use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    enum Status {
+        Active,
+        Archived { at: u64 },
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    struct Dataset {
+        pk: String,
+        version: i64,
+        ratio: f64,
+        public: bool,
+        created: Option<u64>,
+        status: Status,
+        previous: Vec<Status>,
+        owners: BTreeMap<String, String>,
+        #[serde(with = "crate::number_set")]
+        versions: BTreeSet<u64>,
+        #[serde(with = "crate::binary_set")]
+        checksums: HashSet<Vec<u8>>,
+        #[serde(with = "crate::number")]
+        price: String,
+    }
+
+    fn dataset() -> Dataset {
+        Dataset {
+            pk: "c4c".to_string(),
+            version: -3,
+            ratio: 0.5,
+            public: true,
+            created: None,
+            status: Status::Archived { at: 1633132800 },
+            previous: vec![Status::Active],
+            owners: vec![("owner#1".to_string(), "c4c".to_string())].into_iter().collect(),
+            versions: vec![1, 2, 3].into_iter().collect(),
+            checksums: vec![vec![0u8, 1]].into_iter().collect(),
+            price: "0.12345678901234567890123456789012345678".to_string(),
+        }
+    }
+
+    #[test]
+    fn items_round_trip_with_sets_and_exact_numbers() {
+        let item = to_item(&dataset()).unwrap();
+        assert_eq!(item["version"].n.as_deref(), Some("-3"));
+        assert_eq!(item["created"].null, Some(true));
+        let status = item["status"].m.as_ref().and_then(|status| status.get_map("Archived"));
+        assert_eq!(status.and_then(|archived| archived.get_n::<u64>("at")), Some(1633132800));
+        assert_eq!(item["previous"].l.as_ref().unwrap()[0].s.as_deref(), Some("Active"));
+        assert_eq!(item["versions"].ns.as_ref().map(Vec::len), Some(3));
+        assert_eq!(item["checksums"].bs.as_ref().map(Vec::len), Some(1));
+        assert_eq!(item["price"].n.as_deref(), Some("0.12345678901234567890123456789012345678"));
+        assert_eq!(from_item::<Dataset>(item).unwrap(), dataset());
+    }
+
+    #[test]
+    fn floats_deserialize_through_flattened_and_tagged_types() {
+        #[derive(Debug, Deserialize, Serialize, PartialEq)]
+        struct Measurement {
+            ratio: f64,
+        }
+        #[derive(Debug, Deserialize, Serialize, PartialEq)]
+        #[serde(tag = "kind")]
+        enum Reading {
+            Sample {
+                #[serde(flatten)]
+                measurement: Measurement,
+                count: u64,
+            },
+        }
+        let reading = Reading::Sample { measurement: Measurement { ratio: 0.25 }, count: 3 };
+        let item = to_item(&reading).unwrap();
+        assert_eq!(item["ratio"].n.as_deref(), Some("0.25"));
+        assert_eq!(from_item::<Reading>(item).unwrap(), reading);
+    }
+
+    #[test]
+    fn errors_report_the_attribute_path() {
+        let mut item = to_item(&dataset()).unwrap();
+        let previous = vec![ddb_map! { "Archived" => ddb_map! {} }];
+        item.insert("previous".to_string(), previous.into_attribute_value());
+        let err = from_item::<Dataset>(item).unwrap_err();
+        assert_eq!(err.to_string(), "previous[0].Archived: missing field `at`");
+        let mut item = to_item(&dataset()).unwrap();
+        item.insert("version".to_string(), "three".into_attribute_value());
+        assert_eq!(from_item::<Dataset>(item).unwrap_err().path(), "version");
+        let mut empty = dataset();
+        empty.versions.clear();
+        assert_eq!(to_item(&empty).unwrap_err().path(), "versions");
+        let mut nan = dataset();
+        nan.ratio = f64::NAN;
+        assert_eq!(to_item(&nan).unwrap_err().path(), "ratio");
+        assert!(to_item(&vec![1]).is_err());
+    }
+
+    #[test]
+    fn numbers_have_to_be_finite_decimals() {
+        #[derive(Serialize)]
+        struct Reading {
+            #[serde(with = "crate::number")]
+            value: String,
+            #[serde(with = "crate::number_set")]
+            samples: Vec<f64>,
+        }
+        let reading =
+            |value: &str, samples: Vec<f64>| Reading { value: value.to_string(), samples };
+        assert!(to_item(&reading("1.5e3", vec![0.5])).is_ok());
+        for value in ["NaN", "inf", "-infinity", "twelve"] {
+            let err = to_item(&reading(value, vec![0.5])).unwrap_err();
+            assert_eq!(err.to_string(), "value: not a finite number");
+        }
+        let err = to_item(&reading("1", vec![0.5, f64::INFINITY])).unwrap_err();
+        assert_eq!(err.path(), "samples");
+    }
+}
@@ -0,0 +1,366 @@
+use super::{field_path, index_path, ItemError, BINARY_SET, NUMBER, NUMBER_SET, STRING_SET};
+use crate::DdbMap;
+use rusoto_dynamodb::AttributeValue;
+use serde::ser::{self, Serialize};
+
+/// Serializes one attribute value, `path` is where the value ends up in the item
+pub(super) struct Serializer {
+    path: String,
+}
+
+impl Serializer {
+    pub(super) fn new(path: String) -> Serializer {
+        Serializer { path }
+    }
+
+    fn number(&self, n: String) -> Result<AttributeValue, ItemError> {
+        Ok(AttributeValue { n: Some(n), ..Default::default() })
+    }
+}
+
+/// Serializes `value` as the attribute at `path`
+fn serialize_at<T: Serialize + ?Sized>(
+    value: &T, path: String,
+) -> Result<AttributeValue, ItemError> {
+    value.serialize(Serializer::new(path.clone())).map_err(|e| e.or_at(&path))
+}
+
+fn string(s: String) -> AttributeValue {
+    AttributeValue { s: Some(s), ..Default::default() }
+}
+
+fn map(m: DdbMap) -> AttributeValue {
+    AttributeValue { m: Some(m), ..Default::default() }
+}
+
+/// Whether `n` is a finite decimal number, DynamoDB has no `NaN` or infinite numbers
+fn is_decimal(n: &str) -> bool {
+    n.parse::<f64>().is_ok_and(f64::is_finite)
+}
+
+/// Turns the list a set helper serialized into an `SS`, `NS` or `BS`
+fn into_set(name: &str, value: AttributeValue, path: &str) -> Result<AttributeValue, ItemError> {
+    let elements = value.l.unwrap_or_default();
+    if elements.is_empty() {
+        return Err(ItemError::at(path, "DynamoDB sets cannot be empty"));
+    }
+    let invalid = || ItemError::at(path, "invalid set element");
+    let strings =
+        || elements.iter().map(|e| e.s.clone().ok_or_else(invalid)).collect::<Result<_, _>>();
+    Ok(match name {
+        STRING_SET => AttributeValue { ss: Some(strings()?), ..Default::default() },
+        NUMBER_SET => {
+            let ns: Vec<String> = strings()?;
+            if !ns.iter().all(|n| is_decimal(n)) {
+                return Err(invalid());
+            }
+            AttributeValue { ns: Some(ns), ..Default::default() }
+        }
+        _ => AttributeValue {
+            bs: Some(
+                elements
+                    .iter()
+                    .map(|e| e.b.clone().ok_or_else(invalid))
+                    .collect::<Result<_, _>>()?,
+            ),
+            ..Default::default()
+        },
+    })
+}
+
+macro_rules! serialize_integer {
+    ($($method:ident($t:ty)),*) => {
+        $(
+            fn $method(self, v: $t) -> Result<AttributeValue, ItemError> {
+                self.number(v.to_string())
+            }
+        )*
+    };
+}
+
+macro_rules! serialize_float {
+    ($($method:ident($t:ty)),*) => {
+        $(
+            fn $method(self, v: $t) -> Result<AttributeValue, ItemError> {
+                if v.is_finite() {
+                    self.number(v.to_string())
+                } else {
+                    Err(ItemError::at(&self.path, format!("{} cannot be stored as a number", v)))
+                }
+            }
+        )*
+    };
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = AttributeValue;
+    type Error = ItemError;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = VariantSerializer<SeqSerializer>;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = VariantSerializer<MapSerializer>;
+
+    fn serialize_bool(self, v: bool) -> Result<AttributeValue, ItemError> {
+        Ok(AttributeValue { bool: Some(v), ..Default::default() })
+    }
+
+    serialize_integer!(
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_i128(i128),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_u128(u128)
+    );
+
+    serialize_float!(serialize_f32(f32), serialize_f64(f64));
+
+    fn serialize_char(self, v: char) -> Result<AttributeValue, ItemError> {
+        Ok(string(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<AttributeValue, ItemError> {
+        Ok(string(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<AttributeValue, ItemError> {
+        Ok(AttributeValue { b: Some(v.to_vec().into()), ..Default::default() })
+    }
+
+    fn serialize_none(self) -> Result<AttributeValue, ItemError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<AttributeValue, ItemError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<AttributeValue, ItemError> {
+        Ok(AttributeValue { null: Some(true), ..Default::default() })
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<AttributeValue, ItemError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self, _name: &'static str, _index: u32, variant: &'static str,
+    ) -> Result<AttributeValue, ItemError> {
+        Ok(string(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self, name: &'static str, value: &T,
+    ) -> Result<AttributeValue, ItemError> {
+        let path = self.path.clone();
+        let value = value.serialize(self)?;
+        match name {
+            NUMBER => match value.s {
+                Some(n) if is_decimal(&n) => {
+                    Ok(AttributeValue { n: Some(n), ..Default::default() })
+                }
+                _ => Err(ItemError::at(&path, "not a finite number")),
+            },
+            STRING_SET | NUMBER_SET | BINARY_SET => into_set(name, value, &path),
+            _ => Ok(value),
+        }
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self, _name: &'static str, _index: u32, variant: &'static str, value: &T,
+    ) -> Result<AttributeValue, ItemError> {
+        let value = serialize_at(value, field_path(&self.path, variant))?;
+        Ok(map(std::iter::once((variant.to_string(), value)).collect()))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, ItemError> {
+        Ok(SeqSerializer { path: self.path, items: Vec::with_capacity(len.unwrap_or_default()) })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, ItemError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self, _name: &'static str, len: usize,
+    ) -> Result<SeqSerializer, ItemError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self, _name: &'static str, _index: u32, variant: &'static str, len: usize,
+    ) -> Result<VariantSerializer<SeqSerializer>, ItemError> {
+        let path = field_path(&self.path, variant);
+        Ok(VariantSerializer { variant, inner: Serializer::new(path).serialize_seq(Some(len))? })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer, ItemError> {
+        Ok(MapSerializer { path: self.path, map: DdbMap::new(), key: None })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapSerializer, ItemError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self, _name: &'static str, _index: u32, variant: &'static str, len: usize,
+    ) -> Result<VariantSerializer<MapSerializer>, ItemError> {
+        let path = field_path(&self.path, variant);
+        Ok(VariantSerializer { variant, inner: Serializer::new(path).serialize_map(Some(len))? })
+    }
+}
+
+pub(super) struct SeqSerializer {
+    path: String,
+    items: Vec<AttributeValue>,
+}
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ItemError> {
+        let path = index_path(&self.path, self.items.len());
+        self.items.push(serialize_at(value, path)?);
+        Ok(())
+    }
+
+    fn list(self) -> AttributeValue {
+        AttributeValue { l: Some(self.items), ..Default::default() }
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = AttributeValue;
+    type Error = ItemError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ItemError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<AttributeValue, ItemError> {
+        Ok(self.list())
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = AttributeValue;
+    type Error = ItemError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ItemError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<AttributeValue, ItemError> {
+        Ok(self.list())
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = AttributeValue;
+    type Error = ItemError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ItemError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<AttributeValue, ItemError> {
+        Ok(self.list())
+    }
+}
+
+pub(super) struct MapSerializer {
+    path: String,
+    map: DdbMap,
+    key: Option<String>,
+}
+
+impl MapSerializer {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: String, value: &T) -> Result<(), ItemError> {
+        let value = serialize_at(value, field_path(&self.path, &key))?;
+        self.map.insert(key, value);
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = AttributeValue;
+    type Error = ItemError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), ItemError> {
+        let key = match key.serialize(Serializer::new(self.path.clone()))? {
+            AttributeValue { s: Some(key), .. } | AttributeValue { n: Some(key), .. } => key,
+            _ => return Err(ItemError::at(&self.path, "map keys have to be strings or numbers")),
+        };
+        self.key = Some(key);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ItemError> {
+        let key = self.key.take().expect("serialize_value is called after serialize_key");
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<AttributeValue, ItemError> {
+        Ok(map(self.map))
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = AttributeValue;
+    type Error = ItemError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self, key: &'static str, value: &T,
+    ) -> Result<(), ItemError> {
+        self.insert(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<AttributeValue, ItemError> {
+        Ok(map(self.map))
+    }
+}
+
+/// A tuple or struct variant, stored as a map from the variant name to its fields
+pub(super) struct VariantSerializer<S> {
+    variant: &'static str,
+    inner: S,
+}
+
+impl<S> VariantSerializer<S> {
+    fn wrap(variant: &str, value: AttributeValue) -> AttributeValue {
+        map(std::iter::once((variant.to_string(), value)).collect())
+    }
+}
+
+impl ser::SerializeTupleVariant for VariantSerializer<SeqSerializer> {
+    type Ok = AttributeValue;
+    type Error = ItemError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ItemError> {
+        self.inner.push(value)
+    }
+
+    fn end(self) -> Result<AttributeValue, ItemError> {
+        Ok(Self::wrap(self.variant, self.inner.list()))
+    }
+}
+
+impl ser::SerializeStructVariant for VariantSerializer<MapSerializer> {
+    type Ok = AttributeValue;
+    type Error = ItemError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self, key: &'static str, value: &T,
+    ) -> Result<(), ItemError> {
+        self.inner.insert(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<AttributeValue, ItemError> {
+        Ok(Self::wrap(self.variant, map(self.inner.map)))
+    }
+}
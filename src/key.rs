@@ -0,0 +1,197 @@
+use crate::{DdbClient, DdbError, DdbMap, IntoAttributeValue};
+use rusoto_dynamodb::{AttributeValue, DescribeTableInput, TableDescription};
+use std::collections::HashMap;
+
+/// Extracts the primary key of an item, so typed items can be deleted without building the
+/// key by hand
+pub trait ItemKey {
+    fn key(&self) -> DdbMap;
+}
+
+/// # Dynamodb key
+/// The primary key of an item, a hash key and an optional range key whose values are strings,
+/// numbers or binary, i.e. `S`, `N` or `B`. `get_item` and `delete_item` validate the key
+/// against the key schema of the table before sending the request, so a missing range key or
+/// a number where the table expects a string fails before the request is sent. Reading the
+/// schema describes the table, which needs the `dynamodb:DescribeTable` permission, on every
+/// call unless the client is wrapped in a `KeySchemaCache`. Builders that take a `DdbMap` key,
+/// e.g. `UpdateItem`, `DeleteItem`, `BatchWrite` or `Transaction`, send it as it is, pass them
+/// `validated(..).await?.key()` to check it first.
+/// ```
+/// # use ddb_util::*;
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// let client = MemoryDb::new()
+///     .table("relations", "pk", Some("version"))
+///     .attribute_type("relations", "version", "N");
+/// let key = Key::hash_range("pk", "c4c", "version", 3).validated(&client, "relations").await?;
+/// let wrong_type = Key::hash_range("pk", "c4c", "version", "3");
+/// assert!(wrong_type.validated(&client, "relations").await.is_err());
+/// let schema = client.key_schema("relations").await?;
+/// assert!(Key::hash("pk", "c4c").validate(&schema).is_err());
+/// #     Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Key {
+    hash: (String, AttributeValue),
+    range: Option<(String, AttributeValue)>,
+}
+
+impl Key {
+    pub fn hash(name: &str, value: impl IntoAttributeValue) -> Key {
+        Key {
+            hash: (name.to_string(), value.into_attribute_value()),
+            range: None,
+        }
+    }
+
+    pub fn hash_range(
+        hash_name: &str, hash_value: impl IntoAttributeValue, range_name: &str,
+        range_value: impl IntoAttributeValue,
+    ) -> Key {
+        Key {
+            hash: (hash_name.to_string(), hash_value.into_attribute_value()),
+            range: Some((range_name.to_string(), range_value.into_attribute_value())),
+        }
+    }
+
+    /// Fails with `DdbError::InvalidKey` unless the key has exactly the key attributes of
+    /// `schema`, with the declared types
+    pub fn validate(&self, schema: &KeySchema) -> Result<(), DdbError> {
+        let expected = std::iter::once(&schema.hash).chain(&schema.range);
+        let actual = std::iter::once(&self.hash).chain(&self.range);
+        let names: Vec<&String> = expected.clone().map(|attribute| &attribute.name).collect();
+        if !actual.clone().map(|(name, _)| name).eq(names.iter().copied()) {
+            let actual: Vec<&String> = actual.map(|(name, _)| name).collect();
+            return Err(DdbError::InvalidKey(format!(
+                "expected the key attributes {:?}, got {:?}",
+                names, actual
+            )));
+        }
+        for ((name, value), attribute) in actual.zip(expected) {
+            let found = key_type(value).ok_or_else(|| {
+                DdbError::InvalidKey(format!("{} has to be a string, number or binary", name))
+            })?;
+            match &attribute.attribute_type {
+                Some(declared) if declared != found => {
+                    return Err(DdbError::InvalidKey(format!(
+                        "{} has to be of type {}, got {}",
+                        name, declared, found
+                    )))
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// The key, after it is validated against the key schema of `table`
+    pub async fn validated(self, client: &impl DdbClient, table: &str) -> Result<Key, DdbError> {
+        self.validate(&client.key_schema(table).await?)?;
+        Ok(self)
+    }
+}
+
+impl ItemKey for Key {
+    fn key(&self) -> DdbMap {
+        std::iter::once(&self.hash).chain(&self.range).cloned().collect()
+    }
+}
+
+/// The type of a key value, `None` unless exactly one of `S`, `N` or `B` is set
+fn key_type(value: &AttributeValue) -> Option<&'static str> {
+    match value {
+        AttributeValue { s: Some(_), n: None, b: None, .. } => Some("S"),
+        AttributeValue { s: None, n: Some(_), b: None, .. } => Some("N"),
+        AttributeValue { s: None, n: None, b: Some(_), .. } => Some("B"),
+        _ => None,
+    }
+}
+
+/// A key attribute of a table, `attribute_type` is `S`, `N` or `B` when it is known
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyAttribute {
+    pub name: String,
+    pub attribute_type: Option<String>,
+}
+
+/// The hash and range key attributes of a table, as returned by `DdbClient::key_schema`
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeySchema {
+    pub hash: KeyAttribute,
+    pub range: Option<KeyAttribute>,
+}
+
+impl KeySchema {
+    pub(crate) fn from_description(table: TableDescription) -> Result<KeySchema, DdbError> {
+        let name = table.table_name.unwrap_or_default();
+        let types: HashMap<String, String> = table
+            .attribute_definitions
+            .unwrap_or_default()
+            .into_iter()
+            .map(|definition| (definition.attribute_name, definition.attribute_type))
+            .collect();
+        let (mut hash, mut range) = (None, None);
+        for element in table.key_schema.unwrap_or_default() {
+            let attribute = KeyAttribute {
+                attribute_type: types.get(&element.attribute_name).cloned(),
+                name: element.attribute_name,
+            };
+            match element.key_type.as_str() {
+                "HASH" => hash = Some(attribute),
+                _ => range = Some(attribute),
+            }
+        }
+        match hash {
+            Some(hash) => Ok(KeySchema { hash, range }),
+            None => Err(DdbError::InvalidKey(format!("{} has no hash key", name))),
+        }
+    }
+}
+
+pub(crate) async fn describe_key_schema(
+    client: &(impl DdbClient + ?Sized), table: &str,
+) -> Result<KeySchema, DdbError> {
+    let input = DescribeTableInput {
+        table_name: table.to_string(),
+    };
+    let description = client.describe_table(input).await?.table.unwrap_or_default();
+    KeySchema::from_description(description)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Blob {
+        size: u64,
+    }
+
+    #[tokio::test]
+    async fn keys_are_validated_against_the_table() -> Result<(), DdbError> {
+        let client = MemoryDb::new()
+            .table("blobs", "id", None)
+            .attribute_type("blobs", "id", "B");
+        let mut item = Key::hash("id", vec![1u8, 2, 3]).key();
+        item.insert("size".to_string(), 3.into_attribute_value());
+        put_item(&client, "blobs", item).await?;
+        let key = Key::hash("id", vec![1u8, 2, 3]);
+        let blob: Option<Blob> = get_item(&client, "blobs", key.clone(), false, None).await?;
+        assert_eq!(blob, Some(Blob { size: 3 }));
+        let res = get_item::<Blob>(&client, "blobs", Key::hash("id", "abc"), false, None).await;
+        assert!(matches!(res, Err(DdbError::InvalidKey(_))));
+        let key_with_range = Key::hash_range("id", vec![1u8], "sk", "x");
+        let res = delete_item::<Blob>(&client, "blobs", key_with_range, None).await;
+        assert!(matches!(res, Err(DdbError::InvalidKey(_))));
+        let res = get_item::<Blob>(&client, "missing", Key::hash("id", "x"), false, None).await;
+        assert!(matches!(res, Err(DdbError::DescribeTable(_))));
+        let res = Key::hash("id", "abc").validated(&client, "blobs").await;
+        assert!(matches!(res, Err(DdbError::InvalidKey(_))));
+        assert_eq!(key.clone().validated(&client, "blobs").await?, key);
+        Ok(())
+    }
+}
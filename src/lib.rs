@@ -1,28 +1,81 @@
+#![allow(clippy::result_large_err)]
 //use rusoto_core::{RusotoError};
-use itertools::Itertools;
+use futures::stream::Stream;
 use rusoto_dynamodb::{
-    AttributeValue, BatchWriteItemInput, DeleteRequest, DynamoDb, DynamoDbClient, GetItemInput,
-    PutItemInput, PutItemOutput, PutRequest, QueryInput, WriteRequest,
+    AttributeValue, Get, GetItemInput, PutItemInput, PutItemOutput, TransactGetItem,
+    TransactGetItemsInput,
 };
-use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use std::collections::HashMap;
 
+// lets the code generated by `#[derive(DdbEntity)]` refer to `::ddb_util` inside this crate
+extern crate self as ddb_util;
+
+mod batch;
+mod client;
+mod delete;
+mod entity;
+mod error;
+mod expression;
+mod item;
+mod key;
+#[cfg(feature = "local")]
+mod local;
+mod map;
+mod memory;
+mod page;
+mod put;
+mod query;
+mod retry;
+mod scan;
+mod schema_cache;
+mod stream;
+mod template;
+mod transaction;
+mod update;
+mod value;
+
+pub use batch::{BatchGet, BatchGetResult, BatchWrite, BatchWriteResult, ChunkError};
+pub use client::DdbClient;
+pub use ddb_util_derive::DdbEntity;
+pub use delete::DeleteItem;
+pub use entity::DdbEntity;
+pub use error::DdbError;
+pub use expression::{
+    attr, not, projection, Attr, Condition, Expression, ExpressionAttributes, Projection, Size,
+    UpdateExpression,
+};
+pub use item::{binary_set, from_item, number, number_set, string_set, to_item, ItemError};
+pub use key::{ItemKey, Key, KeyAttribute, KeySchema};
+#[cfg(feature = "local")]
+pub use local::{LocalDynamoDb, TableSchema, LOCAL_ENDPOINT_VAR};
+pub use map::{DdbMapBuilder, DdbMapExt};
+pub use memory::MemoryDb;
+pub use page::{Page, PageToken};
+pub use put::PutItem;
+pub use query::Query;
+pub use retry::RetryPolicy;
+pub use scan::Scan;
+pub use schema_cache::KeySchemaCache;
+pub use template::KeyTemplate;
+pub use transaction::{CancellationReason, Transaction};
+pub use update::UpdateItem;
+pub use value::{FromAttributeValue, IntoAttributeValue};
+
 pub type DdbMap = HashMap<String, AttributeValue>;
 
 pub fn set_kv(
-    item: &mut HashMap<String, AttributeValue>, key: String, val: String,
+    item: &mut HashMap<String, AttributeValue>, key: String, val: impl IntoAttributeValue,
 ) -> &HashMap<String, AttributeValue> {
-    item.insert(
-        key,
-        AttributeValue {
-            s: Some(val),
-            ..Default::default()
-        },
-    );
+    item.insert(key, val.into_attribute_value());
     item
 }
 
-/// # Dynamodb query function
+/// # Dynamodb get_item function
+/// Returns `None` when no item exists for the key. The key is validated against the key schema
+/// of the table first, see `Key`. Set `consistent_read` for a strongly consistent read, and
+/// pass a `projection_exp` to fetch only some of the attributes.
 /// ```
 /// # use rusoto_core::{Region, RusotoError};
 /// # use rusoto_dynamodb::{
@@ -32,7 +85,7 @@ pub fn set_kv(
 /// # use std::collections::HashMap;
 /// # use ddb_util::*;
 ///
-/// # #[derive(Debug)]
+/// # #[derive(Debug, Deserialize)]
 /// # struct Dataset {
 /// #     pk: String,
 /// #     sk: String,
@@ -40,150 +93,786 @@ pub fn set_kv(
 /// #     created: Option<u64>,
 /// # }
 ///
-/// # #[tokio::test]
-/// # async fn try_ddb_util_main() -> Result<(), String> {
+/// # #[cfg(feature = "local")]
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// # let local = match LocalDynamoDb::from_env()? {
+/// #     Some(local) => local,
+/// #     None => return Ok(()),
+/// # };
+/// # let relations = local.fixture("fixtures/relations.json").await?;
+/// # let relations = relations.as_str();
 /// let client = DynamoDbClient::new(Region::EuWest1);
-/// let mut key: DdbMap = HashMap::new();
-/// set_kv(&mut key, "pk".to_string(), "c4c".to_string());
-/// set_kv(&mut key, "sk".to_string(), "c4c".to_string());
-/// let x: Dataset = get_item(&client, "relations", key).await;
+/// # let client = local.client().clone();
+/// let key = Key::hash_range("pk", "c4c", "sk", "dataset#1");
+/// let x: Option<Dataset> = get_item(&client, relations, key, false, None).await?;
+/// # local.teardown().await?;
 /// #     Ok(())
 /// # }
+/// # #[cfg(not(feature = "local"))]
+/// # fn main() {}
 /// ```
-pub async fn get_item<'a, T: Deserialize<'a> + Default>(
-    client: &DynamoDbClient, table: &str, key: DdbMap,
-) -> T {
+pub async fn get_item<T: DeserializeOwned>(
+    client: &impl DdbClient, table: &str, key: Key, consistent_read: bool,
+    projection_exp: Option<String>,
+) -> Result<Option<T>, DdbError> {
+    key.validate(&client.key_schema(table).await?)?;
     let get_item_input = GetItemInput {
-        key,
+        key: key.key(),
         table_name: table.to_string(),
+        consistent_read: Some(consistent_read),
+        projection_expression: projection_exp,
         ..Default::default()
     };
-    let res = client.get_item(get_item_input).await.unwrap().item.unwrap();
-    serde_dynamodb::from_hashmap(res).unwrap()
+    match client.get_item(get_item_input).await?.item {
+        Some(item) => Ok(Some(from_item(item)?)),
+        None => Ok(None),
+    }
+}
+
+/// The positional arguments of the deprecated query functions as a `Query`
+fn positional_query(
+    table: &str, index_name: Option<String>, key_cond_exp: Option<String>,
+    exp_attr_vals: Option<DdbMap>, exp_attr_names: Option<HashMap<String, String>>,
+    projection_exp: Option<String>, filter_exp: Option<String>,
+) -> Query {
+    let mut query = Query::new(table)
+        .values(exp_attr_vals.unwrap_or_default())
+        .names(exp_attr_names.unwrap_or_default());
+    if let Some(index_name) = index_name {
+        query = query.index(&index_name);
+    }
+    if let Some(key_cond_exp) = key_cond_exp {
+        query = query.key_condition(key_cond_exp);
+    }
+    if let Some(projection_exp) = projection_exp {
+        query = query.project(projection_exp);
+    }
+    if let Some(filter_exp) = filter_exp {
+        query = query.filter(filter_exp);
+    }
+    query
 }
 
 /// # Dynamodb query function
+/// Follows `LastEvaluatedKey` until the result set is exhausted, or until `max_items` items
+/// have been collected. Same as `Query::send`.
+#[deprecated(note = "use the Query builder")]
+#[allow(clippy::too_many_arguments)]
+pub async fn query<T: DeserializeOwned>(
+    client: &impl DdbClient, table: &str, index_name: Option<String>, key_cond_exp: Option<String>,
+    exp_attr_vals: Option<DdbMap>, exp_attr_names: Option<HashMap<String, String>>,
+    projection_exp: Option<String>, filter_exp: Option<String>, max_items: Option<usize>,
+) -> Result<Vec<T>, DdbError> {
+    let mut query = positional_query(
+        table,
+        index_name,
+        key_cond_exp,
+        exp_attr_vals,
+        exp_attr_names,
+        projection_exp,
+        filter_exp,
+    );
+    if let Some(max_items) = max_items {
+        query = query.max_items(max_items);
+    }
+    query.send(client).await
+}
+
+/// # Dynamodb paged query function
+/// Fetches a single page of at most `limit` items starting after `token`. Same as `Query::page`.
+#[deprecated(note = "use the Query builder")]
+#[allow(clippy::too_many_arguments)]
+pub async fn query_page<T: DeserializeOwned>(
+    client: &impl DdbClient, table: &str, index_name: Option<String>, key_cond_exp: Option<String>,
+    exp_attr_vals: Option<DdbMap>, exp_attr_names: Option<HashMap<String, String>>,
+    projection_exp: Option<String>, filter_exp: Option<String>, limit: Option<i64>,
+    token: Option<&PageToken>,
+) -> Result<Page<T>, DdbError> {
+    let mut query = positional_query(
+        table,
+        index_name,
+        key_cond_exp,
+        exp_attr_vals,
+        exp_attr_names,
+        projection_exp,
+        filter_exp,
+    );
+    if let Some(limit) = limit {
+        query = query.limit(limit);
+    }
+    query.page(client, token).await
+}
+
+/// # Dynamodb streaming query function
+/// Yields the items one at a time, fetching the next page only when the consumer asks for
+/// more items. Same as `Query::stream`.
+#[deprecated(note = "use the Query builder")]
+#[allow(clippy::too_many_arguments)]
+pub fn query_stream<'c, T: DeserializeOwned + 'c>(
+    client: &'c impl DdbClient, table: &str, index_name: Option<String>,
+    key_cond_exp: Option<String>, exp_attr_vals: Option<DdbMap>,
+    exp_attr_names: Option<HashMap<String, String>>, projection_exp: Option<String>,
+    filter_exp: Option<String>,
+) -> impl Stream<Item = Result<T, DdbError>> + 'c {
+    positional_query(
+        table,
+        index_name,
+        key_cond_exp,
+        exp_attr_vals,
+        exp_attr_names,
+        projection_exp,
+        filter_exp,
+    )
+    .stream(client)
+}
+
+/// # Dynamodb scan function
+/// Scans the whole table, following `LastEvaluatedKey` until every item has been read. Use
+/// `Scan` for filters, projections, streaming and parallel scans.
 /// ```
-/// use rusoto_core::{Region, RusotoError};
-/// use rusoto_dynamodb::{
-///     AttributeValue, DynamoDb, DynamoDbClient, QueryError, QueryInput,
-/// };
-/// use serde::{Deserialize};
-/// use std::collections::HashMap;
-/// use ddb_util::*;
+/// # use rusoto_core::Region;
+/// # use rusoto_dynamodb::DynamoDbClient;
+/// # use serde::Deserialize;
+/// # use ddb_util::*;
 ///
-/// # #[derive(Debug)]
+/// # #[derive(Debug, Deserialize)]
 /// # struct Dataset {
 /// #     pk: String,
 /// #     sk: String,
-/// #     itemtype: String,
-/// #     created: Option<u64>,
 /// # }
 ///
-/// # #[tokio::test]
-/// # async fn try_ddb_util_main() -> Result<(), String> {
+/// # #[cfg(feature = "local")]
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// # let local = match LocalDynamoDb::from_env()? {
+/// #     Some(local) => local,
+/// #     None => return Ok(()),
+/// # };
+/// # let relations = local.fixture("fixtures/relations.json").await?;
+/// # let relations = relations.as_str();
 /// let client = DynamoDbClient::new(Region::EuWest1);
-/// let x: Vec<Dataset> = query(&client, "relations", "dataset").await;
+/// # let client = local.client().clone();
+/// let x: Vec<Dataset> = scan(&client, relations).await?;
+/// # local.teardown().await?;
 /// #     Ok(())
 /// # }
+/// # #[cfg(not(feature = "local"))]
+/// # fn main() {}
 /// ```
-pub async fn query<'a, T: Deserialize<'a>>(
-    client: &DynamoDbClient, table: &str, index_name: Option<String>, key_cond_exp: Option<String>,
-    exp_attr_vals: Option<DdbMap>, exp_attr_names: Option<HashMap<String, String>>, projection_exp: Option<String>,
-    filter_exp: Option<String>) -> Vec<T> {
-    let query_input = QueryInput {
-        key_condition_expression: key_cond_exp,
-        expression_attribute_values: exp_attr_vals,
-        expression_attribute_names: exp_attr_names,
-        projection_expression: projection_exp,
-        filter_expression: filter_exp,
-        table_name: table.to_string(),
-        index_name,
-        ..Default::default()
-    };
-    let items: Vec<T> = client
-        .query(query_input)
-        .await
-        .unwrap()
-        .items
-        .unwrap_or_else(|| vec![])
-        .into_iter()
-        .map(|item| {
-            serde_dynamodb::from_hashmap(item).unwrap()
-        })
-        .collect();
-    items
+pub async fn scan<T: DeserializeOwned>(
+    client: &(impl DdbClient + Clone + 'static), table: &str,
+) -> Result<Vec<T>, DdbError> {
+    Scan::new(table).send(client).await
 }
 
-pub async fn put_item(client: &DynamoDbClient, table: &str, item: DdbMap) -> PutItemOutput {
+/// # Dynamodb streaming scan function
+/// Scans the whole table, or index, one page at a time. Like `Query::stream` the next page is
+/// only requested when the consumer asks for more items. Same as `Scan::stream`.
+#[deprecated(note = "use the Scan builder")]
+pub fn scan_stream<T: DeserializeOwned>(
+    client: &(impl DdbClient + Clone + 'static), table: &str, index_name: Option<String>,
+    exp_attr_vals: Option<DdbMap>, exp_attr_names: Option<HashMap<String, String>>,
+    projection_exp: Option<String>, filter_exp: Option<String>,
+) -> impl Stream<Item = Result<T, DdbError>> {
+    let mut scan = Scan::new(table)
+        .values(exp_attr_vals.unwrap_or_default())
+        .names(exp_attr_names.unwrap_or_default());
+    if let Some(index_name) = index_name {
+        scan = scan.index(&index_name);
+    }
+    if let Some(projection_exp) = projection_exp {
+        scan = scan.project(projection_exp);
+    }
+    if let Some(filter_exp) = filter_exp {
+        scan = scan.filter(filter_exp);
+    }
+    scan.stream(client)
+}
+
+pub async fn put_item(
+    client: &impl DdbClient, table: &str, item: DdbMap,
+) -> Result<PutItemOutput, DdbError> {
     let input = PutItemInput {
         table_name: table.to_string(),
         item,
         ..Default::default()
     };
-    let res = client.put_item(input).await.unwrap();
-    res
+    let res = client.put_item(input).await?;
+    Ok(res)
 }
 
-fn create_write_request(
-    write_items: Option<Vec<DdbMap>>, delete_items: Option<Vec<DdbMap>>,
-) -> Vec<WriteRequest> {
-    let mut dwr: Vec<WriteRequest>;
-    if let Some(di) = delete_items {
-        dwr = di
-            .into_iter()
-            .map(|x| WriteRequest {
-                delete_request: Some(DeleteRequest { key: x }),
-                put_request: None,
-            })
-            .collect();
-    } else {
-        dwr = vec![]
-    }
-    if let Some(wi) = write_items {
-        let pwr: Vec<WriteRequest> = wi
-            .into_iter()
-            .map(|x| WriteRequest {
-                delete_request: None,
-                put_request: Some(PutRequest { item: x }),
-            })
-            .collect();
-        dwr.extend(pwr);
+/// # Dynamodb put_item function for serializable items
+/// Serializes `item` with `to_item` and puts it, a serialization failure is returned as
+/// `DdbError::Serde`.
+/// ```
+/// # use rusoto_core::Region;
+/// # use rusoto_dynamodb::DynamoDbClient;
+/// # use serde::Serialize;
+/// # use ddb_util::*;
+///
+/// # #[derive(Debug, Serialize)]
+/// # struct Dataset {
+/// #     pk: String,
+/// #     sk: String,
+/// # }
+///
+/// # #[cfg(feature = "local")]
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// # let local = match LocalDynamoDb::from_env()? {
+/// #     Some(local) => local,
+/// #     None => return Ok(()),
+/// # };
+/// # let relations = local.fixture("fixtures/relations.json").await?;
+/// # let relations = relations.as_str();
+/// let client = DynamoDbClient::new(Region::EuWest1);
+/// # let client = local.client().clone();
+/// let dataset = Dataset {
+///     pk: "c4c".to_string(),
+///     sk: "dataset#1".to_string(),
+/// };
+/// put_typed_item(&client, relations, &dataset).await?;
+/// # local.teardown().await?;
+/// #     Ok(())
+/// # }
+/// # #[cfg(not(feature = "local"))]
+/// # fn main() {}
+/// ```
+pub async fn put_typed_item<T: Serialize>(
+    client: &impl DdbClient, table: &str, item: &T,
+) -> Result<PutItemOutput, DdbError> {
+    put_item(client, table, to_item(item)?).await
+}
+
+/// # Dynamodb put_if_absent function
+/// Puts `item` only when no item with the same key exists. The key attributes are read from
+/// the key schema of the table, once per client when it is wrapped in a `KeySchemaCache`. An
+/// existing item is reported as `DdbError::ConditionalCheckFailed`.
+/// ```
+/// # use rusoto_core::Region;
+/// # use rusoto_dynamodb::DynamoDbClient;
+/// # use std::collections::HashMap;
+/// # use ddb_util::*;
+///
+/// # #[cfg(feature = "local")]
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// # let local = match LocalDynamoDb::from_env()? {
+/// #     Some(local) => local,
+/// #     None => return Ok(()),
+/// # };
+/// # let relations = local.fixture("fixtures/relations.json").await?;
+/// # let relations = relations.as_str();
+/// let client = DynamoDbClient::new(Region::EuWest1);
+/// # let client = local.client().clone();
+/// let mut item: DdbMap = HashMap::new();
+/// set_kv(&mut item, "pk".to_string(), "c4c".to_string());
+/// set_kv(&mut item, "sk".to_string(), "dataset#1".to_string());
+/// match put_if_absent(&client, relations, item).await {
+///     Ok(()) => println!("created"),
+///     Err(DdbError::ConditionalCheckFailed(_)) => println!("already exists"),
+///     Err(e) => return Err(e),
+/// }
+/// # local.teardown().await?;
+/// #     Ok(())
+/// # }
+/// # #[cfg(not(feature = "local"))]
+/// # fn main() {}
+/// ```
+pub async fn put_if_absent(
+    client: &impl DdbClient, table: &str, item: DdbMap,
+) -> Result<(), DdbError> {
+    let schema = client.key_schema(table).await?;
+    let key_attrs: Vec<&str> = std::iter::once(&schema.hash)
+        .chain(&schema.range)
+        .map(|attribute| attribute.name.as_str())
+        .collect();
+    PutItem::new(table, item)
+        .if_absent(&key_attrs)
+        .send::<DdbMap>(client)
+        .await
+        .map(|_| ())
+}
+
+/// # Dynamodb put_with_condition function
+/// Puts `item` if `condition_exp` holds for the item it replaces, and returns the replaced
+/// item, `None` when there was none. A failed condition is `DdbError::ConditionalCheckFailed`.
+/// ```
+/// # use rusoto_core::Region;
+/// # use rusoto_dynamodb::DynamoDbClient;
+/// # use serde::Deserialize;
+/// # use std::collections::HashMap;
+/// # use ddb_util::*;
+///
+/// # #[derive(Debug, Deserialize)]
+/// # struct Dataset {
+/// #     pk: String,
+/// #     sk: String,
+/// # }
+///
+/// # #[cfg(feature = "local")]
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// # let local = match LocalDynamoDb::from_env()? {
+/// #     Some(local) => local,
+/// #     None => return Ok(()),
+/// # };
+/// # let relations = local.fixture("fixtures/relations.json").await?;
+/// # let relations = relations.as_str();
+/// let client = DynamoDbClient::new(Region::EuWest1);
+/// # let client = local.client().clone();
+/// let mut item: DdbMap = HashMap::new();
+/// set_kv(&mut item, "pk".to_string(), "c4c".to_string());
+/// set_kv(&mut item, "sk".to_string(), "dataset#1".to_string());
+/// let condition = attr("pk").attribute_not_exists().or(attr("status").ne("locked"));
+/// let x: Option<Dataset> = put_with_condition(&client, relations, item, condition).await?;
+/// # local.teardown().await?;
+/// #     Ok(())
+/// # }
+/// # #[cfg(not(feature = "local"))]
+/// # fn main() {}
+/// ```
+pub async fn put_with_condition<T: DeserializeOwned>(
+    client: &impl DdbClient, table: &str, item: DdbMap, condition_exp: impl Expression,
+) -> Result<Option<T>, DdbError> {
+    PutItem::new(table, item)
+        .condition(condition_exp)
+        .return_values("ALL_OLD")
+        .send(client)
+        .await
+}
+
+/// # Dynamodb update_item function
+/// Applies `update_exp` to the item with `key` and returns the item as it is after the update.
+/// Use `UpdateItem` for conditions and other return values.
+/// ```
+/// # use rusoto_core::Region;
+/// # use rusoto_dynamodb::DynamoDbClient;
+/// # use serde::Deserialize;
+/// # use std::collections::HashMap;
+/// # use ddb_util::*;
+///
+/// # #[derive(Debug, Deserialize)]
+/// # struct Dataset {
+/// #     pk: String,
+/// #     sk: String,
+/// #     views: u64,
+/// # }
+///
+/// # #[cfg(feature = "local")]
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// # let local = match LocalDynamoDb::from_env()? {
+/// #     Some(local) => local,
+/// #     None => return Ok(()),
+/// # };
+/// # let relations = local.fixture("fixtures/relations.json").await?;
+/// # let relations = relations.as_str();
+/// let client = DynamoDbClient::new(Region::EuWest1);
+/// # let client = local.client().clone();
+/// let mut key: DdbMap = HashMap::new();
+/// set_kv(&mut key, "pk".to_string(), "c4c".to_string());
+/// set_kv(&mut key, "sk".to_string(), "dataset#1".to_string());
+/// let update = UpdateExpression::new().add("views", 1).remove("draft");
+/// let x: Option<Dataset> = update_item(&client, relations, key, update).await?;
+/// # local.teardown().await?;
+/// #     Ok(())
+/// # }
+/// # #[cfg(not(feature = "local"))]
+/// # fn main() {}
+/// ```
+pub async fn update_item<T: DeserializeOwned>(
+    client: &impl DdbClient, table: &str, key: DdbMap, update_exp: impl Expression,
+) -> Result<Option<T>, DdbError> {
+    UpdateItem::new(table, key)
+        .update(update_exp)
+        .return_values("ALL_NEW")
+        .send(client)
+        .await
+}
+
+/// # Dynamodb delete_item function
+/// Deletes the item with `key` if `condition` holds and returns the deleted item, `None` when
+/// no item existed. A failed condition is `DdbError::ConditionalCheckFailed`. The key is
+/// validated against the key schema of the table first, see `Key`.
+/// ```
+/// # use rusoto_core::Region;
+/// # use rusoto_dynamodb::DynamoDbClient;
+/// # use serde::Deserialize;
+/// # use std::collections::HashMap;
+/// # use ddb_util::*;
+///
+/// # #[derive(Debug, Deserialize)]
+/// # struct Dataset {
+/// #     pk: String,
+/// #     sk: String,
+/// # }
+///
+/// # #[cfg(feature = "local")]
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// # let local = match LocalDynamoDb::from_env()? {
+/// #     Some(local) => local,
+/// #     None => return Ok(()),
+/// # };
+/// # let relations = local.fixture("fixtures/relations.json").await?;
+/// # let relations = relations.as_str();
+/// let client = DynamoDbClient::new(Region::EuWest1);
+/// # let client = local.client().clone();
+/// let key = Key::hash_range("pk", "c4c", "sk", "dataset#1");
+/// let x: Option<Dataset> = delete_item(&client, relations, key, None).await?;
+/// # local.teardown().await?;
+/// #     Ok(())
+/// # }
+/// # #[cfg(not(feature = "local"))]
+/// # fn main() {}
+/// ```
+pub async fn delete_item<T: DeserializeOwned>(
+    client: &impl DdbClient, table: &str, key: Key, condition: Option<Condition>,
+) -> Result<Option<T>, DdbError> {
+    key.validate(&client.key_schema(table).await?)?;
+    let delete = DeleteItem::new(table, key.key()).return_values("ALL_OLD");
+    match condition {
+        Some(condition) => delete.condition(condition).send(client).await,
+        None => delete.send(client).await,
     }
-    dwr
 }
 
+/// # Dynamodb batch write function
+/// Deletes `delete_items` and puts `write_items` in chunks of 25. Unprocessed items are
+/// retried with the default `RetryPolicy`, use `BatchWrite` to configure the retries.
+/// ```
+/// # use rusoto_core::Region;
+/// # use rusoto_dynamodb::DynamoDbClient;
+/// # use std::collections::HashMap;
+/// # use ddb_util::*;
+///
+/// # #[cfg(feature = "local")]
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// # let local = match LocalDynamoDb::from_env()? {
+/// #     Some(local) => local,
+/// #     None => return Ok(()),
+/// # };
+/// # let relations = local.fixture("fixtures/relations.json").await?;
+/// # let relations = relations.as_str();
+/// let client = DynamoDbClient::new(Region::EuWest1);
+/// # let client = local.client().clone();
+/// let mut key: DdbMap = HashMap::new();
+/// set_kv(&mut key, "pk".to_string(), "c4c".to_string());
+/// set_kv(&mut key, "sk".to_string(), "dataset#1".to_string());
+/// let res = batch_write_items(&client, relations, None, Some(vec![key])).await?;
+/// println!("{} deleted, {} failed", res.succeeded, res.failed());
+/// # local.teardown().await?;
+/// #     Ok(())
+/// # }
+/// # #[cfg(not(feature = "local"))]
+/// # fn main() {}
+/// ```
 pub async fn batch_write_items(
-    client: &DynamoDbClient, table: &str, write_items: Option<Vec<DdbMap>>,
+    client: &impl DdbClient, table: &str, write_items: Option<Vec<DdbMap>>,
     delete_items: Option<Vec<DdbMap>>,
-) -> Vec<WriteRequest> {
-    let mut vector: Vec<WriteRequest> = Vec::new();
-    let v = create_write_request(write_items, delete_items);
-    for chunk in &v.into_iter().chunks(25) {
-        let c: Vec<WriteRequest> = chunk.collect();
-        let mut m = HashMap::new();
-        m.insert(table.to_string(), c);
-        let input = BatchWriteItemInput {
-            request_items: m,
-            ..Default::default()
-        };
-        let res = client.batch_write_item(input).await.unwrap();
-        if let Some(m) = res.unprocessed_items {
-            if let Some(e) = m.get(table) {
-                vector.extend(e.clone())
-            }
-        }
+) -> Result<BatchWriteResult, DdbError> {
+    BatchWrite::new(table)
+        .delete_all(delete_items.unwrap_or_default())
+        .put_all(write_items.unwrap_or_default())
+        .send(client)
+        .await
+}
+
+/// # Dynamodb batch get function
+/// Reads `keys` from one table in chunks of 100 and returns the items in the order of the keys,
+/// `None` where no item exists. Use `BatchGet` to read from several tables in one call or to
+/// configure the retries.
+/// ```
+/// # use rusoto_core::Region;
+/// # use rusoto_dynamodb::DynamoDbClient;
+/// # use serde::Deserialize;
+/// # use std::collections::HashMap;
+/// # use ddb_util::*;
+///
+/// # #[derive(Debug, Deserialize)]
+/// # struct Dataset {
+/// #     pk: String,
+/// #     sk: String,
+/// # }
+///
+/// # #[cfg(feature = "local")]
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// # let local = match LocalDynamoDb::from_env()? {
+/// #     Some(local) => local,
+/// #     None => return Ok(()),
+/// # };
+/// # let relations = local.fixture("fixtures/relations.json").await?;
+/// # let relations = relations.as_str();
+/// let client = DynamoDbClient::new(Region::EuWest1);
+/// # let client = local.client().clone();
+/// let mut key: DdbMap = HashMap::new();
+/// set_kv(&mut key, "pk".to_string(), "c4c".to_string());
+/// set_kv(&mut key, "sk".to_string(), "dataset#1".to_string());
+/// let x: Vec<Option<Dataset>> = batch_get_items(&client, relations, vec![key]).await?;
+/// # local.teardown().await?;
+/// #     Ok(())
+/// # }
+/// # #[cfg(not(feature = "local"))]
+/// # fn main() {}
+/// ```
+pub async fn batch_get_items<T: DeserializeOwned>(
+    client: &impl DdbClient, table: &str, keys: Vec<DdbMap>,
+) -> Result<Vec<Option<T>>, DdbError> {
+    BatchGet::new().keys(table, keys).send(client).await?.take(table)
+}
+
+/// # Dynamodb transact get function
+/// Reads the `(table, key, projection_exp)` requests as one consistent snapshot and returns the
+/// items in request order, `None` where no item exists. At most 100 requests, on one or more
+/// tables, fit in a transaction, more fail with `DdbError::TransactionTooLarge` before anything
+/// is sent. No request is sent for an empty list, and a key may only be requested once.
+/// ```
+/// # use rusoto_core::Region;
+/// # use rusoto_dynamodb::DynamoDbClient;
+/// # use serde::Deserialize;
+/// # use std::collections::HashMap;
+/// # use ddb_util::*;
+///
+/// # #[derive(Debug, Deserialize)]
+/// # struct Dataset {
+/// #     pk: String,
+/// #     sk: String,
+/// # }
+///
+/// # #[cfg(feature = "local")]
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// # let local = match LocalDynamoDb::from_env()? {
+/// #     Some(local) => local,
+/// #     None => return Ok(()),
+/// # };
+/// # let relations = local.fixture("fixtures/relations.json").await?;
+/// # let relations = relations.as_str();
+/// # let relations_archive = local.fixture("fixtures/relations_archive.json").await?;
+/// # let relations_archive = relations_archive.as_str();
+/// let client = DynamoDbClient::new(Region::EuWest1);
+/// # let client = local.client().clone();
+/// let mut key: DdbMap = HashMap::new();
+/// set_kv(&mut key, "pk".to_string(), "c4c".to_string());
+/// set_kv(&mut key, "sk".to_string(), "dataset#1".to_string());
+/// let x: Vec<Option<Dataset>> = transact_get_items(
+///     &client,
+///     vec![
+///         (relations, key.clone(), None),
+///         (relations_archive, key, Some("pk, sk".to_string())),
+///     ],
+/// )
+/// .await?;
+/// # local.teardown().await?;
+/// #     Ok(())
+/// # }
+/// # #[cfg(not(feature = "local"))]
+/// # fn main() {}
+/// ```
+pub async fn transact_get_items<T: DeserializeOwned>(
+    client: &impl DdbClient, requests: Vec<(&str, DdbMap, Option<String>)>,
+) -> Result<Vec<Option<T>>, DdbError> {
+    transact_get_items_raw(client, requests)
+        .await?
+        .into_iter()
+        .map(|item| item.map(from_item).transpose())
+        .collect::<Result<_, _>>()
+        .map_err(DdbError::from)
+}
+
+/// # Dynamodb transact get function for items of different types
+/// Same as `transact_get_items`, but returns the raw items so each one can be deserialized
+/// into its own type with `from_item`.
+/// ```
+/// # use rusoto_core::Region;
+/// # use rusoto_dynamodb::DynamoDbClient;
+/// # use serde::Deserialize;
+/// # use std::collections::HashMap;
+/// # use ddb_util::*;
+///
+/// # #[derive(Debug, Deserialize)]
+/// # struct Dataset {
+/// #     pk: String,
+/// #     sk: String,
+/// # }
+/// # #[derive(Debug, Deserialize)]
+/// # struct Owner {
+/// #     pk: String,
+/// #     name: String,
+/// # }
+///
+/// # #[cfg(feature = "local")]
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// # let local = match LocalDynamoDb::from_env()? {
+/// #     Some(local) => local,
+/// #     None => return Ok(()),
+/// # };
+/// # let relations = local.fixture("fixtures/relations.json").await?;
+/// # let relations = relations.as_str();
+/// # let owners = local.fixture("fixtures/owners.json").await?;
+/// # let owners = owners.as_str();
+/// let client = DynamoDbClient::new(Region::EuWest1);
+/// # let client = local.client().clone();
+/// let mut dataset_key: DdbMap = HashMap::new();
+/// set_kv(&mut dataset_key, "pk".to_string(), "c4c".to_string());
+/// set_kv(&mut dataset_key, "sk".to_string(), "dataset#1".to_string());
+/// let mut owner_key: DdbMap = HashMap::new();
+/// set_kv(&mut owner_key, "pk".to_string(), "owner#1".to_string());
+/// let mut items = transact_get_items_raw(
+///     &client,
+///     vec![(relations, dataset_key, None), (owners, owner_key, None)],
+/// )
+/// .await?
+/// .into_iter();
+/// let dataset: Option<Dataset> =
+///     items.next().flatten().map(from_item).transpose()?;
+/// let owner: Option<Owner> =
+///     items.next().flatten().map(from_item).transpose()?;
+/// # local.teardown().await?;
+/// #     Ok(())
+/// # }
+/// # #[cfg(not(feature = "local"))]
+/// # fn main() {}
+/// ```
+pub async fn transact_get_items_raw(
+    client: &impl DdbClient, requests: Vec<(&str, DdbMap, Option<String>)>,
+) -> Result<Vec<Option<DdbMap>>, DdbError> {
+    if requests.is_empty() {
+        return Ok(Vec::new());
+    }
+    if requests.len() > transaction::MAX_TRANSACT_ITEMS {
+        return Err(DdbError::TransactionTooLarge(requests.len()));
     }
-    vector
+    let input = TransactGetItemsInput {
+        transact_items: requests
+            .into_iter()
+            .map(|(table, key, projection_exp)| TransactGetItem {
+                get: Get {
+                    table_name: table.to_string(),
+                    key,
+                    projection_expression: projection_exp,
+                    ..Default::default()
+                },
+            })
+            .collect(),
+        ..Default::default()
+    };
+    let res = client.transact_get_items(input).await?;
+    Ok(res
+        .responses
+        .unwrap_or_default()
+        .into_iter()
+        .map(|response| response.item)
+        .collect())
+}
+
+/// # Dynamodb batch put function for serializable items
+/// Serializes `items` and puts them in chunks of 25 like `batch_write_items`. Nothing is
+/// written when one of the items fails to serialize.
+/// ```
+/// # use rusoto_core::Region;
+/// # use rusoto_dynamodb::DynamoDbClient;
+/// # use serde::Serialize;
+/// # use ddb_util::*;
+///
+/// # #[derive(Debug, Serialize)]
+/// # struct Dataset {
+/// #     pk: String,
+/// #     sk: String,
+/// # }
+///
+/// # #[cfg(feature = "local")]
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// # let local = match LocalDynamoDb::from_env()? {
+/// #     Some(local) => local,
+/// #     None => return Ok(()),
+/// # };
+/// # let relations = local.fixture("fixtures/relations.json").await?;
+/// # let relations = relations.as_str();
+/// let client = DynamoDbClient::new(Region::EuWest1);
+/// # let client = local.client().clone();
+/// let datasets: Vec<Dataset> = (1..=3)
+///     .map(|i| Dataset {
+///         pk: "c4c".to_string(),
+///         sk: format!("dataset#{}", i),
+///     })
+///     .collect();
+/// let res = batch_put_items(&client, relations, &datasets).await?;
+/// assert_eq!(res.failed(), 0);
+/// # local.teardown().await?;
+/// #     Ok(())
+/// # }
+/// # #[cfg(not(feature = "local"))]
+/// # fn main() {}
+/// ```
+pub async fn batch_put_items<'a, T: Serialize + 'a>(
+    client: &impl DdbClient, table: &str, items: impl IntoIterator<Item = &'a T>,
+) -> Result<BatchWriteResult, DdbError> {
+    BatchWrite::new(table).put_all_typed(items).send(client).await
+}
+
+/// # Dynamodb batch delete function for typed items
+/// Deletes the items with the keys of `items`, see `ItemKey`, in chunks of 25 like
+/// `batch_write_items`.
+/// ```
+/// # use rusoto_core::Region;
+/// # use rusoto_dynamodb::DynamoDbClient;
+/// # use std::collections::HashMap;
+/// # use ddb_util::*;
+///
+/// # struct Dataset {
+/// #     pk: String,
+/// #     sk: String,
+/// # }
+/// impl ItemKey for Dataset {
+///     fn key(&self) -> DdbMap {
+///         let mut key: DdbMap = HashMap::new();
+///         set_kv(&mut key, "pk".to_string(), self.pk.clone());
+///         set_kv(&mut key, "sk".to_string(), self.sk.clone());
+///         key
+///     }
+/// }
+///
+/// # #[cfg(feature = "local")]
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// # let local = match LocalDynamoDb::from_env()? {
+/// #     Some(local) => local,
+/// #     None => return Ok(()),
+/// # };
+/// # let relations = local.fixture("fixtures/relations.json").await?;
+/// # let relations = relations.as_str();
+/// let client = DynamoDbClient::new(Region::EuWest1);
+/// # let client = local.client().clone();
+/// let stale = vec![Dataset {
+///     pk: "c4c".to_string(),
+///     sk: "dataset#1".to_string(),
+/// }];
+/// let res = batch_delete_items(&client, relations, &stale).await?;
+/// # local.teardown().await?;
+/// #     Ok(())
+/// # }
+/// # #[cfg(not(feature = "local"))]
+/// # fn main() {}
+/// ```
+pub async fn batch_delete_items<'a, K: ItemKey + 'a>(
+    client: &impl DdbClient, table: &str, items: impl IntoIterator<Item = &'a K>,
+) -> Result<BatchWriteResult, DdbError> {
+    BatchWrite::new(table).delete_all_typed(items).send(client).await
 }
 
 #[cfg(test)]
 mod tests {
     use crate::*;
-    use rusoto_core::Region;
-    use rusoto_dynamodb::DynamoDbClient;
     use serde::Deserialize;
 
+    #[allow(dead_code)]
     #[derive(Debug, Deserialize)]
     struct Dataset {
         pk: String,
@@ -192,26 +881,70 @@ mod tests {
         created: Option<u64>,
     }
 
+    #[cfg(feature = "local")]
     #[tokio::test]
     async fn try_ddb_util_main() -> Result<(), String> {
+        let local = match LocalDynamoDb::from_env().map_err(|e| e.to_string())? {
+            Some(local) => local,
+            None => return Ok(()),
+        };
+        let relations = local.fixture("fixtures/relations.json").await.map_err(|e| e.to_string())?;
         let mut exp_attr: DdbMap = HashMap::new();
         set_kv(
             &mut exp_attr,
             ":itemtype".to_string(),
             "dataset".to_string(),
         );
-        let client = DynamoDbClient::new(Region::EuWest1);
-        let _x: Vec<Dataset> = query(
-            &client,
-            "relations",
-            Some("".to_string()),
-            Some("itemtype = :itemtype".to_string()),
-            Some(exp_attr),
-            None,
-            None,
-            None
-        )
-        .await;
+        let x: Vec<Dataset> = Query::new(&relations)
+            .index("itemtype-index")
+            .key_condition("itemtype = :itemtype")
+            .values(exp_attr)
+            .send(local.client())
+            .await
+            .map_err(|e| e.to_string())?;
+        assert_eq!(x.len(), 3);
+        local.teardown().await.map_err(|e| e.to_string())
+    }
+
+    fn key(sk: &str) -> DdbMap {
+        ddb_map! { "pk" => "c4c", "sk" => sk }
+    }
+
+    #[tokio::test]
+    async fn transact_get_items_returns_items_in_request_order() -> Result<(), DdbError> {
+        let client = MemoryDb::new().table("relations", "pk", Some("sk"));
+        for sk in &["dataset#1", "dataset#2"] {
+            let mut item = key(sk);
+            set_kv(&mut item, "itemtype".to_string(), "dataset".to_string());
+            put_item(&client, "relations", item).await?;
+        }
+        let requests = vec![
+            ("relations", key("dataset#2"), None),
+            ("relations", key("dataset#3"), None),
+            ("relations", key("dataset#1"), None),
+        ];
+        let items: Vec<Option<Dataset>> = transact_get_items(&client, requests).await?;
+        let sks: Vec<Option<String>> = items.into_iter().map(|d| d.map(|d| d.sk)).collect();
+        assert_eq!(sks, vec![Some("dataset#2".to_string()), None, Some("dataset#1".to_string())]);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn transact_get_items_checks_the_request_count_and_duplicates() {
+        let client = MemoryDb::new().table("relations", "pk", Some("sk"));
+        assert!(transact_get_items_raw(&client, Vec::new()).await.unwrap().is_empty());
+        let requests = vec![("relations", key("dataset#1"), None); 2];
+        let res = transact_get_items_raw(&client, requests).await;
+        assert!(matches!(res, Err(DdbError::TransactGetItems(_))));
+        let requests: Vec<_> =
+            (0..101).map(|i| ("relations", key(&format!("dataset#{}", i)), None)).collect();
+        let res = transact_get_items_raw(&client, requests).await;
+        assert!(matches!(res, Err(DdbError::TransactionTooLarge(101))));
+        let transaction = (0..101).fold(Transaction::new(), |transaction, i| {
+            transaction.delete("relations", key(&format!("dataset#{}", i)))
+        });
+        let res = transaction.send(&client).await;
+        assert!(matches!(res, Err(DdbError::TransactionTooLarge(101))));
+        assert!(client.items("relations").is_empty());
+    }
 }
@@ -0,0 +1,337 @@
+use crate::{to_item, BatchWrite, DdbError, DdbMap};
+use rusoto_core::credential::StaticProvider;
+use rusoto_core::{HttpClient, Region};
+use rusoto_dynamodb::{
+    AttributeDefinition, CreateTableInput, DeleteTableInput, DynamoDb, DynamoDbClient,
+    GlobalSecondaryIndex, KeySchemaElement, Projection,
+};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+use tokio::runtime::Builder;
+
+/// Environment variable holding the DynamoDB Local endpoint, e.g. `http://localhost:8000`
+pub const LOCAL_ENDPOINT_VAR: &str = "DDB_LOCAL_ENDPOINT";
+
+#[derive(Clone, Debug, Deserialize)]
+struct IndexSchema {
+    name: String,
+    hash_key: String,
+    #[serde(default)]
+    range_key: Option<String>,
+}
+
+/// Key schema of a table created by `LocalDynamoDb`
+///
+/// Key attributes are strings unless `attribute_type` says otherwise. Global secondary indexes
+/// project all attributes. In a fixture file the schema is the `table` object, with the same
+/// field names: `name`, `hash_key`, `range_key`, `attribute_types` and `indexes`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TableSchema {
+    name: String,
+    hash_key: String,
+    #[serde(default)]
+    range_key: Option<String>,
+    #[serde(default)]
+    attribute_types: BTreeMap<String, String>,
+    #[serde(default)]
+    indexes: Vec<IndexSchema>,
+}
+
+impl TableSchema {
+    /// `name` is the prefix of the unique table names created from this schema
+    pub fn new(name: &str, hash_key: &str, range_key: Option<&str>) -> TableSchema {
+        TableSchema {
+            name: name.to_string(),
+            hash_key: hash_key.to_string(),
+            range_key: range_key.map(str::to_string),
+            attribute_types: BTreeMap::new(),
+            indexes: Vec::new(),
+        }
+    }
+
+    /// Declares the type, `S`, `N` or `B`, of a key attribute of the table or of an index
+    pub fn attribute_type(mut self, attribute: &str, attr_type: &str) -> Self {
+        self.attribute_types.insert(attribute.to_string(), attr_type.to_string());
+        self
+    }
+
+    pub fn index(mut self, index: &str, hash_key: &str, range_key: Option<&str>) -> Self {
+        self.indexes.push(IndexSchema {
+            name: index.to_string(),
+            hash_key: hash_key.to_string(),
+            range_key: range_key.map(str::to_string),
+        });
+        self
+    }
+
+    fn key_schema(hash_key: &str, range_key: &Option<String>) -> Vec<KeySchemaElement> {
+        let hash = KeySchemaElement {
+            attribute_name: hash_key.to_string(),
+            key_type: "HASH".to_string(),
+        };
+        let range = range_key.iter().map(|range_key| KeySchemaElement {
+            attribute_name: range_key.clone(),
+            key_type: "RANGE".to_string(),
+        });
+        std::iter::once(hash).chain(range).collect()
+    }
+
+    fn into_input(self, table_name: String) -> CreateTableInput {
+        let key_schema = TableSchema::key_schema(&self.hash_key, &self.range_key);
+        let indexes: Vec<GlobalSecondaryIndex> = self
+            .indexes
+            .iter()
+            .map(|index| GlobalSecondaryIndex {
+                index_name: index.name.clone(),
+                key_schema: TableSchema::key_schema(&index.hash_key, &index.range_key),
+                projection: Projection {
+                    projection_type: Some("ALL".to_string()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .collect();
+        let mut attributes: Vec<&String> = key_schema
+            .iter()
+            .chain(indexes.iter().flat_map(|index| &index.key_schema))
+            .map(|key| &key.attribute_name)
+            .collect();
+        attributes.sort();
+        attributes.dedup();
+        let attribute_definitions = attributes
+            .into_iter()
+            .map(|attribute| AttributeDefinition {
+                attribute_name: attribute.clone(),
+                attribute_type: self
+                    .attribute_types
+                    .get(attribute)
+                    .cloned()
+                    .unwrap_or_else(|| "S".to_string()),
+            })
+            .collect();
+        CreateTableInput {
+            table_name,
+            attribute_definitions,
+            key_schema,
+            global_secondary_indexes: if indexes.is_empty() { None } else { Some(indexes) },
+            billing_mode: Some("PAY_PER_REQUEST".to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Fixture {
+    table: TableSchema,
+    #[serde(default)]
+    items: Vec<serde_json::Value>,
+}
+
+/// # DynamoDB Local test harness
+/// Points a `DynamoDbClient` at the DynamoDB Local endpoint in `DDB_LOCAL_ENDPOINT` and
+/// creates tables with a unique name, `<schema name>-<random suffix>`, so tests can run in
+/// parallel against the same endpoint. `fixture` creates a table from a json file with a
+/// `table` schema and plain json `items`, e.g.
+/// `{"table": {"name": "relations", "hash_key": "pk", "range_key": "sk"}, "items": [...]}`.
+/// `teardown` deletes every table that was created, and tables that are left when the harness
+/// is dropped, e.g. because a test returned early or panicked, are deleted on drop. `from_env`
+/// is `None` when the variable is not set, so tests can skip themselves when there is no
+/// endpoint. Only available with the `local` feature.
+/// ```no_run
+/// # use serde::Deserialize;
+/// # use ddb_util::*;
+///
+/// # #[derive(Debug, Deserialize)]
+/// # struct Dataset {
+/// #     pk: String,
+/// #     sk: String,
+/// # }
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// let local = match LocalDynamoDb::from_env()? {
+///     Some(local) => local,
+///     None => return Ok(()),
+/// };
+/// let relations = local.fixture("fixtures/relations.json").await?;
+/// let datasets: Vec<Dataset> = Query::new(&relations)
+///     .key_condition(attr("pk").eq("c4c"))
+///     .send(local.client())
+///     .await?;
+/// let schema = TableSchema::new("counters", "pk", None);
+/// let counters = local.create_table(&schema).await?;
+/// local.teardown().await?;
+/// #     Ok(())
+/// # }
+/// ```
+pub struct LocalDynamoDb {
+    endpoint: String,
+    client: DynamoDbClient,
+    tables: Mutex<Vec<String>>,
+}
+
+impl LocalDynamoDb {
+    /// Harness for the endpoint in `DDB_LOCAL_ENDPOINT`, `None` when it is not set
+    pub fn from_env() -> Result<Option<LocalDynamoDb>, DdbError> {
+        std::env::var(LOCAL_ENDPOINT_VAR)
+            .ok()
+            .filter(|endpoint| !endpoint.is_empty())
+            .map(|endpoint| LocalDynamoDb::new(&endpoint))
+            .transpose()
+    }
+
+    /// Harness for `endpoint`, signing requests with dummy credentials
+    pub fn new(endpoint: &str) -> Result<LocalDynamoDb, DdbError> {
+        Ok(LocalDynamoDb {
+            endpoint: endpoint.to_string(),
+            client: local_client(endpoint)?,
+            tables: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub fn client(&self) -> &DynamoDbClient {
+        &self.client
+    }
+
+    /// Creates a uniquely named table from `schema` and returns its name
+    pub async fn create_table(&self, schema: &TableSchema) -> Result<String, DdbError> {
+        let table = format!("{}-{:08x}", schema.name, rand::random::<u32>());
+        self.client.create_table(schema.clone().into_input(table.clone())).await?;
+        self.tables.lock().unwrap_or_else(|e| e.into_inner()).push(table.clone());
+        Ok(table)
+    }
+
+    /// Writes `items` to `table`, failing when any of them was not written
+    pub async fn seed(
+        &self, table: &str, items: impl IntoIterator<Item = DdbMap>,
+    ) -> Result<(), DdbError> {
+        let res = BatchWrite::new(table).put_all(items).send(&self.client).await?;
+        if let Some(chunk) = res.errors.into_iter().next() {
+            return Err(chunk.error);
+        }
+        match res.unprocessed.len() {
+            0 => Ok(()),
+            n => Err(DdbError::Fixture(format!("{} items were not written to {}", n, table))),
+        }
+    }
+
+    /// Creates the table declared in the fixture file at `path`, seeds it with the items of
+    /// the file and returns its name
+    pub async fn fixture(&self, path: impl AsRef<Path>) -> Result<String, DdbError> {
+        let path = path.as_ref();
+        let invalid = |e: &dyn std::fmt::Display| {
+            DdbError::Fixture(format!("{}: {}", path.display(), e))
+        };
+        let content = std::fs::read_to_string(path).map_err(|e| invalid(&e))?;
+        let fixture: Fixture = serde_json::from_str(&content).map_err(|e| invalid(&e))?;
+        let items = fixture
+            .items
+            .iter()
+            .map(to_item)
+            .collect::<Result<Vec<DdbMap>, _>>()?;
+        let table = self.create_table(&fixture.table).await?;
+        self.seed(&table, items).await?;
+        Ok(table)
+    }
+
+    /// Deletes every table created by this harness
+    ///
+    /// Every table is tried, the first error is returned and the tables that could not be
+    /// deleted are left to the drop of the harness.
+    pub async fn teardown(self) -> Result<(), DdbError> {
+        let tables = std::mem::take(&mut *self.tables.lock().unwrap_or_else(|e| e.into_inner()));
+        let mut error = None;
+        for table in tables {
+            let input = DeleteTableInput {
+                table_name: table.clone(),
+            };
+            if let Err(e) = self.client.delete_table(input).await {
+                self.tables.lock().unwrap_or_else(|e| e.into_inner()).push(table);
+                error.get_or_insert(DdbError::from(e));
+            }
+        }
+        error.map_or(Ok(()), Err)
+    }
+}
+
+impl Drop for LocalDynamoDb {
+    /// Deletes the tables `teardown` did not get to
+    ///
+    /// The runtime of the test may already be shutting down, so the tables are deleted on a
+    /// thread of their own, with a client and runtime of its own.
+    fn drop(&mut self) {
+        let tables = std::mem::take(self.tables.get_mut().unwrap_or_else(|e| e.into_inner()));
+        if tables.is_empty() {
+            return;
+        }
+        let endpoint = self.endpoint.clone();
+        let cleanup = std::thread::spawn(move || {
+            let runtime = Builder::new_current_thread().enable_all().build().ok()?;
+            let client = local_client(&endpoint).ok()?;
+            runtime.block_on(async {
+                for table in tables {
+                    let input = DeleteTableInput {
+                        table_name: table,
+                    };
+                    if let Err(e) = client.delete_table(input).await {
+                        log::warn!("failed to delete a DynamoDB Local table: {}", e);
+                    }
+                }
+            });
+            Some(())
+        });
+        let _ = cleanup.join();
+    }
+}
+
+/// A client for `endpoint`, signing requests with dummy credentials
+fn local_client(endpoint: &str) -> Result<DynamoDbClient, DdbError> {
+    let region = Region::Custom {
+        name: "local".to_string(),
+        endpoint: endpoint.to_string(),
+    };
+    let credentials = StaticProvider::new_minimal("local".to_string(), "local".to_string());
+    let http = HttpClient::new()
+        .map_err(|e| DdbError::Fixture(format!("failed to create the http client: {}", e)))?;
+    Ok(DynamoDbClient::new_with(http, credentials, region))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Fixture, TableSchema};
+    use crate::DdbMap;
+
+    #[test]
+    fn schema_declares_every_key_attribute_once() {
+        let input = TableSchema::new("relations", "pk", Some("sk"))
+            .index("itemtype-index", "itemtype", Some("sk"))
+            .attribute_type("sk", "N")
+            .into_input("relations-1".to_string());
+        let attributes: Vec<(&str, &str)> = input
+            .attribute_definitions
+            .iter()
+            .map(|a| (a.attribute_name.as_str(), a.attribute_type.as_str()))
+            .collect();
+        assert_eq!(attributes, vec![("itemtype", "S"), ("pk", "S"), ("sk", "N")]);
+        assert_eq!(input.key_schema[1].key_type, "RANGE");
+        let indexes = input.global_secondary_indexes.unwrap();
+        assert_eq!(indexes[0].key_schema[0].attribute_name, "itemtype");
+        assert_eq!(indexes[0].projection.projection_type.as_deref(), Some("ALL"));
+    }
+
+    #[test]
+    fn fixtures_declare_a_table_and_items() {
+        for name in ["relations", "relations_archive", "owners"] {
+            let path = format!("{}/fixtures/{}.json", env!("CARGO_MANIFEST_DIR"), name);
+            let fixture: Fixture =
+                serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+            assert_eq!(fixture.table.name, name);
+            for item in &fixture.items {
+                let item: DdbMap = crate::to_item(item).unwrap();
+                assert!(item.contains_key(&fixture.table.hash_key));
+            }
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{DdbError, DdbMap, FromAttributeValue, IntoAttributeValue};
+use rusoto_dynamodb::AttributeValue;
+use std::str::FromStr;
+
+/// # DdbMap builder
+/// Builds an item or a key attribute by attribute, choosing the attribute type of every value
+/// with `IntoAttributeValue`. The `ddb_map!` macro is shorthand for the builder, and `DdbMapExt`
+/// reads typed values back out of raw items.
+/// ```
+/// # use ddb_util::*;
+/// # use std::collections::BTreeSet;
+///
+/// let tags: BTreeSet<String> = vec!["public".to_string()].into_iter().collect();
+/// let item = DdbMapBuilder::new()
+///     .set("pk", "c4c")
+///     .set("sk", "dataset#1")
+///     .set("version", 3)
+///     .set("archived", false)
+///     .set("tags", tags)
+///     .set("deleted", None::<u64>)
+///     .build();
+/// assert_eq!(item, ddb_map! {
+///     "pk" => "c4c",
+///     "sk" => "dataset#1",
+///     "version" => 3,
+///     "archived" => false,
+///     "tags" => vec!["public".to_string()].into_iter().collect::<BTreeSet<_>>(),
+///     "deleted" => None::<u64>,
+/// });
+/// assert_eq!(item.get_n::<i64>("version"), Some(3));
+/// assert_eq!(item.get_bool("archived"), Some(false));
+/// assert_eq!(item.get_s("version"), None);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DdbMapBuilder {
+    map: DdbMap,
+}
+
+impl DdbMapBuilder {
+    pub fn new() -> DdbMapBuilder {
+        DdbMapBuilder::default()
+    }
+
+    /// Sets `name` to `value`, replacing an earlier value
+    pub fn set(mut self, name: &str, value: impl IntoAttributeValue) -> Self {
+        self.map.insert(name.to_string(), value.into_attribute_value());
+        self
+    }
+
+    pub fn build(self) -> DdbMap {
+        self.map
+    }
+}
+
+/// Builds a `DdbMap` from `name => value` pairs, see `DdbMapBuilder`
+#[macro_export]
+macro_rules! ddb_map {
+    ($($name:expr => $value:expr),* $(,)?) => {
+        $crate::DdbMapBuilder::new()$(.set($name, $value))*.build()
+    };
+}
+
+/// Typed access to the attributes of a raw item
+///
+/// Every accessor returns `None` when the attribute is missing or has another type, `get_n`
+/// also when the number does not parse as `T`. `get_as` converts with `FromAttributeValue`
+/// instead and fails with `DdbError::Conversion`, a missing attribute reads as `NULL`.
+pub trait DdbMapExt {
+    fn get_s(&self, name: &str) -> Option<&str>;
+
+    fn get_n<T: FromStr>(&self, name: &str) -> Option<T>;
+
+    fn get_bool(&self, name: &str) -> Option<bool>;
+
+    fn get_list(&self, name: &str) -> Option<&[AttributeValue]>;
+
+    fn get_map(&self, name: &str) -> Option<&DdbMap>;
+
+    fn get_as<T: FromAttributeValue>(&self, name: &str) -> Result<T, DdbError>;
+}
+
+impl DdbMapExt for DdbMap {
+    fn get_s(&self, name: &str) -> Option<&str> {
+        self.get(name)?.s.as_deref()
+    }
+
+    fn get_n<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.get(name)?.n.as_ref()?.parse().ok()
+    }
+
+    fn get_bool(&self, name: &str) -> Option<bool> {
+        self.get(name)?.bool
+    }
+
+    fn get_list(&self, name: &str) -> Option<&[AttributeValue]> {
+        self.get(name)?.l.as_deref()
+    }
+
+    fn get_map(&self, name: &str) -> Option<&DdbMap> {
+        self.get(name)?.m.as_ref()
+    }
+
+    fn get_as<T: FromAttributeValue>(&self, name: &str) -> Result<T, DdbError> {
+        let value = self.get(name).cloned().unwrap_or(AttributeValue {
+            null: Some(true),
+            ..Default::default()
+        });
+        T::from_attribute_value(value).map_err(|e| match e {
+            DdbError::Conversion(msg) => DdbError::Conversion(format!("{}: {}", name, msg)),
+            e => e,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::collections::{BTreeMap, HashSet};
+
+    #[test]
+    fn values_get_the_matching_attribute_type() {
+        let owner: BTreeMap<String, &str> = vec![("name".to_string(), "c4c")].into_iter().collect();
+        let versions: HashSet<u64> = vec![1, 2].into_iter().collect();
+        let item = ddb_map! {
+            "pk" => "c4c",
+            "size" => 1.5,
+            "owner" => owner,
+            "versions" => versions,
+            "history" => vec![ddb_map! { "version" => 1 }, ddb_map! { "version" => 2 }],
+            "checksum" => vec![0u8, 1],
+            "checksums" => vec![vec![0u8]].into_iter().collect::<HashSet<_>>(),
+            "previous" => None::<String>,
+        };
+        assert_eq!(item.get_s("pk"), Some("c4c"));
+        assert_eq!(item.get_n::<f64>("size"), Some(1.5));
+        assert_eq!(item.get_n::<i64>("size"), None);
+        assert_eq!(item.get_map("owner").and_then(|owner| owner.get_s("name")), Some("c4c"));
+        let mut ns = item["versions"].ns.clone().unwrap();
+        ns.sort();
+        assert_eq!(ns, vec!["1", "2"]);
+        let history = item.get_list("history").unwrap();
+        assert_eq!(history[1].m.as_ref().and_then(|m| m.get_n::<u64>("version")), Some(2));
+        assert_eq!(item["checksum"].b.as_deref(), Some(&[0u8, 1][..]));
+        assert_eq!(item["checksums"].bs.as_ref().map(Vec::len), Some(1));
+        assert_eq!(item["previous"].null, Some(true));
+        assert_eq!(item.get_bool("pk"), None);
+    }
+}
@@ -0,0 +1,817 @@
+//! Parsing and evaluation of condition, key condition, projection and update expressions for
+//! `MemoryDb`. Placeholders are resolved while parsing, so the parsed expressions only hold
+//! attribute names and values.
+use crate::DdbMap;
+use rusoto_dynamodb::AttributeValue;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Element {
+    Name(String),
+    Index(usize),
+}
+
+pub(crate) type Path = Vec<Element>;
+
+#[derive(Clone, Debug)]
+pub(crate) enum Operand {
+    Path(Path),
+    Size(Path),
+    Value(Box<AttributeValue>),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Comparator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum Condition {
+    Compare(Operand, Comparator, Operand),
+    Between(Operand, Operand, Operand),
+    In(Operand, Vec<Operand>),
+    Exists(Path),
+    NotExists(Path),
+    Type(Path, Operand),
+    BeginsWith(Operand, Operand),
+    Contains(Operand, Operand),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum SetValue {
+    Operand(Operand),
+    IfNotExists(Path, Box<SetValue>),
+    ListAppend(Box<SetValue>, Box<SetValue>),
+    Plus(Box<SetValue>, Box<SetValue>),
+    Minus(Box<SetValue>, Box<SetValue>),
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum Action {
+    Set(Path, SetValue),
+    Remove(Path),
+    Add(Path, Box<AttributeValue>),
+    Delete(Path, Box<AttributeValue>),
+}
+
+impl Action {
+    fn path(&self) -> &Path {
+        match self {
+            Action::Set(path, _)
+            | Action::Remove(path)
+            | Action::Add(path, _)
+            | Action::Delete(path, _) => path,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Name(String),
+    Value(String),
+    Number(usize),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 14] =
+    ["<>", "<=", ">=", "=", "<", ">", "(", ")", ",", ".", "[", "]", "+", "-"];
+
+fn tokenize(exp: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = exp.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let word = |start: usize| {
+            let end = (start..chars.len())
+                .find(|&j| !(chars[j].is_alphanumeric() || chars[j] == '_'))
+                .unwrap_or(chars.len());
+            (chars[start..end].iter().collect::<String>(), end)
+        };
+        if c.is_alphabetic() || c == '_' {
+            let (w, end) = word(i);
+            tokens.push(Token::Ident(w));
+            i = end;
+        } else if c == '#' || c == ':' {
+            let (w, end) = word(i + 1);
+            let placeholder = format!("{}{}", c, w);
+            tokens.push(if c == '#' {
+                Token::Name(placeholder)
+            } else {
+                Token::Value(placeholder)
+            });
+            i = end;
+        } else if c.is_ascii_digit() {
+            let end = (i..chars.len()).find(|&j| !chars[j].is_ascii_digit()).unwrap_or(chars.len());
+            let n: String = chars[i..end].iter().collect();
+            tokens.push(Token::Number(n.parse().map_err(|_| format!("invalid index {}", n))?));
+            i = end;
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let symbol = SYMBOLS
+                .iter()
+                .find(|s| rest.starts_with(**s))
+                .ok_or_else(|| format!("invalid character '{}' in expression {}", c, exp))?;
+            tokens.push(Token::Symbol(symbol));
+            i += symbol.len();
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    names: &'a HashMap<String, String>,
+    values: &'a DdbMap,
+}
+
+impl<'a> Parser<'a> {
+    fn new(
+        exp: &str, names: &'a HashMap<String, String>, values: &'a DdbMap,
+    ) -> Result<Self, String> {
+        Ok(Parser { tokens: tokenize(exp)?, pos: 0, names, values })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), String> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(format!("expected '{}' at {:?}", symbol, self.peek()))
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Ident(w)) if w.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// True when the next tokens are `function(`
+    fn at_function(&self, function: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(w)) if w == function)
+            && self.tokens.get(self.pos + 1) == Some(&Token::Symbol("("))
+    }
+
+    fn value(&mut self) -> Result<AttributeValue, String> {
+        match self.next() {
+            Some(Token::Value(placeholder)) => self
+                .values
+                .get(&placeholder)
+                .cloned()
+                .ok_or_else(|| format!("undefined expression attribute value {}", placeholder)),
+            token => Err(format!("expected a value placeholder at {:?}", token)),
+        }
+    }
+
+    fn segment(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Ident(name)) => Ok(name),
+            Some(Token::Name(placeholder)) => self
+                .names
+                .get(&placeholder)
+                .cloned()
+                .ok_or_else(|| format!("undefined expression attribute name {}", placeholder)),
+            token => Err(format!("expected an attribute name at {:?}", token)),
+        }
+    }
+
+    fn path(&mut self) -> Result<Path, String> {
+        let mut path = vec![Element::Name(self.segment()?)];
+        loop {
+            if self.eat_symbol(".") {
+                path.push(Element::Name(self.segment()?));
+            } else if self.eat_symbol("[") {
+                match self.next() {
+                    Some(Token::Number(i)) => path.push(Element::Index(i)),
+                    token => return Err(format!("expected a list index at {:?}", token)),
+                }
+                self.expect_symbol("]")?;
+            } else {
+                return Ok(path);
+            }
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        if let Some(Token::Value(_)) = self.peek() {
+            return Ok(Operand::Value(Box::new(self.value()?)));
+        }
+        if self.at_function("size") {
+            self.pos += 2;
+            let path = self.path()?;
+            self.expect_symbol(")")?;
+            return Ok(Operand::Size(path));
+        }
+        Ok(Operand::Path(self.path()?))
+    }
+
+    fn condition(&mut self) -> Result<Condition, String> {
+        let mut cond = self.and()?;
+        while self.eat_keyword("OR") {
+            cond = Condition::Or(Box::new(cond), Box::new(self.and()?));
+        }
+        Ok(cond)
+    }
+
+    fn and(&mut self) -> Result<Condition, String> {
+        let mut cond = self.not()?;
+        while self.eat_keyword("AND") {
+            cond = Condition::And(Box::new(cond), Box::new(self.not()?));
+        }
+        Ok(cond)
+    }
+
+    fn not(&mut self) -> Result<Condition, String> {
+        if self.eat_keyword("NOT") {
+            return Ok(Condition::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Condition, String> {
+        if self.eat_symbol("(") {
+            let cond = self.condition()?;
+            self.expect_symbol(")")?;
+            return Ok(cond);
+        }
+        for function in [
+            "attribute_exists",
+            "attribute_not_exists",
+            "attribute_type",
+            "begins_with",
+            "contains",
+        ] {
+            if self.at_function(function) {
+                self.pos += 2;
+                let cond = match function {
+                    "attribute_exists" => Condition::Exists(self.path()?),
+                    "attribute_not_exists" => Condition::NotExists(self.path()?),
+                    "attribute_type" => {
+                        let path = self.path()?;
+                        self.expect_symbol(",")?;
+                        Condition::Type(path, self.operand()?)
+                    }
+                    _ => {
+                        let l = self.operand()?;
+                        self.expect_symbol(",")?;
+                        let r = self.operand()?;
+                        if function == "begins_with" {
+                            Condition::BeginsWith(l, r)
+                        } else {
+                            Condition::Contains(l, r)
+                        }
+                    }
+                };
+                self.expect_symbol(")")?;
+                return Ok(cond);
+            }
+        }
+        let l = self.operand()?;
+        if self.eat_keyword("BETWEEN") {
+            let lo = self.operand()?;
+            if !self.eat_keyword("AND") {
+                return Err("expected AND in BETWEEN".to_string());
+            }
+            return Ok(Condition::Between(l, lo, self.operand()?));
+        }
+        if self.eat_keyword("IN") {
+            self.expect_symbol("(")?;
+            let mut list = vec![self.operand()?];
+            while self.eat_symbol(",") {
+                list.push(self.operand()?);
+            }
+            self.expect_symbol(")")?;
+            return Ok(Condition::In(l, list));
+        }
+        let comparator = match self.next() {
+            Some(Token::Symbol("=")) => Comparator::Eq,
+            Some(Token::Symbol("<>")) => Comparator::Ne,
+            Some(Token::Symbol("<")) => Comparator::Lt,
+            Some(Token::Symbol("<=")) => Comparator::Le,
+            Some(Token::Symbol(">")) => Comparator::Gt,
+            Some(Token::Symbol(">=")) => Comparator::Ge,
+            token => return Err(format!("expected a comparator at {:?}", token)),
+        };
+        Ok(Condition::Compare(l, comparator, self.operand()?))
+    }
+
+    fn set_value(&mut self) -> Result<SetValue, String> {
+        let l = self.set_term()?;
+        if self.eat_symbol("+") {
+            return Ok(SetValue::Plus(Box::new(l), Box::new(self.set_term()?)));
+        }
+        if self.eat_symbol("-") {
+            return Ok(SetValue::Minus(Box::new(l), Box::new(self.set_term()?)));
+        }
+        Ok(l)
+    }
+
+    fn set_term(&mut self) -> Result<SetValue, String> {
+        if self.at_function("if_not_exists") {
+            self.pos += 2;
+            let path = self.path()?;
+            self.expect_symbol(",")?;
+            let value = self.set_value()?;
+            self.expect_symbol(")")?;
+            return Ok(SetValue::IfNotExists(path, Box::new(value)));
+        }
+        if self.at_function("list_append") {
+            self.pos += 2;
+            let l = self.set_value()?;
+            self.expect_symbol(",")?;
+            let r = self.set_value()?;
+            self.expect_symbol(")")?;
+            return Ok(SetValue::ListAppend(Box::new(l), Box::new(r)));
+        }
+        Ok(SetValue::Operand(self.operand()?))
+    }
+
+    fn actions(&mut self) -> Result<Vec<Action>, String> {
+        let mut actions = Vec::new();
+        while !self.at_end() {
+            let clause = match self.next() {
+                Some(Token::Ident(w)) => w.to_ascii_uppercase(),
+                token => return Err(format!("expected SET, REMOVE, ADD or DELETE at {:?}", token)),
+            };
+            loop {
+                let path = self.path()?;
+                actions.push(match clause.as_str() {
+                    "SET" => {
+                        self.expect_symbol("=")?;
+                        Action::Set(path, self.set_value()?)
+                    }
+                    "REMOVE" => Action::Remove(path),
+                    "ADD" => Action::Add(path, Box::new(self.value()?)),
+                    "DELETE" => Action::Delete(path, Box::new(self.value()?)),
+                    other => return Err(format!("unknown update clause {}", other)),
+                });
+                if !self.eat_symbol(",") {
+                    break;
+                }
+            }
+        }
+        Ok(actions)
+    }
+
+    fn finish<T>(self, parsed: T) -> Result<T, String> {
+        match self.peek() {
+            None => Ok(parsed),
+            Some(token) => Err(format!("unexpected {:?} at the end of the expression", token)),
+        }
+    }
+}
+
+pub(crate) fn parse_condition(
+    exp: &str, names: &HashMap<String, String>, values: &DdbMap,
+) -> Result<Condition, String> {
+    let mut parser = Parser::new(exp, names, values)?;
+    let cond = parser.condition()?;
+    parser.finish(cond)
+}
+
+pub(crate) fn parse_projection(
+    exp: &str, names: &HashMap<String, String>,
+) -> Result<Vec<Path>, String> {
+    let values = DdbMap::new();
+    let mut parser = Parser::new(exp, names, &values)?;
+    let mut paths = vec![parser.path()?];
+    while parser.eat_symbol(",") {
+        paths.push(parser.path()?);
+    }
+    parser.finish(paths)
+}
+
+pub(crate) fn parse_update(
+    exp: &str, names: &HashMap<String, String>, values: &DdbMap,
+) -> Result<Vec<Action>, String> {
+    let mut parser = Parser::new(exp, names, values)?;
+    let actions = parser.actions()?;
+    parser.finish(actions)
+}
+
+pub(crate) fn resolve<'i>(item: &'i DdbMap, path: &[Element]) -> Option<&'i AttributeValue> {
+    let (first, rest) = path.split_first()?;
+    let mut value = match first {
+        Element::Name(name) => item.get(name)?,
+        Element::Index(_) => return None,
+    };
+    for element in rest {
+        value = match element {
+            Element::Name(name) => value.m.as_ref()?.get(name)?,
+            Element::Index(i) => value.l.as_ref()?.get(*i)?,
+        };
+    }
+    Some(value)
+}
+
+fn number(v: &AttributeValue) -> Option<f64> {
+    v.n.as_ref()?.parse().ok()
+}
+
+fn n(v: impl ToString) -> AttributeValue {
+    AttributeValue { n: Some(v.to_string()), ..Default::default() }
+}
+
+fn size(v: &AttributeValue) -> Option<usize> {
+    v.s.as_ref()
+        .map(String::len)
+        .or_else(|| v.b.as_ref().map(|b| b.len()))
+        .or_else(|| v.ss.as_ref().map(Vec::len))
+        .or_else(|| v.ns.as_ref().map(Vec::len))
+        .or_else(|| v.bs.as_ref().map(Vec::len))
+        .or_else(|| v.l.as_ref().map(Vec::len))
+        .or_else(|| v.m.as_ref().map(HashMap::len))
+}
+
+fn type_name(v: &AttributeValue) -> &'static str {
+    match v {
+        v if v.s.is_some() => "S",
+        v if v.n.is_some() => "N",
+        v if v.b.is_some() => "B",
+        v if v.bool.is_some() => "BOOL",
+        v if v.null.is_some() => "NULL",
+        v if v.ss.is_some() => "SS",
+        v if v.ns.is_some() => "NS",
+        v if v.bs.is_some() => "BS",
+        v if v.l.is_some() => "L",
+        _ => "M",
+    }
+}
+
+/// Order of two scalar values of the same type, `None` for anything else
+pub(crate) fn compare(a: &AttributeValue, b: &AttributeValue) -> Option<Ordering> {
+    if let (Some(x), Some(y)) = (number(a), number(b)) {
+        return x.partial_cmp(&y);
+    }
+    if let (Some(x), Some(y)) = (&a.s, &b.s) {
+        return Some(x.cmp(y));
+    }
+    if let (Some(x), Some(y)) = (&a.b, &b.b) {
+        return Some(x.as_ref().cmp(y.as_ref()));
+    }
+    None
+}
+
+fn set(values: &Option<Vec<String>>) -> Option<HashSet<&String>> {
+    values.as_ref().map(|v| v.iter().collect())
+}
+
+fn equal(a: &AttributeValue, b: &AttributeValue) -> bool {
+    match compare(a, b) {
+        Some(ordering) => ordering == Ordering::Equal,
+        None => {
+            if a.ss.is_some() || a.ns.is_some() {
+                return set(&a.ss) == set(&b.ss) && set(&a.ns) == set(&b.ns);
+            }
+            a == b
+        }
+    }
+}
+
+fn evaluate(item: &DdbMap, operand: &Operand) -> Option<AttributeValue> {
+    match operand {
+        Operand::Path(path) => resolve(item, path).cloned(),
+        Operand::Size(path) => resolve(item, path).and_then(size).map(n),
+        Operand::Value(value) => Some(*value.clone()),
+    }
+}
+
+impl Condition {
+    pub(crate) fn matches(&self, item: &DdbMap) -> bool {
+        let eval = |operand| evaluate(item, operand);
+        match self {
+            Condition::Compare(l, comparator, r) => match (eval(l), eval(r)) {
+                (Some(l), Some(r)) => match comparator {
+                    Comparator::Eq => equal(&l, &r),
+                    Comparator::Ne => !equal(&l, &r),
+                    Comparator::Lt => compare(&l, &r) == Some(Ordering::Less),
+                    Comparator::Le => matches!(compare(&l, &r), Some(o) if o != Ordering::Greater),
+                    Comparator::Gt => compare(&l, &r) == Some(Ordering::Greater),
+                    Comparator::Ge => matches!(compare(&l, &r), Some(o) if o != Ordering::Less),
+                },
+                (None, Some(_)) => matches!(comparator, Comparator::Ne),
+                _ => false,
+            },
+            Condition::Between(v, lo, hi) => match (eval(v), eval(lo), eval(hi)) {
+                (Some(v), Some(lo), Some(hi)) => {
+                    matches!(compare(&v, &lo), Some(o) if o != Ordering::Less)
+                        && matches!(compare(&v, &hi), Some(o) if o != Ordering::Greater)
+                }
+                _ => false,
+            },
+            Condition::In(v, list) => match eval(v) {
+                Some(v) => list.iter().filter_map(eval).any(|o| equal(&v, &o)),
+                None => false,
+            },
+            Condition::Exists(path) => resolve(item, path).is_some(),
+            Condition::NotExists(path) => resolve(item, path).is_none(),
+            Condition::Type(path, t) => match (resolve(item, path), eval(t)) {
+                (Some(v), Some(t)) => t.s.as_deref() == Some(type_name(v)),
+                _ => false,
+            },
+            Condition::BeginsWith(v, prefix) => match (eval(v), eval(prefix)) {
+                (Some(v), Some(prefix)) => match (&v.s, &prefix.s, &v.b, &prefix.b) {
+                    (Some(s), Some(p), _, _) => s.starts_with(p.as_str()),
+                    (_, _, Some(b), Some(p)) => b.starts_with(p),
+                    _ => false,
+                },
+                _ => false,
+            },
+            Condition::Contains(v, operand) => match (eval(v), eval(operand)) {
+                (Some(v), Some(o)) => {
+                    if let (Some(s), Some(sub)) = (&v.s, &o.s) {
+                        s.contains(sub.as_str())
+                    } else if let (Some(set), Some(member)) = (&v.ss, &o.s) {
+                        set.contains(member)
+                    } else if let (Some(set), Some(_)) = (&v.ns, &o.n) {
+                        set.iter().any(|m| equal(&n(m), &o))
+                    } else if let (Some(set), Some(member)) = (&v.bs, &o.b) {
+                        set.contains(member)
+                    } else if let Some(list) = &v.l {
+                        list.iter().any(|e| equal(e, &o))
+                    } else {
+                        false
+                    }
+                }
+                _ => false,
+            },
+            Condition::And(l, r) => l.matches(item) && r.matches(item),
+            Condition::Or(l, r) => l.matches(item) || r.matches(item),
+            Condition::Not(c) => !c.matches(item),
+        }
+    }
+}
+
+fn arithmetic(l: &AttributeValue, r: &AttributeValue, sign: i8) -> Result<AttributeValue, String> {
+    let (ls, rs) = match (&l.n, &r.n) {
+        (Some(ls), Some(rs)) => (ls, rs),
+        _ => return Err("an operand in the update expression has an incorrect data type".into()),
+    };
+    if let (Ok(x), Ok(y)) = (ls.parse::<i128>(), rs.parse::<i128>()) {
+        return Ok(n(x + i128::from(sign) * y));
+    }
+    match (ls.parse::<f64>(), rs.parse::<f64>()) {
+        (Ok(x), Ok(y)) => Ok(n(x + f64::from(sign) * y)),
+        _ => Err(format!("invalid numbers {} and {}", ls, rs)),
+    }
+}
+
+fn set_value(item: &DdbMap, value: &SetValue) -> Result<AttributeValue, String> {
+    match value {
+        SetValue::Operand(operand) => evaluate(item, operand).ok_or_else(|| {
+            "the provided expression refers to an attribute that does not exist".into()
+        }),
+        SetValue::IfNotExists(path, default) => match resolve(item, path) {
+            Some(v) => Ok(v.clone()),
+            None => set_value(item, default),
+        },
+        SetValue::ListAppend(l, r) => {
+            let (l, r) = (set_value(item, l)?, set_value(item, r)?);
+            match (l.l, r.l) {
+                (Some(mut l), Some(r)) => {
+                    l.extend(r);
+                    Ok(AttributeValue { l: Some(l), ..Default::default() })
+                }
+                _ => Err("list_append needs two lists".to_string()),
+            }
+        }
+        SetValue::Plus(l, r) => arithmetic(&set_value(item, l)?, &set_value(item, r)?, 1),
+        SetValue::Minus(l, r) => arithmetic(&set_value(item, l)?, &set_value(item, r)?, -1),
+    }
+}
+
+/// The value at `path` for writing, `None` when a parent of it does not exist
+fn resolve_mut<'i>(item: &'i mut DdbMap, path: &[Element]) -> Option<&'i mut AttributeValue> {
+    let (first, rest) = path.split_first()?;
+    let mut value = match first {
+        Element::Name(name) => item.get_mut(name)?,
+        Element::Index(_) => return None,
+    };
+    for element in rest {
+        value = match element {
+            Element::Name(name) => value.m.as_mut()?.get_mut(name)?,
+            Element::Index(i) => value.l.as_mut()?.get_mut(*i)?,
+        };
+    }
+    Some(value)
+}
+
+fn write(item: &mut DdbMap, path: &[Element], value: AttributeValue) -> Result<(), String> {
+    let invalid = || "the document path provided in the update expression is invalid".to_string();
+    let (last, parent) = path.split_last().ok_or_else(invalid)?;
+    if parent.is_empty() {
+        return match last {
+            Element::Name(name) => {
+                item.insert(name.clone(), value);
+                Ok(())
+            }
+            Element::Index(_) => Err(invalid()),
+        };
+    }
+    let parent = resolve_mut(item, parent).ok_or_else(invalid)?;
+    match (last, parent) {
+        (Element::Name(name), AttributeValue { m: Some(m), .. }) => {
+            m.insert(name.clone(), value);
+        }
+        (Element::Index(i), AttributeValue { l: Some(l), .. }) => match l.get_mut(*i) {
+            Some(element) => *element = value,
+            None => l.push(value),
+        },
+        _ => return Err(invalid()),
+    }
+    Ok(())
+}
+
+fn remove(item: &mut DdbMap, path: &[Element]) {
+    match path.split_last() {
+        Some((Element::Name(name), [])) => {
+            item.remove(name);
+        }
+        Some((last, parent)) => match (last, resolve_mut(item, parent)) {
+            (Element::Name(name), Some(AttributeValue { m: Some(m), .. })) => {
+                m.remove(name);
+            }
+            (Element::Index(i), Some(AttributeValue { l: Some(l), .. })) if *i < l.len() => {
+                l.remove(*i);
+            }
+            _ => {}
+        },
+        None => {}
+    }
+}
+
+fn set_union(set: &mut Option<Vec<String>>, other: &Option<Vec<String>>) -> bool {
+    match (set.as_mut(), other) {
+        (Some(set), Some(other)) => {
+            for member in other {
+                if !set.contains(member) {
+                    set.push(member.clone());
+                }
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Applies the update actions to `item`, every value is computed from the item as it was
+/// before the update
+pub(crate) fn apply(item: &mut DdbMap, actions: &[Action]) -> Result<(), String> {
+    let before = item.clone();
+    for action in actions {
+        match action {
+            Action::Set(path, value) => write(item, path, set_value(&before, value)?)?,
+            Action::Remove(path) => remove(item, path),
+            Action::Add(path, value) => match resolve(item, path).cloned() {
+                None => write(item, path, *value.clone())?,
+                Some(current) if current.n.is_some() => {
+                    write(item, path, arithmetic(&current, value, 1)?)?
+                }
+                Some(mut current) => {
+                    let merged = set_union(&mut current.ss, &value.ss)
+                        || set_union(&mut current.ns, &value.ns);
+                    if let (Some(set), Some(other)) = (current.bs.as_mut(), &value.bs) {
+                        set.extend(
+                            other.iter().filter(|b| !set.contains(b)).cloned().collect::<Vec<_>>(),
+                        );
+                    } else if !merged {
+                        return Err("ADD needs a number or a set".to_string());
+                    }
+                    write(item, path, current)?
+                }
+            },
+            Action::Delete(path, value) => {
+                if let Some(mut current) = resolve(item, path).cloned() {
+                    if let (Some(set), Some(other)) = (current.ss.as_mut(), &value.ss) {
+                        set.retain(|m| !other.contains(m));
+                    }
+                    if let (Some(set), Some(other)) = (current.ns.as_mut(), &value.ns) {
+                        set.retain(|m| !other.contains(m));
+                    }
+                    if let (Some(set), Some(other)) = (current.bs.as_mut(), &value.bs) {
+                        set.retain(|m| !other.contains(m));
+                    }
+                    if size(&current) == Some(0) {
+                        remove(item, path);
+                    } else {
+                        write(item, path, current)?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Names of the top level attributes changed by `actions`
+pub(crate) fn updated_attributes(actions: &[Action]) -> HashSet<String> {
+    actions
+        .iter()
+        .filter_map(|action| match action.path().first() {
+            Some(Element::Name(name)) => Some(name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Copies the attributes at `paths` out of `item`, keeping their nesting
+///
+/// Paths into the same list element are merged into one element, and the projected elements of
+/// a list keep the order of their indexes, e.g. `a[2].x, a[0], a[2].y` gives `[a[0], {x, y}]`.
+pub(crate) fn project(item: &DdbMap, paths: &[Path]) -> DdbMap {
+    let mut projected = Projection::Map(HashMap::new());
+    for path in paths {
+        if let Some(value) = resolve(item, path) {
+            projected.insert(path, value.clone());
+        }
+    }
+    projected.into_value().m.unwrap_or_default()
+}
+
+/// A projected value, with the list elements keyed by their index until every path is copied
+enum Projection {
+    Value(AttributeValue),
+    Map(HashMap<String, Projection>),
+    List(BTreeMap<usize, Projection>),
+}
+
+impl Projection {
+    fn new(element: Option<&Element>) -> Projection {
+        match element {
+            Some(Element::Index(_)) => Projection::List(BTreeMap::new()),
+            _ => Projection::Map(HashMap::new()),
+        }
+    }
+
+    fn insert(&mut self, path: &[Element], value: AttributeValue) {
+        let (element, rest) = match path.split_first() {
+            Some(split) => split,
+            None => {
+                *self = Projection::Value(value);
+                return;
+            }
+        };
+        let next = Projection::new(rest.first());
+        let child = match (self, element) {
+            (Projection::Map(map), Element::Name(name)) => map.entry(name.clone()).or_insert(next),
+            (Projection::List(list), Element::Index(i)) => list.entry(*i).or_insert(next),
+            _ => return,
+        };
+        child.insert(rest, value);
+    }
+
+    fn into_value(self) -> AttributeValue {
+        match self {
+            Projection::Value(value) => value,
+            Projection::Map(map) => AttributeValue {
+                m: Some(map.into_iter().map(|(name, p)| (name, p.into_value())).collect()),
+                ..Default::default()
+            },
+            Projection::List(list) => AttributeValue {
+                l: Some(list.into_values().map(Projection::into_value).collect()),
+                ..Default::default()
+            },
+        }
+    }
+}
@@ -0,0 +1,1019 @@
+use crate::{DdbClient, DdbMap};
+use async_trait::async_trait;
+use expression::{
+    apply, compare, parse_condition, parse_projection, parse_update, project, updated_attributes,
+    Action,
+};
+use rusoto_core::RusotoError;
+use rusoto_dynamodb::{
+    AttributeDefinition, BatchGetItemError, BatchGetItemInput, BatchGetItemOutput,
+    BatchWriteItemError, BatchWriteItemInput, BatchWriteItemOutput, DeleteItemError,
+    DeleteItemInput, DeleteItemOutput, DescribeTableError, DescribeTableInput,
+    DescribeTableOutput, GetItemError, GetItemInput, GetItemOutput, ItemResponse,
+    KeySchemaElement, PutItemError, PutItemInput, PutItemOutput, QueryError, QueryInput,
+    QueryOutput, ScanError, ScanInput, ScanOutput, TableDescription, TransactGetItemsError,
+    TransactGetItemsInput, TransactGetItemsOutput, TransactWriteItemsError,
+    TransactWriteItemsInput, TransactWriteItemsOutput, UpdateItemError, UpdateItemInput,
+    UpdateItemOutput,
+};
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+mod expression;
+#[cfg(test)]
+pub(crate) mod probe;
+
+/// # In-memory DynamoDB
+/// A `DdbClient` that keeps its tables in memory, for tests that should run without AWS.
+/// Tables have a hash key and an optional range key and can have global secondary indexes,
+/// which project all attributes. Condition, key condition, filter, projection and update
+/// expressions are evaluated, queries return items in range key order and `Limit` pages are
+/// followed with `LastEvaluatedKey` like on DynamoDB. Batch writes never leave items
+/// unprocessed and write nothing when one of their requests is invalid, and transactions are
+/// applied atomically or canceled with one reason per operation. Clones share the same tables.
+/// ```
+/// # use serde::{Deserialize, Serialize};
+/// # use ddb_util::*;
+///
+/// # #[derive(Debug, Deserialize, Serialize, PartialEq)]
+/// # struct Dataset {
+/// #     pk: String,
+/// #     sk: String,
+/// #     itemtype: String,
+/// # }
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// let client = MemoryDb::new()
+///     .table("relations", "pk", Some("sk"))
+///     .index("relations", "itemtype-index", "itemtype", None);
+/// let dataset = Dataset {
+///     pk: "c4c".to_string(),
+///     sk: "dataset#1".to_string(),
+///     itemtype: "dataset".to_string(),
+/// };
+/// put_typed_item(&client, "relations", &dataset).await?;
+/// let datasets: Vec<Dataset> = Query::new("relations")
+///     .index("itemtype-index")
+///     .key_condition(attr("itemtype").eq("dataset"))
+///     .send(&client)
+///     .await?;
+/// assert_eq!(datasets, vec![dataset]);
+/// #     Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MemoryDb {
+    tables: Arc<Mutex<HashMap<String, Table>>>,
+}
+
+#[derive(Clone, Debug)]
+struct KeySchema {
+    hash: String,
+    range: Option<String>,
+}
+
+impl KeySchema {
+    fn names(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.hash).chain(self.range.as_ref())
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Table {
+    key: KeySchema,
+    indexes: HashMap<String, KeySchema>,
+    attribute_types: HashMap<String, String>,
+    items: Vec<DdbMap>,
+}
+
+enum Failure {
+    Validation(String),
+    ResourceNotFound(String),
+    ConditionalCheckFailed,
+    TransactionCanceled(Vec<&'static str>),
+}
+
+type Outcome<T> = Result<T, Failure>;
+
+fn invalid<T>(message: impl ToString) -> Outcome<T> {
+    Err(Failure::Validation(message.to_string()))
+}
+
+/// The rusoto error variants a failure maps to for one operation
+trait ServiceError: Sized {
+    fn resource_not_found(message: String) -> Self;
+
+    fn conditional_check_failed(_message: String) -> Option<Self> {
+        None
+    }
+
+    fn transaction_canceled(_message: String) -> Option<Self> {
+        None
+    }
+}
+
+macro_rules! service_error {
+    ($error:ident $(, conditional: $conditional:ident)? $(, canceled: $canceled:ident)?) => {
+        impl ServiceError for $error {
+            fn resource_not_found(message: String) -> Self {
+                $error::ResourceNotFound(message)
+            }
+            $(
+                fn conditional_check_failed(message: String) -> Option<Self> {
+                    Some($error::$conditional(message))
+                }
+            )?
+            $(
+                fn transaction_canceled(message: String) -> Option<Self> {
+                    Some($error::$canceled(message))
+                }
+            )?
+        }
+    };
+}
+
+service_error!(GetItemError);
+service_error!(PutItemError, conditional: ConditionalCheckFailed);
+service_error!(UpdateItemError, conditional: ConditionalCheckFailed);
+service_error!(DeleteItemError, conditional: ConditionalCheckFailed);
+service_error!(QueryError);
+service_error!(ScanError);
+service_error!(BatchGetItemError);
+service_error!(BatchWriteItemError);
+service_error!(TransactGetItemsError, canceled: TransactionCanceled);
+service_error!(TransactWriteItemsError, canceled: TransactionCanceled);
+service_error!(DescribeTableError);
+
+fn into_rusoto<E: ServiceError>(failure: Failure) -> RusotoError<E> {
+    let service = match failure {
+        Failure::Validation(message) => return RusotoError::Validation(message),
+        Failure::ResourceNotFound(table) => {
+            Some(E::resource_not_found(format!("Requested resource not found: {}", table)))
+        }
+        Failure::ConditionalCheckFailed => {
+            E::conditional_check_failed("The conditional request failed".to_string())
+        }
+        Failure::TransactionCanceled(reasons) => E::transaction_canceled(format!(
+            "Transaction cancelled, please refer cancellation reasons for specific reasons [{}]",
+            reasons.join(", ")
+        )),
+    };
+    match service {
+        Some(e) => RusotoError::Service(e),
+        None => RusotoError::Validation("unexpected failure".to_string()),
+    }
+}
+
+fn empty(map: &Option<DdbMap>) -> DdbMap {
+    map.clone().unwrap_or_default()
+}
+
+/// Parses an optional condition and checks it against `item`
+fn check(
+    condition: &Option<String>, names: &Option<HashMap<String, String>>, values: &Option<DdbMap>,
+    item: Option<&DdbMap>,
+) -> Outcome<bool> {
+    let condition = match condition {
+        Some(condition) => condition,
+        None => return Ok(true),
+    };
+    let names = names.clone().unwrap_or_default();
+    let cond = parse_condition(condition, &names, &empty(values)).map_err(Failure::Validation)?;
+    Ok(cond.matches(item.unwrap_or(&DdbMap::new())))
+}
+
+fn actions(
+    update: &Option<String>, names: &Option<HashMap<String, String>>, values: &Option<DdbMap>,
+) -> Outcome<Vec<Action>> {
+    match update {
+        Some(update) => {
+            let names = names.clone().unwrap_or_default();
+            parse_update(update, &names, &empty(values)).map_err(Failure::Validation)
+        }
+        None => Ok(Vec::new()),
+    }
+}
+
+fn projection(
+    item: DdbMap, projection: &Option<String>, names: &Option<HashMap<String, String>>,
+) -> Outcome<DdbMap> {
+    match projection {
+        Some(projection) => {
+            let names = names.clone().unwrap_or_default();
+            let paths = parse_projection(projection, &names).map_err(Failure::Validation)?;
+            Ok(project(&item, &paths))
+        }
+        None => Ok(item),
+    }
+}
+
+fn cmp_by(a: &DdbMap, b: &DdbMap, names: &[&String]) -> Ordering {
+    for name in names {
+        let ordering = match (a.get(*name), b.get(*name)) {
+            (Some(x), Some(y)) => compare(x, y).unwrap_or(Ordering::Equal),
+            (x, y) => x.is_some().cmp(&y.is_some()),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+fn segment_of(item: &DdbMap, hash: &str, total_segments: i64) -> i64 {
+    let mut hasher = DefaultHasher::new();
+    format!("{:?}", item.get(hash)).hash(&mut hasher);
+    (hasher.finish() % total_segments.max(1) as u64) as i64
+}
+
+/// What one page of a query or scan read from a table
+struct Page {
+    items: Vec<DdbMap>,
+    count: i64,
+    scanned_count: i64,
+    last_evaluated_key: Option<DdbMap>,
+}
+
+impl Table {
+    fn new(hash_key: &str, range_key: Option<&str>) -> Table {
+        Table {
+            key: KeySchema { hash: hash_key.to_string(), range: range_key.map(str::to_string) },
+            indexes: HashMap::new(),
+            attribute_types: HashMap::new(),
+            items: Vec::new(),
+        }
+    }
+
+    /// The key attributes of `item`, failing when one of them is missing
+    fn key_of(&self, item: &DdbMap) -> Outcome<DdbMap> {
+        self.key
+            .names()
+            .map(|name| match item.get(name) {
+                Some(value) => Ok((name.clone(), value.clone())),
+                None => invalid(format!("missing key attribute {}", name)),
+            })
+            .collect()
+    }
+
+    /// Validates a key that has to consist of exactly the key attributes
+    fn check_key(&self, key: &DdbMap) -> Outcome<DdbMap> {
+        let key_of = self.key_of(key)?;
+        if key_of.len() != key.len() {
+            return invalid("the provided key element does not match the schema");
+        }
+        Ok(key_of)
+    }
+
+    fn position(&self, key: &DdbMap) -> Option<usize> {
+        self.items
+            .iter()
+            .position(|item| self.key.names().all(|name| item.get(name) == key.get(name)))
+    }
+
+    fn get(&self, key: &DdbMap) -> Option<&DdbMap> {
+        self.position(key).map(|i| &self.items[i])
+    }
+
+    fn put(&mut self, item: DdbMap) -> Option<DdbMap> {
+        match self.position(&item) {
+            Some(i) => Some(std::mem::replace(&mut self.items[i], item)),
+            None => {
+                self.items.push(item);
+                None
+            }
+        }
+    }
+
+    /// Applies `actions` to the item with `key`, creating it when it does not exist
+    fn update(&mut self, key: DdbMap, actions: &[Action]) -> Outcome<DdbMap> {
+        let mut new = self.get(&key).cloned().unwrap_or_else(|| key.clone());
+        apply(&mut new, actions).map_err(Failure::Validation)?;
+        if self.key_of(&new)? != key {
+            return invalid("cannot update attribute that is part of the key");
+        }
+        self.put(new.clone());
+        Ok(new)
+    }
+
+    fn delete(&mut self, key: &DdbMap) -> Option<DdbMap> {
+        self.position(key).map(|i| self.items.remove(i))
+    }
+
+    /// Items of the table or of one of its indexes, in key order, with the key attribute
+    /// names used for ordering and for `LastEvaluatedKey`
+    fn view(&self, index: &Option<String>) -> Outcome<(Vec<&DdbMap>, Vec<&String>)> {
+        let mut names: Vec<&String> = Vec::new();
+        if let Some(index) = index {
+            let schema = match self.indexes.get(index) {
+                Some(schema) => schema,
+                None => return invalid(format!("the table does not have the index {}", index)),
+            };
+            names.extend(schema.names());
+        }
+        for name in self.key.names() {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        let mut items: Vec<&DdbMap> = self
+            .items
+            .iter()
+            .filter(|item| names.iter().all(|name| item.contains_key(*name)))
+            .collect();
+        items.sort_by(|a, b| cmp_by(a, b, &names));
+        Ok((items, names))
+    }
+
+    /// Reads up to `limit` of `items` after `start`, then filters and projects them
+    #[allow(clippy::too_many_arguments)]
+    fn page(
+        items: Vec<&DdbMap>, names: &[&String], forward: bool, start: &Option<DdbMap>,
+        limit: Option<i64>, filter: &Option<String>, projection_exp: &Option<String>,
+        exp_names: &Option<HashMap<String, String>>, exp_values: &Option<DdbMap>,
+    ) -> Outcome<Page> {
+        let after = |item: &DdbMap| match start {
+            Some(start) => {
+                let ordering = cmp_by(item, start, names);
+                if forward {
+                    ordering == Ordering::Greater
+                } else {
+                    ordering == Ordering::Less
+                }
+            }
+            None => true,
+        };
+        let remaining: Vec<&DdbMap> = items.into_iter().filter(|item| after(item)).collect();
+        let limit = limit.map_or(remaining.len(), |limit| limit.max(0) as usize);
+        let last_evaluated_key = if remaining.len() > limit && limit > 0 {
+            let last = remaining[limit - 1];
+            Some(names.iter().map(|name| ((*name).clone(), last[*name].clone())).collect())
+        } else {
+            None
+        };
+        let scanned: Vec<&DdbMap> = remaining.into_iter().take(limit).collect();
+        let scanned_count = scanned.len() as i64;
+        let mut matched = Vec::new();
+        for item in scanned {
+            if check(filter, exp_names, exp_values, Some(item))? {
+                matched.push(projection(item.clone(), projection_exp, exp_names)?);
+            }
+        }
+        Ok(Page { count: matched.len() as i64, items: matched, scanned_count, last_evaluated_key })
+    }
+}
+
+impl MemoryDb {
+    pub fn new() -> MemoryDb {
+        MemoryDb::default()
+    }
+
+    /// Adds an empty table, replacing a table with the same name
+    pub fn table(self, table: &str, hash_key: &str, range_key: Option<&str>) -> Self {
+        self.lock().insert(table.to_string(), Table::new(hash_key, range_key));
+        self
+    }
+
+    /// Adds a global secondary index to `table`, items without the index key attributes are
+    /// left out of the index
+    pub fn index(self, table: &str, index: &str, hash_key: &str, range_key: Option<&str>) -> Self {
+        if let Some(t) = self.lock().get_mut(table) {
+            let schema =
+                KeySchema { hash: hash_key.to_string(), range: range_key.map(str::to_string) };
+            t.indexes.insert(index.to_string(), schema);
+        }
+        self
+    }
+
+    /// Declares the type, `S`, `N` or `B`, of a key attribute of `table`. Only declared types are
+    /// reported by `describe_table` and checked when a `Key` is validated.
+    pub fn attribute_type(self, table: &str, attribute: &str, attr_type: &str) -> Self {
+        if let Some(t) = self.lock().get_mut(table) {
+            t.attribute_types.insert(attribute.to_string(), attr_type.to_string());
+        }
+        self
+    }
+
+    /// Every item of `table` in key order, for assertions in tests
+    pub fn items(&self, table: &str) -> Vec<DdbMap> {
+        let tables = self.lock();
+        match tables.get(table).map(|t| t.view(&None)) {
+            Some(Ok((items, _))) => items.into_iter().cloned().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Table>> {
+        self.tables.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn describe(&self, input: DescribeTableInput) -> Outcome<DescribeTableOutput> {
+        let tables = self.lock();
+        let table = table(&tables, &input.table_name)?;
+        let key_schema = std::iter::once(("HASH", &table.key.hash))
+            .chain(table.key.range.iter().map(|range| ("RANGE", range)))
+            .map(|(key_type, name)| KeySchemaElement {
+                attribute_name: name.clone(),
+                key_type: key_type.to_string(),
+            })
+            .collect();
+        let attribute_definitions = table
+            .attribute_types
+            .iter()
+            .map(|(name, attr_type)| AttributeDefinition {
+                attribute_name: name.clone(),
+                attribute_type: attr_type.clone(),
+            })
+            .collect();
+        Ok(DescribeTableOutput {
+            table: Some(TableDescription {
+                table_name: Some(input.table_name),
+                key_schema: Some(key_schema),
+                attribute_definitions: Some(attribute_definitions),
+                item_count: Some(table.items.len() as i64),
+                table_status: Some("ACTIVE".to_string()),
+                ..Default::default()
+            }),
+        })
+    }
+
+    fn get(&self, input: GetItemInput) -> Outcome<GetItemOutput> {
+        let tables = self.lock();
+        let table = table(&tables, &input.table_name)?;
+        let key = table.check_key(&input.key)?;
+        let item = table.get(&key).cloned();
+        let item = item
+            .map(|item| {
+                projection(item, &input.projection_expression, &input.expression_attribute_names)
+            })
+            .transpose()?;
+        Ok(GetItemOutput { item, ..Default::default() })
+    }
+
+    fn put(&self, input: PutItemInput) -> Outcome<PutItemOutput> {
+        let mut tables = self.lock();
+        let table = table_mut(&mut tables, &input.table_name)?;
+        let key = table.key_of(&input.item)?;
+        let names = &input.expression_attribute_names;
+        let values = &input.expression_attribute_values;
+        if !check(&input.condition_expression, names, values, table.get(&key))? {
+            return Err(Failure::ConditionalCheckFailed);
+        }
+        let all_old = input.return_values.as_deref() == Some("ALL_OLD");
+        let old = table.put(input.item);
+        Ok(PutItemOutput { attributes: old.filter(|_| all_old), ..Default::default() })
+    }
+
+    fn update(&self, input: UpdateItemInput) -> Outcome<UpdateItemOutput> {
+        let mut tables = self.lock();
+        let table = table_mut(&mut tables, &input.table_name)?;
+        let key = table.check_key(&input.key)?;
+        let names = &input.expression_attribute_names;
+        let values = &input.expression_attribute_values;
+        let old = table.get(&key).cloned();
+        if !check(&input.condition_expression, names, values, old.as_ref())? {
+            return Err(Failure::ConditionalCheckFailed);
+        }
+        let actions = actions(&input.update_expression, names, values)?;
+        let new = table.update(key, &actions)?;
+        let updated = updated_attributes(&actions);
+        let only_updated = |item: DdbMap| -> DdbMap {
+            item.into_iter().filter(|(name, _)| updated.contains(name)).collect()
+        };
+        let attributes = match input.return_values.as_deref() {
+            Some("ALL_OLD") => old,
+            Some("UPDATED_OLD") => old.map(only_updated),
+            Some("ALL_NEW") => Some(new),
+            Some("UPDATED_NEW") => Some(only_updated(new)),
+            _ => None,
+        };
+        Ok(UpdateItemOutput { attributes, ..Default::default() })
+    }
+
+    fn delete(&self, input: DeleteItemInput) -> Outcome<DeleteItemOutput> {
+        let mut tables = self.lock();
+        let table = table_mut(&mut tables, &input.table_name)?;
+        let key = table.check_key(&input.key)?;
+        let names = &input.expression_attribute_names;
+        let values = &input.expression_attribute_values;
+        if !check(&input.condition_expression, names, values, table.get(&key))? {
+            return Err(Failure::ConditionalCheckFailed);
+        }
+        let old = table.delete(&key);
+        Ok(DeleteItemOutput {
+            attributes: old.filter(|_| input.return_values.as_deref() == Some("ALL_OLD")),
+            ..Default::default()
+        })
+    }
+
+    fn query(&self, input: QueryInput) -> Outcome<QueryOutput> {
+        let tables = self.lock();
+        let table = table(&tables, &input.table_name)?;
+        let (items, names) = table.view(&input.index_name)?;
+        let exp_names = input.expression_attribute_names.clone().unwrap_or_default();
+        let key_condition = match &input.key_condition_expression {
+            Some(key_condition) => key_condition,
+            None => return invalid("KeyConditionExpression is required"),
+        };
+        let key_condition =
+            parse_condition(key_condition, &exp_names, &empty(&input.expression_attribute_values))
+                .map_err(Failure::Validation)?;
+        let mut items: Vec<&DdbMap> =
+            items.into_iter().filter(|item| key_condition.matches(item)).collect();
+        let forward = input.scan_index_forward.unwrap_or(true);
+        if !forward {
+            items.reverse();
+        }
+        let page = Table::page(
+            items,
+            &names,
+            forward,
+            &input.exclusive_start_key,
+            input.limit,
+            &input.filter_expression,
+            &input.projection_expression,
+            &input.expression_attribute_names,
+            &input.expression_attribute_values,
+        )?;
+        let count_only = input.select.as_deref() == Some("COUNT");
+        Ok(QueryOutput {
+            count: Some(page.count),
+            items: if count_only { None } else { Some(page.items) },
+            last_evaluated_key: page.last_evaluated_key,
+            scanned_count: Some(page.scanned_count),
+            ..Default::default()
+        })
+    }
+
+    fn scan(&self, input: ScanInput) -> Outcome<ScanOutput> {
+        let tables = self.lock();
+        let table = table(&tables, &input.table_name)?;
+        let (mut items, names) = table.view(&input.index_name)?;
+        if let (Some(segment), Some(total)) = (input.segment, input.total_segments) {
+            items.retain(|item| segment_of(item, &table.key.hash, total) == segment);
+        }
+        let page = Table::page(
+            items,
+            &names,
+            true,
+            &input.exclusive_start_key,
+            input.limit,
+            &input.filter_expression,
+            &input.projection_expression,
+            &input.expression_attribute_names,
+            &input.expression_attribute_values,
+        )?;
+        let count_only = input.select.as_deref() == Some("COUNT");
+        Ok(ScanOutput {
+            count: Some(page.count),
+            items: if count_only { None } else { Some(page.items) },
+            last_evaluated_key: page.last_evaluated_key,
+            scanned_count: Some(page.scanned_count),
+            ..Default::default()
+        })
+    }
+
+    fn batch_get(&self, input: BatchGetItemInput) -> Outcome<BatchGetItemOutput> {
+        if input.request_items.values().map(|keys| keys.keys.len()).sum::<usize>() > 100 {
+            return invalid("too many items requested for the BatchGetItem call");
+        }
+        let tables = self.lock();
+        let mut responses = HashMap::new();
+        for (name, keys) in input.request_items {
+            let table = table(&tables, &name)?;
+            let mut items = Vec::new();
+            for key in &keys.keys {
+                let key = table.check_key(key)?;
+                if let Some(item) = table.get(&key) {
+                    let names = &keys.expression_attribute_names;
+                    items.push(projection(item.clone(), &keys.projection_expression, names)?);
+                }
+            }
+            responses.insert(name, items);
+        }
+        Ok(BatchGetItemOutput {
+            responses: Some(responses),
+            unprocessed_keys: Some(HashMap::new()),
+            ..Default::default()
+        })
+    }
+
+    fn batch_write(&self, input: BatchWriteItemInput) -> Outcome<BatchWriteItemOutput> {
+        if input.request_items.values().map(Vec::len).sum::<usize>() > 25 {
+            return invalid("too many items requested for the BatchWriteItem call");
+        }
+        let mut tables = self.lock();
+        for (name, requests) in &input.request_items {
+            let table = table(&tables, name)?;
+            for request in requests {
+                if let Some(put) = &request.put_request {
+                    table.key_of(&put.item)?;
+                }
+                if let Some(delete) = &request.delete_request {
+                    table.check_key(&delete.key)?;
+                }
+            }
+        }
+        for (name, requests) in input.request_items {
+            let table = table_mut(&mut tables, &name)?;
+            for request in requests {
+                if let Some(put) = request.put_request {
+                    table.put(put.item);
+                }
+                if let Some(delete) = request.delete_request {
+                    let key = table.check_key(&delete.key)?;
+                    table.delete(&key);
+                }
+            }
+        }
+        Ok(BatchWriteItemOutput { unprocessed_items: Some(HashMap::new()), ..Default::default() })
+    }
+
+    fn transact_get(&self, input: TransactGetItemsInput) -> Outcome<TransactGetItemsOutput> {
+        if input.transact_items.len() > 100 {
+            return invalid("too many items in the transaction");
+        }
+        let tables = self.lock();
+        let mut responses = Vec::new();
+        let mut seen = Vec::new();
+        for get in input.transact_items.into_iter().map(|t| t.get) {
+            let table = table(&tables, &get.table_name)?;
+            let key = table.check_key(&get.key)?;
+            once(&mut seen, &get.table_name, &key)?;
+            let item = table
+                .get(&key)
+                .cloned()
+                .map(|item| {
+                    projection(item, &get.projection_expression, &get.expression_attribute_names)
+                })
+                .transpose()?;
+            responses.push(ItemResponse { item });
+        }
+        Ok(TransactGetItemsOutput { responses: Some(responses), ..Default::default() })
+    }
+
+    fn transact_write(&self, input: TransactWriteItemsInput) -> Outcome<TransactWriteItemsOutput> {
+        if input.transact_items.len() > 100 {
+            return invalid("too many items in the transaction");
+        }
+        let mut tables = self.lock();
+        let mut reasons = Vec::new();
+        let mut seen = Vec::new();
+        for op in &input.transact_items {
+            let (name, key, condition, names, values) = if let Some(put) = &op.put {
+                let key = table(&tables, &put.table_name)?.key_of(&put.item)?;
+                let exp = (&put.condition_expression, &put.expression_attribute_names);
+                (&put.table_name, key, exp.0, exp.1, &put.expression_attribute_values)
+            } else if let Some(update) = &op.update {
+                let key = table(&tables, &update.table_name)?.check_key(&update.key)?;
+                let exp = (&update.condition_expression, &update.expression_attribute_names);
+                (&update.table_name, key, exp.0, exp.1, &update.expression_attribute_values)
+            } else if let Some(delete) = &op.delete {
+                let key = table(&tables, &delete.table_name)?.check_key(&delete.key)?;
+                let exp = (&delete.condition_expression, &delete.expression_attribute_names);
+                (&delete.table_name, key, exp.0, exp.1, &delete.expression_attribute_values)
+            } else if let Some(check_op) = &op.condition_check {
+                let key = table(&tables, &check_op.table_name)?.check_key(&check_op.key)?;
+                once(&mut seen, &check_op.table_name, &key)?;
+                let condition = Some(check_op.condition_expression.clone());
+                let names = &check_op.expression_attribute_names;
+                let values = &check_op.expression_attribute_values;
+                let item = table(&tables, &check_op.table_name)?.get(&key);
+                let ok = check(&condition, names, values, item)?;
+                reasons.push(if ok { "None" } else { "ConditionalCheckFailed" });
+                continue;
+            } else {
+                return invalid(
+                    "a transact item needs one of Put, Update, Delete or ConditionCheck",
+                );
+            };
+            once(&mut seen, name, &key)?;
+            let item = table(&tables, name)?.get(&key);
+            let ok = check(condition, names, values, item)?;
+            reasons.push(if ok { "None" } else { "ConditionalCheckFailed" });
+        }
+        if reasons.iter().any(|reason| *reason != "None") {
+            return Err(Failure::TransactionCanceled(reasons));
+        }
+        // the writes go to a copy so that a failing update leaves every table untouched
+        let mut staged: HashMap<String, Table> =
+            tables.iter().map(|(name, table)| (name.clone(), table.clone())).collect();
+        for op in input.transact_items {
+            if let Some(put) = op.put {
+                table_mut(&mut staged, &put.table_name)?.put(put.item);
+            } else if let Some(update) = op.update {
+                let names = &update.expression_attribute_names;
+                let values = &update.expression_attribute_values;
+                let actions = actions(&Some(update.update_expression), names, values)?;
+                let table = table_mut(&mut staged, &update.table_name)?;
+                let key = table.check_key(&update.key)?;
+                table.update(key, &actions)?;
+            } else if let Some(delete) = op.delete {
+                table_mut(&mut staged, &delete.table_name)?.delete(&delete.key);
+            }
+        }
+        *tables = staged;
+        Ok(TransactWriteItemsOutput::default())
+    }
+}
+
+/// Fails when the transaction already has an operation on the item `key` of `table`
+fn once(seen: &mut Vec<(String, DdbMap)>, table: &str, key: &DdbMap) -> Outcome<()> {
+    if seen.iter().any(|(t, k)| t == table && k == key) {
+        return invalid("Transaction request cannot include multiple operations on one item");
+    }
+    seen.push((table.to_string(), key.clone()));
+    Ok(())
+}
+
+fn table<'t>(tables: &'t HashMap<String, Table>, name: &str) -> Outcome<&'t Table> {
+    tables.get(name).ok_or_else(|| Failure::ResourceNotFound(name.to_string()))
+}
+
+fn table_mut<'t>(tables: &'t mut HashMap<String, Table>, name: &str) -> Outcome<&'t mut Table> {
+    tables.get_mut(name).ok_or_else(|| Failure::ResourceNotFound(name.to_string()))
+}
+
+#[async_trait]
+impl DdbClient for MemoryDb {
+    async fn get_item(
+        &self, input: GetItemInput,
+    ) -> Result<GetItemOutput, RusotoError<GetItemError>> {
+        self.get(input).map_err(into_rusoto)
+    }
+
+    async fn put_item(
+        &self, input: PutItemInput,
+    ) -> Result<PutItemOutput, RusotoError<PutItemError>> {
+        self.put(input).map_err(into_rusoto)
+    }
+
+    async fn update_item(
+        &self, input: UpdateItemInput,
+    ) -> Result<UpdateItemOutput, RusotoError<UpdateItemError>> {
+        self.update(input).map_err(into_rusoto)
+    }
+
+    async fn delete_item(
+        &self, input: DeleteItemInput,
+    ) -> Result<DeleteItemOutput, RusotoError<DeleteItemError>> {
+        self.delete(input).map_err(into_rusoto)
+    }
+
+    async fn query(&self, input: QueryInput) -> Result<QueryOutput, RusotoError<QueryError>> {
+        MemoryDb::query(self, input).map_err(into_rusoto)
+    }
+
+    async fn scan(&self, input: ScanInput) -> Result<ScanOutput, RusotoError<ScanError>> {
+        MemoryDb::scan(self, input).map_err(into_rusoto)
+    }
+
+    async fn batch_get_item(
+        &self, input: BatchGetItemInput,
+    ) -> Result<BatchGetItemOutput, RusotoError<BatchGetItemError>> {
+        self.batch_get(input).map_err(into_rusoto)
+    }
+
+    async fn batch_write_item(
+        &self, input: BatchWriteItemInput,
+    ) -> Result<BatchWriteItemOutput, RusotoError<BatchWriteItemError>> {
+        self.batch_write(input).map_err(into_rusoto)
+    }
+
+    async fn transact_get_items(
+        &self, input: TransactGetItemsInput,
+    ) -> Result<TransactGetItemsOutput, RusotoError<TransactGetItemsError>> {
+        self.transact_get(input).map_err(into_rusoto)
+    }
+
+    async fn transact_write_items(
+        &self, input: TransactWriteItemsInput,
+    ) -> Result<TransactWriteItemsOutput, RusotoError<TransactWriteItemsError>> {
+        self.transact_write(input).map_err(into_rusoto)
+    }
+
+    async fn describe_table(
+        &self, input: DescribeTableInput,
+    ) -> Result<DescribeTableOutput, RusotoError<DescribeTableError>> {
+        self.describe(input).map_err(into_rusoto)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    struct Dataset {
+        pk: String,
+        sk: String,
+        itemtype: String,
+        size: u64,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Debug, Deserialize)]
+    struct Keys {
+        pk: String,
+        sk: String,
+    }
+
+    fn dataset(pk: &str, sk: &str, size: u64) -> Dataset {
+        Dataset { pk: pk.to_string(), sk: sk.to_string(), itemtype: "dataset".to_string(), size }
+    }
+
+    fn key(pk: &str, sk: &str) -> DdbMap {
+        let mut key: DdbMap = HashMap::new();
+        set_kv(&mut key, "pk".to_string(), pk.to_string());
+        set_kv(&mut key, "sk".to_string(), sk.to_string());
+        key
+    }
+
+    async fn seeded() -> Result<MemoryDb, DdbError> {
+        let client = MemoryDb::new().table("relations", "pk", Some("sk")).index(
+            "relations",
+            "itemtype-index",
+            "itemtype",
+            Some("size"),
+        );
+        let datasets = vec![
+            dataset("c4c", "dataset#2", 20),
+            dataset("c4c", "dataset#1", 10),
+            dataset("c4c", "dataset#3", 30),
+            dataset("c4d", "dataset#1", 5),
+        ];
+        batch_put_items(&client, "relations", &datasets).await?;
+        Ok(client)
+    }
+
+    #[tokio::test]
+    async fn queries_in_range_key_order_with_filters_and_pages() -> Result<(), DdbError> {
+        let client = seeded().await?;
+        let datasets: Vec<Dataset> = Query::new("relations")
+            .key_condition(attr("pk").eq("c4c").and(attr("sk").begins_with("dataset#")))
+            .filter(attr("size").gt(10))
+            .limit(1)
+            .send(&client)
+            .await?;
+        assert_eq!(
+            datasets,
+            vec![dataset("c4c", "dataset#2", 20), dataset("c4c", "dataset#3", 30)]
+        );
+        let page: Page<Dataset> = Query::new("relations")
+            .key_condition(attr("pk").eq("c4c"))
+            .scan_forward(false)
+            .limit(2)
+            .page(&client, None)
+            .await?;
+        assert_eq!(
+            page.items,
+            vec![dataset("c4c", "dataset#3", 30), dataset("c4c", "dataset#2", 20)]
+        );
+        let page: Page<Dataset> = Query::new("relations")
+            .key_condition(attr("pk").eq("c4c"))
+            .scan_forward(false)
+            .limit(2)
+            .page(&client, page.next.as_ref())
+            .await?;
+        assert_eq!(page.items, vec![dataset("c4c", "dataset#1", 10)]);
+        assert!(page.next.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn queries_and_scans_global_secondary_indexes() -> Result<(), DdbError> {
+        let client = seeded().await?;
+        put_item(&client, "relations", key("c4e", "owner")).await?;
+        let sizes: Vec<u64> = Query::new("relations")
+            .index("itemtype-index")
+            .key_condition(attr("itemtype").eq("dataset").and(attr("size").between(5, 20)))
+            .send::<Dataset>(&client)
+            .await?
+            .into_iter()
+            .map(|d| d.size)
+            .collect();
+        assert_eq!(sizes, vec![5, 10, 20]);
+        let indexed: Vec<Dataset> =
+            Scan::new("relations").index("itemtype-index").send(&client).await?;
+        assert_eq!(indexed.len(), 4);
+        let all: Vec<Keys> = Scan::new("relations").parallel_scan(3).send(&client).await?;
+        assert_eq!(all.len(), 5);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn conditional_writes_fail_like_dynamodb() -> Result<(), DdbError> {
+        let client = seeded().await?;
+        let updated: Option<Dataset> = UpdateItem::new("relations", key("c4c", "dataset#1"))
+            .update(UpdateExpression::new().increment("size", 5))
+            .condition(attr("size").eq(10))
+            .return_values("ALL_NEW")
+            .send(&client)
+            .await?;
+        assert_eq!(updated, Some(dataset("c4c", "dataset#1", 15)));
+        let res = UpdateItem::new("relations", key("c4c", "dataset#1"))
+            .update(UpdateExpression::new().increment("size", 5))
+            .condition(attr("size").eq(10))
+            .send::<Dataset>(&client)
+            .await;
+        assert!(res.unwrap_err().is_conditional_check_failed());
+        let res = put_if_absent(&client, "relations", key("c4c", "dataset#1")).await;
+        assert!(res.unwrap_err().is_conditional_check_failed());
+        let deleted: Option<Dataset> =
+            delete_item(&client, "relations", Key::hash_range("pk", "c4d", "sk", "dataset#1"), None)
+                .await?;
+        assert_eq!(deleted, Some(dataset("c4d", "dataset#1", 5)));
+        assert_eq!(client.items("relations").len(), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn canceled_transactions_write_nothing() -> Result<(), DdbError> {
+        let client = seeded().await?;
+        let res = Transaction::new()
+            .delete("relations", key("c4c", "dataset#1"))
+            .put("relations", &dataset("c4c", "dataset#2", 1))
+            .condition(attr("pk").attribute_not_exists())
+            .send(&client)
+            .await;
+        match res {
+            Err(DdbError::TransactionCanceled(reasons)) => {
+                assert_eq!(reasons, vec![None, Some(CancellationReason::ConditionalCheckFailed)])
+            }
+            other => panic!("expected a canceled transaction, got {:?}", other),
+        }
+        assert_eq!(client.items("relations").len(), 4);
+        Transaction::new()
+            .delete("relations", key("c4c", "dataset#1"))
+            .update("relations", key("c4c", "dataset#2"), UpdateExpression::new().set("size", 1))
+            .send(&client)
+            .await?;
+        let items: Vec<Option<Dataset>> = transact_get_items(
+            &client,
+            vec![
+                ("relations", key("c4c", "dataset#1"), None),
+                ("relations", key("c4c", "dataset#2"), None),
+            ],
+        )
+        .await?;
+        assert_eq!(items, vec![None, Some(dataset("c4c", "dataset#2", 1))]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn batch_gets_return_items_in_key_order() -> Result<(), DdbError> {
+        let client = seeded().await?;
+        let keys = vec![key("c4d", "dataset#1"), key("c4c", "missing"), key("c4c", "dataset#3")];
+        let items: Vec<Option<Dataset>> = batch_get_items(&client, "relations", keys).await?;
+        assert_eq!(
+            items,
+            vec![Some(dataset("c4d", "dataset#1", 5)), None, Some(dataset("c4c", "dataset#3", 30))]
+        );
+        let key = Key::hash_range("pk", "c4c", "sk", "dataset#3");
+        let res = get_item::<Dataset>(&client, "datasets", key, false, None);
+        assert!(res.await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn projections_merge_paths_into_the_same_list_element() -> Result<(), DdbError> {
+        #[derive(Debug, Deserialize, Serialize, PartialEq)]
+        struct Part {
+            x: Option<u64>,
+            y: Option<u64>,
+        }
+        #[derive(Debug, Deserialize, Serialize, PartialEq)]
+        struct Assembly {
+            pk: String,
+            sk: String,
+            parts: Vec<Part>,
+        }
+        let client = MemoryDb::new().table("relations", "pk", Some("sk"));
+        let parts = vec![Part { x: Some(1), y: Some(2) }, Part { x: Some(3), y: Some(4) }];
+        let assembly = Assembly { pk: "c4c".to_string(), sk: "assembly#1".to_string(), parts };
+        put_typed_item(&client, "relations", &assembly).await?;
+        let key = Key::hash_range("pk", "c4c", "sk", "assembly#1");
+        let projection = "pk, sk, parts[1].y, parts[0].x, parts[0].y".to_string();
+        let res = get_item::<Assembly>(&client, "relations", key, false, Some(projection)).await?;
+        let parts = vec![Part { x: Some(1), y: Some(2) }, Part { x: None, y: Some(4) }];
+        assert_eq!(res, Some(Assembly { parts, ..assembly }));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn batch_gets_request_duplicate_keys_once() -> Result<(), DdbError> {
+        let client = seeded().await?;
+        let keys = vec![key("c4c", "dataset#3"), key("c4d", "dataset#1"), key("c4c", "dataset#3")];
+        let items: Vec<Option<Dataset>> = batch_get_items(&client, "relations", keys).await?;
+        let c4c = Some(dataset("c4c", "dataset#3", 30));
+        assert_eq!(items, vec![c4c.clone(), Some(dataset("c4d", "dataset#1", 5)), c4c]);
+        let keys = vec![key("c4c", "dataset#3"), ddb_map! { "pk" => "c4c" }];
+        let res = batch_get_items::<Dataset>(&client, "relations", keys).await;
+        assert!(matches!(res, Err(DdbError::InvalidKey(_))));
+        Ok(())
+    }
+}
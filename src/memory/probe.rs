@@ -0,0 +1,144 @@
+use super::MemoryDb;
+use crate::DdbClient;
+use async_trait::async_trait;
+use rusoto_core::RusotoError;
+use rusoto_dynamodb::{
+    BatchGetItemError, BatchGetItemInput, BatchGetItemOutput, BatchWriteItemError,
+    BatchWriteItemInput, BatchWriteItemOutput, DeleteItemError, DeleteItemInput, DeleteItemOutput,
+    DescribeTableError, DescribeTableInput, DescribeTableOutput, GetItemError, GetItemInput,
+    GetItemOutput, PutItemError, PutItemInput, PutItemOutput, QueryError, QueryInput,
+    QueryOutput, ScanError, ScanInput, ScanOutput, TransactGetItemsError, TransactGetItemsInput,
+    TransactGetItemsOutput, TransactWriteItemsError, TransactWriteItemsInput,
+    TransactWriteItemsOutput, UpdateItemError, UpdateItemInput, UpdateItemOutput,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// A `MemoryDb` for tests that records the scans and batch writes it is sent
+///
+/// Scans and batch writes are held for a moment, so requests that are in flight at the same
+/// time overlap and are counted in `max_in_flight`. The first `unprocessed` write requests are
+/// handed back unprocessed instead of being written.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Probe {
+    pub db: MemoryDb,
+    state: Arc<Mutex<ProbeState>>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ProbeState {
+    pub in_flight: usize,
+    pub max_in_flight: usize,
+    pub scans: Vec<ScanInput>,
+    pub batch_writes: usize,
+    pub unprocessed: usize,
+}
+
+impl Probe {
+    pub fn new(db: MemoryDb) -> Probe {
+        Probe { db, state: Arc::default() }
+    }
+
+    /// Hand back the next `unprocessed` write requests unprocessed
+    pub fn unprocessed(self, unprocessed: usize) -> Self {
+        self.state().unprocessed = unprocessed;
+        self
+    }
+
+    pub fn state(&self) -> MutexGuard<'_, ProbeState> {
+        self.state.lock().unwrap()
+    }
+
+    async fn hold(&self) {
+        {
+            let mut state = self.state();
+            state.in_flight += 1;
+            state.max_in_flight = state.max_in_flight.max(state.in_flight);
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        self.state().in_flight -= 1;
+    }
+}
+
+#[async_trait]
+impl DdbClient for Probe {
+    async fn get_item(
+        &self, input: GetItemInput,
+    ) -> Result<GetItemOutput, RusotoError<GetItemError>> {
+        DdbClient::get_item(&self.db, input).await
+    }
+
+    async fn put_item(
+        &self, input: PutItemInput,
+    ) -> Result<PutItemOutput, RusotoError<PutItemError>> {
+        DdbClient::put_item(&self.db, input).await
+    }
+
+    async fn update_item(
+        &self, input: UpdateItemInput,
+    ) -> Result<UpdateItemOutput, RusotoError<UpdateItemError>> {
+        DdbClient::update_item(&self.db, input).await
+    }
+
+    async fn delete_item(
+        &self, input: DeleteItemInput,
+    ) -> Result<DeleteItemOutput, RusotoError<DeleteItemError>> {
+        DdbClient::delete_item(&self.db, input).await
+    }
+
+    async fn query(&self, input: QueryInput) -> Result<QueryOutput, RusotoError<QueryError>> {
+        DdbClient::query(&self.db, input).await
+    }
+
+    async fn scan(&self, input: ScanInput) -> Result<ScanOutput, RusotoError<ScanError>> {
+        self.state().scans.push(input.clone());
+        self.hold().await;
+        DdbClient::scan(&self.db, input).await
+    }
+
+    async fn batch_get_item(
+        &self, input: BatchGetItemInput,
+    ) -> Result<BatchGetItemOutput, RusotoError<BatchGetItemError>> {
+        DdbClient::batch_get_item(&self.db, input).await
+    }
+
+    async fn batch_write_item(
+        &self, mut input: BatchWriteItemInput,
+    ) -> Result<BatchWriteItemOutput, RusotoError<BatchWriteItemError>> {
+        self.state().batch_writes += 1;
+        self.hold().await;
+        let mut unprocessed = HashMap::new();
+        for (table, requests) in input.request_items.iter_mut() {
+            let mut state = self.state();
+            let bounced = state.unprocessed.min(requests.len());
+            state.unprocessed -= bounced;
+            if bounced > 0 {
+                unprocessed.insert(table.clone(), requests.drain(..bounced).collect());
+            }
+        }
+        input.request_items.retain(|_, requests| !requests.is_empty());
+        if !input.request_items.is_empty() {
+            DdbClient::batch_write_item(&self.db, input).await?;
+        }
+        Ok(BatchWriteItemOutput { unprocessed_items: Some(unprocessed), ..Default::default() })
+    }
+
+    async fn transact_get_items(
+        &self, input: TransactGetItemsInput,
+    ) -> Result<TransactGetItemsOutput, RusotoError<TransactGetItemsError>> {
+        DdbClient::transact_get_items(&self.db, input).await
+    }
+
+    async fn transact_write_items(
+        &self, input: TransactWriteItemsInput,
+    ) -> Result<TransactWriteItemsOutput, RusotoError<TransactWriteItemsError>> {
+        DdbClient::transact_write_items(&self.db, input).await
+    }
+
+    async fn describe_table(
+        &self, input: DescribeTableInput,
+    ) -> Result<DescribeTableOutput, RusotoError<DescribeTableError>> {
+        DdbClient::describe_table(&self.db, input).await
+    }
+}
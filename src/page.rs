@@ -0,0 +1,60 @@
+use crate::DdbMap;
+use std::fmt;
+
+/// One page of results together with the token to fetch the next one
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// `None` when there are no more pages
+    pub next: Option<PageToken>,
+}
+
+/// Opaque continuation token wrapping the `LastEvaluatedKey` of a page
+///
+/// The token is a plain string so it can be handed to clients and passed back unchanged to
+/// fetch the following page. Its content is not meant to be interpreted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PageToken(String);
+
+impl PageToken {
+    pub(crate) fn encode(key: &DdbMap) -> Result<PageToken, serde_json::Error> {
+        serde_json::to_string(key).map(PageToken)
+    }
+
+    pub(crate) fn decode(&self) -> Result<DdbMap, serde_json::Error> {
+        serde_json::from_str(&self.0)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for PageToken {
+    fn from(token: String) -> Self {
+        PageToken(token)
+    }
+}
+
+impl fmt::Display for PageToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn page_token_round_trip() {
+        let mut key: DdbMap = HashMap::new();
+        set_kv(&mut key, "pk".to_string(), "c4c".to_string());
+        set_kv(&mut key, "sk".to_string(), "dataset#1".to_string());
+        let token = PageToken::encode(&key).unwrap();
+        let token = PageToken::from(token.to_string());
+        assert_eq!(token.decode().unwrap(), key);
+        assert!(PageToken::from("garbage".to_string()).decode().is_err());
+    }
+}
@@ -0,0 +1,163 @@
+use crate::expression::hand_written_attributes;
+use crate::item::from_attributes;
+use crate::{attr, DdbClient, DdbError, DdbMap, Expression, ExpressionAttributes};
+use rusoto_dynamodb::PutItemInput;
+use serde::de::DeserializeOwned;
+
+/// # Dynamodb put_item builder
+/// Puts `item`, optionally guarded by a condition so an existing item is not overwritten by
+/// accident. With `return_values` `ALL_OLD` the replaced item is deserialized into `T`, `send`
+/// returns `None` when no item was replaced. A failed condition is
+/// `DdbError::ConditionalCheckFailed`.
+/// ```
+/// # use rusoto_core::Region;
+/// # use rusoto_dynamodb::DynamoDbClient;
+/// # use serde::Deserialize;
+/// # use std::collections::HashMap;
+/// # use ddb_util::*;
+///
+/// # #[derive(Debug, Deserialize)]
+/// # struct Dataset {
+/// #     pk: String,
+/// #     sk: String,
+/// # }
+///
+/// # #[cfg(feature = "local")]
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// # let local = match LocalDynamoDb::from_env()? {
+/// #     Some(local) => local,
+/// #     None => return Ok(()),
+/// # };
+/// # let relations = local.fixture("fixtures/relations.json").await?;
+/// # let relations = relations.as_str();
+/// let client = DynamoDbClient::new(Region::EuWest1);
+/// # let client = local.client().clone();
+/// let mut item: DdbMap = HashMap::new();
+/// set_kv(&mut item, "pk".to_string(), "c4c".to_string());
+/// set_kv(&mut item, "sk".to_string(), "dataset#1".to_string());
+/// set_kv(&mut item, "status".to_string(), "open".to_string());
+/// let res = PutItem::new(relations, item.clone())
+///     .if_absent(&["pk", "sk"])
+///     .send::<Dataset>(&client)
+///     .await;
+/// if let Err(DdbError::ConditionalCheckFailed(_)) = res {
+///     let replaced: Option<Dataset> = PutItem::new(relations, item)
+///         .condition(attr("status").ne("locked"))
+///         .return_values("ALL_OLD")
+///         .send(&client)
+///         .await?;
+/// }
+/// # local.teardown().await?;
+/// #     Ok(())
+/// # }
+/// # #[cfg(not(feature = "local"))]
+/// # fn main() {}
+/// ```
+#[derive(Clone, Debug)]
+pub struct PutItem {
+    input: PutItemInput,
+    attrs: ExpressionAttributes,
+}
+
+impl PutItem {
+    pub fn new(table: &str, item: DdbMap) -> PutItem {
+        PutItem {
+            input: PutItemInput {
+                table_name: table.to_string(),
+                item,
+                ..Default::default()
+            },
+            attrs: ExpressionAttributes::default(),
+        }
+    }
+
+    /// Only put the item when no item with the same key exists, by requiring that the key
+    /// attributes `key_attrs` do not exist
+    ///
+    /// Replaces any other condition, the placeholders of a typed condition are dropped with it
+    /// while hand written values and names stay. Without `key_attrs` nothing would be checked,
+    /// so an empty slice makes `send` fail with `DdbError::Expression`.
+    pub fn if_absent(mut self, key_attrs: &[&str]) -> Self {
+        let absent = key_attrs
+            .iter()
+            .map(|key_attr| attr(key_attr).attribute_not_exists())
+            .reduce(|cond, next| cond.and(next));
+        match absent {
+            Some(absent) => self.condition(absent),
+            None => {
+                self.attrs.error("if_absent needs at least one key attribute");
+                self
+            }
+        }
+    }
+
+    pub fn condition(mut self, condition_exp: impl Expression) -> Self {
+        self.input.condition_expression = Some(condition_exp.render(&mut self.attrs));
+        self
+    }
+
+    hand_written_attributes!();
+
+    /// `NONE` or `ALL_OLD`
+    pub fn return_values(mut self, return_values: &str) -> Self {
+        self.input.return_values = Some(return_values.to_string());
+        self
+    }
+
+    pub async fn send<T: DeserializeOwned>(
+        self, client: &impl DdbClient,
+    ) -> Result<Option<T>, DdbError> {
+        let input = self.attrs.apply(self.input)?;
+        Ok(from_attributes(client.put_item(input).await?.attributes)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn dataset(status: &str) -> DdbMap {
+        ddb_map! { "pk" => "c4c", "sk" => "dataset#1", "status" => status }
+    }
+
+    #[tokio::test]
+    async fn if_absent_only_puts_missing_items() -> Result<(), DdbError> {
+        let client = MemoryDb::new().table("relations", "pk", Some("sk"));
+        put_if_absent(&client, "relations", dataset("open")).await?;
+        let res = put_if_absent(&client, "relations", dataset("done")).await;
+        assert!(matches!(res, Err(DdbError::ConditionalCheckFailed(_))));
+        assert_eq!(client.items("relations"), vec![dataset("open")]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn if_absent_without_key_attributes_is_rejected() {
+        let client = MemoryDb::new().table("relations", "pk", Some("sk"));
+        let res = PutItem::new("relations", dataset("open")).if_absent(&[]).send::<DdbMap>(&client);
+        let err = res.await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid expression: if_absent needs at least one key attribute"
+        );
+        assert!(client.items("relations").is_empty());
+    }
+
+    #[test]
+    fn if_absent_drops_the_placeholders_of_the_replaced_condition() -> Result<(), DdbError> {
+        let put = PutItem::new("relations", dataset("open"))
+            .condition(attr("status").ne("locked"))
+            .if_absent(&["pk", "sk"]);
+        let input = put.attrs.apply(put.input)?;
+        assert_eq!(
+            input.condition_expression.as_deref(),
+            Some("attribute_not_exists(#n1) AND attribute_not_exists(#n2)")
+        );
+        let mut names: Vec<String> =
+            input.expression_attribute_names.unwrap().into_keys().collect();
+        names.sort();
+        assert_eq!(names, vec!["#n1", "#n2"]);
+        assert_eq!(input.expression_attribute_values, None);
+        Ok(())
+    }
+}
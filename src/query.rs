@@ -0,0 +1,332 @@
+use crate::expression::hand_written_attributes;
+use crate::{
+    from_item, stream, DdbClient, DdbError, Expression, ExpressionAttributes, Page, PageToken,
+};
+use futures::stream::Stream;
+use rusoto_dynamodb::QueryInput;
+use serde::de::DeserializeOwned;
+
+/// # Dynamodb query builder
+/// Collects the `QueryInput` fields one by one, then run it with `send`, `page` or `stream`.
+/// `send` follows `LastEvaluatedKey` until the result set is exhausted or `max_items` items
+/// have been collected. Expressions are either plain strings, with their placeholders added
+/// through `values` and `names`, or typed expressions which fill in the placeholders
+/// themselves.
+/// ```
+/// # use rusoto_core::Region;
+/// # use rusoto_dynamodb::DynamoDbClient;
+/// # use serde::Deserialize;
+/// # use std::collections::HashMap;
+/// # use ddb_util::*;
+///
+/// # #[derive(Debug, Deserialize)]
+/// # struct Dataset {
+/// #     pk: String,
+/// #     sk: String,
+/// #     itemtype: String,
+/// #     created: Option<u64>,
+/// # }
+///
+/// # #[cfg(feature = "local")]
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// # let local = match LocalDynamoDb::from_env()? {
+/// #     Some(local) => local,
+/// #     None => return Ok(()),
+/// # };
+/// # let relations = local.fixture("fixtures/relations.json").await?;
+/// # let relations = relations.as_str();
+/// let client = DynamoDbClient::new(Region::EuWest1);
+/// # let client = local.client().clone();
+/// let mut exp_attr: DdbMap = HashMap::new();
+/// set_kv(&mut exp_attr, ":itemtype".to_string(), "dataset".to_string());
+/// let x: Vec<Dataset> = Query::new(relations)
+///     .index("itemtype-index")
+///     .key_condition("itemtype = :itemtype")
+///     .values(exp_attr)
+///     .scan_forward(false)
+///     .send(&client)
+///     .await?;
+/// let y: Vec<Dataset> = Query::new(relations)
+///     .key_condition(attr("pk").eq("c4c").and(attr("sk").begins_with("dataset#")))
+///     .filter(attr("created").gt(1633046400))
+///     .project(projection(&["pk", "sk", "itemtype", "created"]))
+///     .send(&client)
+///     .await?;
+/// # local.teardown().await?;
+/// #     Ok(())
+/// # }
+/// # #[cfg(not(feature = "local"))]
+/// # fn main() {}
+/// ```
+#[derive(Clone, Debug)]
+pub struct Query {
+    input: QueryInput,
+    attrs: ExpressionAttributes,
+    max_items: Option<usize>,
+}
+
+impl Query {
+    pub fn new(table: &str) -> Query {
+        Query {
+            input: QueryInput {
+                table_name: table.to_string(),
+                ..Default::default()
+            },
+            attrs: ExpressionAttributes::default(),
+            max_items: None,
+        }
+    }
+
+    /// Query a global or local secondary index instead of the table
+    pub fn index(mut self, index_name: &str) -> Self {
+        self.input.index_name = Some(index_name.to_string());
+        self
+    }
+
+    pub fn key_condition(mut self, key_cond_exp: impl Expression) -> Self {
+        self.input.key_condition_expression = Some(key_cond_exp.render(&mut self.attrs));
+        self
+    }
+
+    pub fn filter(mut self, filter_exp: impl Expression) -> Self {
+        self.input.filter_expression = Some(filter_exp.render(&mut self.attrs));
+        self
+    }
+
+    pub fn project(mut self, projection_exp: impl Expression) -> Self {
+        self.input.projection_expression = Some(projection_exp.render(&mut self.attrs));
+        self
+    }
+
+    hand_written_attributes!();
+
+    /// Maximum number of items evaluated per request, i.e. the page size
+    pub fn limit(mut self, limit: i64) -> Self {
+        self.input.limit = Some(limit);
+        self
+    }
+
+    /// Stop `send` once this many items have been collected
+    pub fn max_items(mut self, max_items: usize) -> Self {
+        self.max_items = Some(max_items);
+        self
+    }
+
+    /// Pass `false` to return the items in descending sort key order
+    pub fn scan_forward(mut self, scan_index_forward: bool) -> Self {
+        self.input.scan_index_forward = Some(scan_index_forward);
+        self
+    }
+
+    pub fn consistent_read(mut self, consistent_read: bool) -> Self {
+        self.input.consistent_read = Some(consistent_read);
+        self
+    }
+
+    /// One of `ALL_ATTRIBUTES`, `ALL_PROJECTED_ATTRIBUTES`, `SPECIFIC_ATTRIBUTES` or `COUNT`
+    pub fn select(mut self, select: &str) -> Self {
+        self.input.select = Some(select.to_string());
+        self
+    }
+
+    /// One of `INDEXES`, `TOTAL` or `NONE`
+    pub fn return_consumed_capacity(mut self, return_consumed_capacity: &str) -> Self {
+        self.input.return_consumed_capacity = Some(return_consumed_capacity.to_string());
+        self
+    }
+
+    /// Runs the query and collects every page
+    pub async fn send<T: DeserializeOwned>(
+        self, client: &impl DdbClient,
+    ) -> Result<Vec<T>, DdbError> {
+        let max_items = self.max_items.unwrap_or(usize::MAX);
+        let mut query_input = self.into_input()?;
+        let mut items: Vec<T> = Vec::new();
+        loop {
+            let res = client.query(query_input.clone()).await?;
+            for item in res.items.unwrap_or_default() {
+                if items.len() >= max_items {
+                    return Ok(items);
+                }
+                items.push(from_item(item)?);
+            }
+            match res.last_evaluated_key {
+                Some(key) if items.len() < max_items => query_input.exclusive_start_key = Some(key),
+                _ => return Ok(items),
+            }
+        }
+    }
+
+    /// Fetches a single page starting after `token`
+    ///
+    /// Pass the returned `next` token back in to continue, it is `None` on the last page.
+    pub async fn page<T: DeserializeOwned>(
+        self, client: &impl DdbClient, token: Option<&PageToken>,
+    ) -> Result<Page<T>, DdbError> {
+        let mut query_input = self.into_input()?;
+        query_input.exclusive_start_key = token
+            .map(PageToken::decode)
+            .transpose()
+            .map_err(DdbError::PageToken)?;
+        let res = client.query(query_input).await?;
+        let items = res
+            .items
+            .unwrap_or_default()
+            .into_iter()
+            .map(from_item)
+            .collect::<Result<Vec<T>, _>>()?;
+        let next = res
+            .last_evaluated_key
+            .as_ref()
+            .map(PageToken::encode)
+            .transpose()
+            .map_err(DdbError::PageToken)?;
+        Ok(Page { items, next })
+    }
+
+    /// Yields the items one at a time
+    ///
+    /// The next page is only requested once the consumer has polled past the items of the
+    /// current one, so memory use is bounded by the page size.
+    pub fn stream<'c, T: DeserializeOwned + 'c>(
+        self, client: &'c impl DdbClient,
+    ) -> impl Stream<Item = Result<T, DdbError>> + 'c {
+        stream::paginate(self.into_input(), move |mut input: QueryInput| async move {
+            let res = client.query(input.clone()).await?;
+            let next = res.last_evaluated_key.map(|key| {
+                input.exclusive_start_key = Some(key);
+                input
+            });
+            Ok((res.items.unwrap_or_default(), next))
+        })
+    }
+
+    fn into_input(self) -> Result<QueryInput, DdbError> {
+        self.attrs.apply(self.input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use futures::TryStreamExt;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Dataset {
+        pk: String,
+        sk: String,
+    }
+
+    fn dataset(sk: &str) -> Dataset {
+        Dataset { pk: "c4c".to_string(), sk: sk.to_string() }
+    }
+
+    async fn seeded() -> Result<MemoryDb, DdbError> {
+        let client = MemoryDb::new().table("relations", "pk", Some("sk"));
+        for sk in &["dataset#1", "dataset#2", "dataset#3", "owner#1"] {
+            put_item(&client, "relations", ddb_map! { "pk" => "c4c", "sk" => *sk }).await?;
+        }
+        put_item(&client, "relations", ddb_map! { "pk" => "c4d", "sk" => "dataset#1" }).await?;
+        Ok(client)
+    }
+
+    fn datasets() -> Query {
+        Query::new("relations")
+            .key_condition(attr("pk").eq("c4c").and(attr("sk").begins_with("dataset#")))
+    }
+
+    #[tokio::test]
+    async fn send_follows_pages_until_max_items() -> Result<(), DdbError> {
+        let client = seeded().await?;
+        let all: Vec<Dataset> = datasets().limit(1).send(&client).await?;
+        assert_eq!(all, vec![dataset("dataset#1"), dataset("dataset#2"), dataset("dataset#3")]);
+        let first: Vec<Dataset> = datasets().limit(1).max_items(2).send(&client).await?;
+        assert_eq!(first, vec![dataset("dataset#1"), dataset("dataset#2")]);
+        let first: Vec<Dataset> = datasets().max_items(1).send(&client).await?;
+        assert_eq!(first, vec![dataset("dataset#1")]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pages_continue_from_their_token() -> Result<(), DdbError> {
+        let client = seeded().await?;
+        let page: Page<Dataset> = datasets().limit(2).page(&client, None).await?;
+        assert_eq!(page.items, vec![dataset("dataset#1"), dataset("dataset#2")]);
+        let page: Page<Dataset> = datasets().limit(2).page(&client, page.next.as_ref()).await?;
+        assert_eq!(page.items, vec![dataset("dataset#3")]);
+        assert!(page.next.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn streams_items_across_pages() -> Result<(), DdbError> {
+        let client = seeded().await?;
+        let all: Vec<Dataset> = datasets().limit(2).stream(&client).try_collect().await?;
+        assert_eq!(all, vec![dataset("dataset#1"), dataset("dataset#2"), dataset("dataset#3")]);
+        let mut stream = Box::pin(datasets().limit(1).stream::<Dataset>(&client));
+        assert_eq!(stream.try_next().await?, Some(dataset("dataset#1")));
+        Ok(())
+    }
+
+    #[allow(deprecated)]
+    #[tokio::test]
+    async fn deprecated_functions_match_the_builder() -> Result<(), DdbError> {
+        let client = seeded().await?;
+        let mut values: DdbMap = HashMap::new();
+        set_kv(&mut values, ":pk".to_string(), "c4c".to_string());
+        set_kv(&mut values, ":prefix".to_string(), "dataset#".to_string());
+        let key_cond = || Some("pk = :pk AND begins_with(sk, :prefix)".to_string());
+        let first: Vec<Dataset> = query(
+            &client,
+            "relations",
+            None,
+            key_cond(),
+            Some(values.clone()),
+            None,
+            None,
+            None,
+            Some(2),
+        )
+        .await?;
+        assert_eq!(first, vec![dataset("dataset#1"), dataset("dataset#2")]);
+        let page: Page<Dataset> = query_page(
+            &client,
+            "relations",
+            None,
+            key_cond(),
+            Some(values.clone()),
+            None,
+            None,
+            None,
+            Some(2),
+            None,
+        )
+        .await?;
+        assert_eq!(page.items, first);
+        let all: Vec<Dataset> =
+            query_stream(&client, "relations", None, key_cond(), Some(values), None, None, None)
+                .try_collect()
+                .await?;
+        assert_eq!(all.len(), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn hand_written_placeholders_never_replace_generated_ones() -> Result<(), DdbError> {
+        let client = seeded().await?;
+        let mut values: DdbMap = HashMap::new();
+        set_kv(&mut values, ":v0".to_string(), "c4d");
+        let res = datasets().values(values).send::<Dataset>(&client).await;
+        assert!(matches!(res, Err(DdbError::Expression(_))));
+        let input =
+            datasets().filter(attr("sk").eq("x")).filter(attr("sk").ne("x")).into_input()?;
+        assert_eq!(input.filter_expression.as_deref(), Some("#n1 <> :v3"));
+        let mut placeholders: Vec<String> =
+            input.expression_attribute_values.unwrap().into_keys().collect();
+        placeholders.sort();
+        assert_eq!(placeholders, vec![":v0", ":v1", ":v3"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+use rand::Rng;
+use std::time::{Duration, Instant};
+
+/// How unprocessed items and retryable errors are retried
+///
+/// The delay before attempt `n + 1` is drawn uniformly from zero up to
+/// `min(max_delay, base_delay * 2^n)`, i.e. exponential backoff with full jitter. Retrying stops
+/// after `max_attempts` attempts in total, or when the next delay would end after `time_budget`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub time_budget: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(5),
+            time_budget: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Send every request once and hand back whatever was not processed
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Delay before the next attempt, or `None` when `attempts` attempts made since `start`
+    /// have used up the policy
+    pub(crate) fn backoff(&self, attempts: u32, start: Instant) -> Option<Duration> {
+        if attempts >= self.max_attempts {
+            return None;
+        }
+        let cap = self
+            .base_delay
+            .checked_mul(2u32.saturating_pow(attempts.min(31)))
+            .map_or(self.max_delay, |d| d.min(self.max_delay));
+        let delay = cap.mul_f64(rand::thread_rng().gen::<f64>());
+        match self.time_budget {
+            Some(budget) if start.elapsed() + delay > budget => None,
+            _ => Some(delay),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::RetryPolicy;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn backoff_is_capped_and_bounded_by_attempts_and_budget() {
+        let retry = RetryPolicy {
+            max_attempts: 40,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(100),
+            time_budget: None,
+        };
+        let start = Instant::now();
+        for attempts in 1..40 {
+            let cap = Duration::from_millis(10 * 2u64.pow(attempts.min(10))).min(retry.max_delay);
+            assert!(retry.backoff(attempts, start).unwrap() <= cap);
+        }
+        assert_eq!(retry.backoff(40, start), None);
+        assert_eq!(RetryPolicy::none().backoff(1, start), None);
+        let exhausted = RetryPolicy {
+            time_budget: Some(Duration::from_millis(0)),
+            ..retry
+        };
+        assert_eq!(exhausted.backoff(1, start - Duration::from_millis(1)), None);
+    }
+}
@@ -0,0 +1,379 @@
+use crate::expression::hand_written_attributes;
+use crate::{
+    from_item, DdbClient, DdbError, DdbMap, Expression, ExpressionAttributes, Page, PageToken,
+};
+use futures::channel::mpsc;
+use futures::future;
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use futures::SinkExt;
+use rusoto_dynamodb::ScanInput;
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// # Dynamodb scan builder
+/// Same ergonomics as `Query`, without the key condition. `send` and `stream` follow
+/// `LastEvaluatedKey` through the whole table. With `parallel_scan` the table is split into
+/// segments that are scanned concurrently on tokio tasks, at most `concurrency` of them at a
+/// time, and merged into one stream. `send` and `stream` clone the client into the tasks, so it
+/// has to be `Clone + 'static`, like `DynamoDbClient` or `MemoryDb`.
+/// ```
+/// # use rusoto_core::Region;
+/// # use rusoto_dynamodb::DynamoDbClient;
+/// # use futures::TryStreamExt;
+/// # use serde::Deserialize;
+/// # use ddb_util::*;
+///
+/// # #[derive(Debug, Deserialize)]
+/// # struct Dataset {
+/// #     pk: String,
+/// #     sk: String,
+/// # }
+///
+/// # #[cfg(feature = "local")]
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// # let local = match LocalDynamoDb::from_env()? {
+/// #     Some(local) => local,
+/// #     None => return Ok(()),
+/// # };
+/// # let relations = local.fixture("fixtures/relations.json").await?;
+/// # let relations = relations.as_str();
+/// let client = DynamoDbClient::new(Region::EuWest1);
+/// # let client = local.client().clone();
+/// let datasets: Vec<Dataset> = Scan::new(relations)
+///     .filter(attr("itemtype").eq("dataset"))
+///     .send(&client)
+///     .await?;
+/// let mut all = Box::pin(
+///     Scan::new(relations)
+///         .parallel_scan(8)
+///         .concurrency(4)
+///         .stream::<Dataset>(&client),
+/// );
+/// while let Some(dataset) = all.try_next().await? {
+///     println!("{:?}", dataset);
+/// }
+/// # local.teardown().await?;
+/// #     Ok(())
+/// # }
+/// # #[cfg(not(feature = "local"))]
+/// # fn main() {}
+/// ```
+#[derive(Clone, Debug)]
+pub struct Scan {
+    input: ScanInput,
+    attrs: ExpressionAttributes,
+    max_items: Option<usize>,
+    total_segments: Option<i64>,
+    concurrency: Option<usize>,
+}
+
+impl Scan {
+    pub fn new(table: &str) -> Scan {
+        Scan {
+            input: ScanInput {
+                table_name: table.to_string(),
+                ..Default::default()
+            },
+            attrs: ExpressionAttributes::default(),
+            max_items: None,
+            total_segments: None,
+            concurrency: None,
+        }
+    }
+
+    /// Scan a global or local secondary index instead of the table
+    pub fn index(mut self, index_name: &str) -> Self {
+        self.input.index_name = Some(index_name.to_string());
+        self
+    }
+
+    pub fn filter(mut self, filter_exp: impl Expression) -> Self {
+        self.input.filter_expression = Some(filter_exp.render(&mut self.attrs));
+        self
+    }
+
+    pub fn project(mut self, projection_exp: impl Expression) -> Self {
+        self.input.projection_expression = Some(projection_exp.render(&mut self.attrs));
+        self
+    }
+
+    hand_written_attributes!();
+
+    /// Maximum number of items evaluated per request, i.e. the page size
+    pub fn limit(mut self, limit: i64) -> Self {
+        self.input.limit = Some(limit);
+        self
+    }
+
+    /// Stop `send` once this many items have been collected
+    pub fn max_items(mut self, max_items: usize) -> Self {
+        self.max_items = Some(max_items);
+        self
+    }
+
+    pub fn consistent_read(mut self, consistent_read: bool) -> Self {
+        self.input.consistent_read = Some(consistent_read);
+        self
+    }
+
+    /// One of `ALL_ATTRIBUTES`, `ALL_PROJECTED_ATTRIBUTES`, `SPECIFIC_ATTRIBUTES` or `COUNT`
+    pub fn select(mut self, select: &str) -> Self {
+        self.input.select = Some(select.to_string());
+        self
+    }
+
+    /// One of `INDEXES`, `TOTAL` or `NONE`
+    pub fn return_consumed_capacity(mut self, return_consumed_capacity: &str) -> Self {
+        self.input.return_consumed_capacity = Some(return_consumed_capacity.to_string());
+        self
+    }
+
+    /// Scan only `segment` of `total_segments`, e.g. when the segments are spread over workers
+    pub fn segment(mut self, segment: i64, total_segments: i64) -> Self {
+        self.input.segment = Some(segment);
+        self.input.total_segments = Some(total_segments);
+        self
+    }
+
+    /// Split the scan into `total_segments` segments scanned on their own tasks, at least 1
+    pub fn parallel_scan(mut self, total_segments: i64) -> Self {
+        self.total_segments = Some(total_segments.max(1));
+        self
+    }
+
+    /// Maximum number of segments scanned at the same time, all of them by default, at least 1
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency.max(1));
+        self
+    }
+
+    /// Runs the scan and collects every page
+    pub async fn send<T: DeserializeOwned>(
+        self, client: &(impl DdbClient + Clone + 'static),
+    ) -> Result<Vec<T>, DdbError> {
+        let max_items = self.max_items.unwrap_or(usize::MAX);
+        self.stream(client).take(max_items).try_collect().await
+    }
+
+    /// Fetches a single page starting after `token`
+    ///
+    /// Pass the returned `next` token back in to continue, it is `None` on the last page.
+    /// `parallel_scan` is ignored here, use `segment` to page through one segment.
+    pub async fn page<T: DeserializeOwned>(
+        self, client: &impl DdbClient, token: Option<&PageToken>,
+    ) -> Result<Page<T>, DdbError> {
+        let mut scan_input = self.into_input()?;
+        scan_input.exclusive_start_key = token
+            .map(PageToken::decode)
+            .transpose()
+            .map_err(DdbError::PageToken)?;
+        let res = client.scan(scan_input).await?;
+        let items = res
+            .items
+            .unwrap_or_default()
+            .into_iter()
+            .map(from_item)
+            .collect::<Result<Vec<T>, _>>()?;
+        let next = res
+            .last_evaluated_key
+            .as_ref()
+            .map(PageToken::encode)
+            .transpose()
+            .map_err(DdbError::PageToken)?;
+        Ok(Page { items, next })
+    }
+
+    /// Yields the items one at a time
+    ///
+    /// A plain scan only requests its next page once the consumer has polled past the items of
+    /// the current one. With `parallel_scan` every segment is paged on its own tokio task, so a
+    /// tokio runtime is needed, and only runs a couple of pages ahead of the consumer. Items of
+    /// different segments arrive in no particular order. Dropping the stream stops the segments
+    /// after their current request.
+    pub fn stream<T: DeserializeOwned>(
+        self, client: &(impl DdbClient + Clone + 'static),
+    ) -> impl Stream<Item = Result<T, DdbError>> {
+        let total_segments = self.total_segments;
+        let concurrency = self.concurrency;
+        let pages = match (self.into_input(), total_segments) {
+            (Ok(scan_input), Some(total)) => {
+                spawn_segments(client.clone(), scan_input, total, concurrency).left_stream()
+            }
+            (scan_input, _) => segment_pages(client.clone(), scan_input).right_stream(),
+        };
+        pages
+            .map_ok(|items| stream::iter(items.into_iter().map(|item| Ok(from_item(item)?))))
+            .try_flatten()
+    }
+
+    fn into_input(self) -> Result<ScanInput, DdbError> {
+        self.attrs.apply(self.input)
+    }
+}
+
+/// The pages of one segment, each requested once the previous one has been consumed
+fn segment_pages<C: DdbClient>(
+    client: C, scan_input: Result<ScanInput, DdbError>,
+) -> impl Stream<Item = Result<Vec<DdbMap>, DdbError>> {
+    stream::try_unfold((client, Some(scan_input)), |(client, input)| async move {
+        let mut input = match input {
+            Some(input) => input?,
+            None => return Ok(None),
+        };
+        let res = client.scan(input.clone()).await?;
+        let next = res.last_evaluated_key.map(|key| {
+            input.exclusive_start_key = Some(key);
+            Ok(input)
+        });
+        Ok(Some((res.items.unwrap_or_default(), (client, next))))
+    })
+}
+
+/// Pages every segment on its own task, at most `concurrency` of them at a time, and forwards
+/// their pages through one channel
+fn spawn_segments<C: DdbClient + Clone + 'static>(
+    client: C, scan_input: ScanInput, total: i64, concurrency: Option<usize>,
+) -> impl Stream<Item = Result<Vec<DdbMap>, DdbError>> {
+    let permits = Arc::new(Semaphore::new(concurrency.unwrap_or(total as usize)));
+    let (pages, received) = mpsc::channel(0);
+    let tasks: Vec<_> = (0..total)
+        .map(|segment| {
+            let input = ScanInput {
+                segment: Some(segment),
+                total_segments: Some(total),
+                ..scan_input.clone()
+            };
+            let (client, mut pages, permits) = (client.clone(), pages.clone(), permits.clone());
+            tokio::spawn(async move {
+                let _permit = permits.acquire_owned().await;
+                let segment = segment_pages(client, Ok(input));
+                futures::pin_mut!(segment);
+                while !pages.is_closed() {
+                    match segment.next().await {
+                        Some(page) => {
+                            let failed = page.is_err();
+                            if pages.send(page).await.is_err() || failed {
+                                break;
+                            }
+                        }
+                        None => break,
+                    }
+                }
+            })
+        })
+        .collect();
+    // A panicking segment would otherwise end the stream early without an error
+    let panics = stream::once(future::join_all(tasks)).filter_map(|results| async move {
+        for result in results {
+            if let Err(e) = result {
+                if e.is_panic() {
+                    std::panic::resume_unwind(e.into_panic());
+                }
+            }
+        }
+        None
+    });
+    received.chain(panics)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::probe::Probe;
+    use crate::*;
+    use futures::TryStreamExt;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Dataset {
+        pk: String,
+        sk: String,
+    }
+
+    async fn seeded() -> Result<Probe, DdbError> {
+        let db = MemoryDb::new().table("relations", "pk", Some("sk"));
+        for i in 0..12 {
+            put_item(&db, "relations", ddb_map! { "pk" => format!("c4c{}", i), "sk" => "dataset" })
+                .await?;
+        }
+        Ok(Probe::new(db))
+    }
+
+    #[tokio::test]
+    async fn parallel_scan_requests_every_segment() -> Result<(), DdbError> {
+        let client = seeded().await?;
+        let datasets: Vec<Dataset> = Scan::new("relations").parallel_scan(4).send(&client).await?;
+        assert_eq!(datasets.len(), 12);
+        let state = client.state();
+        let mut segments: Vec<_> = state.scans.iter().map(|s| s.segment.unwrap()).collect();
+        segments.sort_unstable();
+        assert_eq!(segments, vec![0, 1, 2, 3]);
+        assert!(state.scans.iter().all(|s| s.total_segments == Some(4)));
+        assert_eq!(state.max_in_flight, 4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn concurrency_caps_the_segments_in_flight() -> Result<(), DdbError> {
+        let client = seeded().await?;
+        let datasets: Vec<Dataset> =
+            Scan::new("relations").parallel_scan(4).concurrency(2).send(&client).await?;
+        assert_eq!(datasets.len(), 12);
+        assert_eq!(client.state().scans.len(), 4);
+        assert_eq!(client.state().max_in_flight, 2);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn dropping_the_stream_stops_the_segments() -> Result<(), DdbError> {
+        let client = seeded().await?;
+        let mut datasets =
+            Box::pin(Scan::new("relations").limit(1).parallel_scan(2).stream::<Dataset>(&client));
+        datasets.try_next().await?;
+        drop(datasets);
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        let scans = client.state().scans.len();
+        assert!(scans < 12, "{} scans", scans);
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(client.state().scans.len(), scans);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn max_items_stops_requesting_pages() -> Result<(), DdbError> {
+        let client = seeded().await?;
+        let datasets: Vec<Dataset> =
+            Scan::new("relations").limit(2).max_items(5).send(&client).await?;
+        assert_eq!(datasets.len(), 5);
+        assert_eq!(client.state().scans.len(), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn segments_below_one_scan_the_whole_table() -> Result<(), DdbError> {
+        let client = seeded().await?;
+        let datasets: Vec<Dataset> =
+            Scan::new("relations").parallel_scan(0).concurrency(0).send(&client).await?;
+        assert_eq!(datasets.len(), 12);
+        assert_eq!(client.state().scans[0].total_segments, Some(1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[allow(deprecated)]
+    async fn deprecated_scan_stream_matches_the_builder() -> Result<(), DdbError> {
+        let client = seeded().await?;
+        let filter = Some("sk = :sk".to_string());
+        let values = Some(ddb_map! { ":sk" => "dataset" });
+        let streamed: Vec<Dataset> =
+            scan_stream(&client, "relations", None, values, None, None, filter)
+                .try_collect()
+                .await?;
+        let sent: Vec<Dataset> =
+            Scan::new("relations").filter(attr("sk").eq("dataset")).send(&client).await?;
+        assert_eq!(streamed, sent);
+        assert_eq!(sent.len(), 12);
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+use crate::key::{describe_key_schema, KeySchema};
+use crate::{DdbClient, DdbError};
+use async_trait::async_trait;
+use rusoto_core::RusotoError;
+use rusoto_dynamodb::{
+    BatchGetItemError, BatchGetItemInput, BatchGetItemOutput, BatchWriteItemError,
+    BatchWriteItemInput, BatchWriteItemOutput, DeleteItemError, DeleteItemInput, DeleteItemOutput,
+    DescribeTableError, DescribeTableInput, DescribeTableOutput, GetItemError, GetItemInput,
+    GetItemOutput, PutItemError, PutItemInput, PutItemOutput, QueryError, QueryInput,
+    QueryOutput, ScanError, ScanInput, ScanOutput, TransactGetItemsError, TransactGetItemsInput,
+    TransactGetItemsOutput, TransactWriteItemsError, TransactWriteItemsInput,
+    TransactWriteItemsOutput, UpdateItemError, UpdateItemInput, UpdateItemOutput,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+type Schemas = Arc<Mutex<HashMap<String, KeySchema>>>;
+
+/// # Key schema cache
+/// A `DdbClient` that describes the key schema of each table once, for the key validation of
+/// `get_item`, `delete_item` and `Key::validated`. Without it every validation describes the
+/// table again. The cache belongs to the wrapped client and is shared by its clones, wrap one
+/// client per endpoint. Every other operation is passed on to the wrapped client.
+/// ```
+/// # use rusoto_core::Region;
+/// # use rusoto_dynamodb::DynamoDbClient;
+/// # use serde::Deserialize;
+/// # use ddb_util::*;
+///
+/// # #[derive(Debug, Deserialize)]
+/// # struct Dataset {
+/// #     pk: String,
+/// #     sk: String,
+/// # }
+///
+/// # #[cfg(feature = "local")]
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// # let local = match LocalDynamoDb::from_env()? {
+/// #     Some(local) => local,
+/// #     None => return Ok(()),
+/// # };
+/// # let relations = local.fixture("fixtures/relations.json").await?;
+/// # let relations = relations.as_str();
+/// let client = KeySchemaCache::new(DynamoDbClient::new(Region::EuWest1));
+/// # let client = KeySchemaCache::new(local.client().clone());
+/// let key = Key::hash_range("pk", "c4c", "sk", "dataset#1");
+/// let x: Option<Dataset> = get_item(&client, relations, key.clone(), false, None).await?;
+/// let x: Option<Dataset> = delete_item(&client, relations, key, None).await?;
+/// # local.teardown().await?;
+/// #     Ok(())
+/// # }
+/// # #[cfg(not(feature = "local"))]
+/// # fn main() {}
+/// ```
+#[derive(Clone, Debug)]
+pub struct KeySchemaCache<C> {
+    client: C,
+    schemas: Schemas,
+}
+
+impl<C: DdbClient> KeySchemaCache<C> {
+    pub fn new(client: C) -> KeySchemaCache<C> {
+        KeySchemaCache {
+            client,
+            schemas: Schemas::default(),
+        }
+    }
+
+    pub fn client(&self) -> &C {
+        &self.client
+    }
+}
+
+#[async_trait]
+impl<C: DdbClient> DdbClient for KeySchemaCache<C> {
+    async fn get_item(
+        &self, input: GetItemInput,
+    ) -> Result<GetItemOutput, RusotoError<GetItemError>> {
+        self.client.get_item(input).await
+    }
+
+    async fn put_item(
+        &self, input: PutItemInput,
+    ) -> Result<PutItemOutput, RusotoError<PutItemError>> {
+        self.client.put_item(input).await
+    }
+
+    async fn update_item(
+        &self, input: UpdateItemInput,
+    ) -> Result<UpdateItemOutput, RusotoError<UpdateItemError>> {
+        self.client.update_item(input).await
+    }
+
+    async fn delete_item(
+        &self, input: DeleteItemInput,
+    ) -> Result<DeleteItemOutput, RusotoError<DeleteItemError>> {
+        self.client.delete_item(input).await
+    }
+
+    async fn query(&self, input: QueryInput) -> Result<QueryOutput, RusotoError<QueryError>> {
+        self.client.query(input).await
+    }
+
+    async fn scan(&self, input: ScanInput) -> Result<ScanOutput, RusotoError<ScanError>> {
+        self.client.scan(input).await
+    }
+
+    async fn batch_get_item(
+        &self, input: BatchGetItemInput,
+    ) -> Result<BatchGetItemOutput, RusotoError<BatchGetItemError>> {
+        self.client.batch_get_item(input).await
+    }
+
+    async fn batch_write_item(
+        &self, input: BatchWriteItemInput,
+    ) -> Result<BatchWriteItemOutput, RusotoError<BatchWriteItemError>> {
+        self.client.batch_write_item(input).await
+    }
+
+    async fn transact_get_items(
+        &self, input: TransactGetItemsInput,
+    ) -> Result<TransactGetItemsOutput, RusotoError<TransactGetItemsError>> {
+        self.client.transact_get_items(input).await
+    }
+
+    async fn transact_write_items(
+        &self, input: TransactWriteItemsInput,
+    ) -> Result<TransactWriteItemsOutput, RusotoError<TransactWriteItemsError>> {
+        self.client.transact_write_items(input).await
+    }
+
+    async fn describe_table(
+        &self, input: DescribeTableInput,
+    ) -> Result<DescribeTableOutput, RusotoError<DescribeTableError>> {
+        self.client.describe_table(input).await
+    }
+
+    async fn key_schema(&self, table: &str) -> Result<KeySchema, DdbError> {
+        if let Some(schema) = self.schemas.lock().unwrap_or_else(|e| e.into_inner()).get(table) {
+            return Ok(schema.clone());
+        }
+        let schema = describe_key_schema(&self.client, table).await?;
+        let mut schemas = self.schemas.lock().unwrap_or_else(|e| e.into_inner());
+        schemas.insert(table.to_string(), schema.clone());
+        Ok(schema)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[tokio::test]
+    async fn schemas_are_cached_per_client_and_table() -> Result<(), DdbError> {
+        let db = MemoryDb::new().table("relations", "pk", Some("sk"));
+        let client = KeySchemaCache::new(db.clone());
+        let key = Key::hash_range("pk", "c4c", "sk", "dataset#1");
+        key.clone().validated(&client, "relations").await?;
+        let db = db.table("relations", "id", None);
+        key.clone().validated(&client.clone(), "relations").await?;
+        assert!(key.validated(&db, "relations").await.is_err());
+        let other = KeySchemaCache::new(db);
+        assert!(Key::hash("id", "c4c").validated(&other, "relations").await.is_ok());
+        assert!(Key::hash("id", "c4c").validated(&client, "relations").await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,71 @@
+use crate::{from_item, DdbError, DdbMap};
+use futures::stream::{self, Stream, TryStreamExt};
+use serde::de::DeserializeOwned;
+use std::future::Future;
+
+/// Turns a paginated request into a stream of deserialized items
+///
+/// `fetch` sends the request `input` and returns the items of that page together with the
+/// request for the next page, if there is one. A page is only fetched once the items of the
+/// previous page have been consumed. When the first request could not be built, its error is
+/// the only item of the stream.
+pub(crate) fn paginate<'c, T, I, F, Fut>(
+    input: Result<I, DdbError>, mut fetch: F,
+) -> impl Stream<Item = Result<T, DdbError>> + 'c
+where
+    T: DeserializeOwned + 'c,
+    I: 'c,
+    F: FnMut(I) -> Fut + 'c,
+    Fut: Future<Output = Result<(Vec<DdbMap>, Option<I>), DdbError>> + 'c,
+{
+    stream::try_unfold(Some(input), move |input| {
+        let page = input.map(|input| input.map(&mut fetch));
+        async move {
+            let (items, next) = match page {
+                Some(page) => page?.await?,
+                None => return Ok::<_, DdbError>(None),
+            };
+            let items = items
+                .into_iter()
+                .map(|item| from_item(item).map_err(DdbError::from));
+            Ok(Some((stream::iter(items), next.map(Ok))))
+        }
+    })
+    .try_flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stream::paginate;
+    use crate::*;
+    use futures::TryStreamExt;
+    use serde::Deserialize;
+    use std::cell::Cell;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Dataset {
+        pk: String,
+    }
+
+    #[tokio::test]
+    async fn paginate_fetches_lazily() -> Result<(), DdbError> {
+        let fetched = Cell::new(0);
+        let pages = paginate(Ok(0), |page: usize| {
+            fetched.set(fetched.get() + 1);
+            async move {
+                let mut item: DdbMap = HashMap::new();
+                set_kv(&mut item, "pk".to_string(), format!("c4c{}", page));
+                let next = if page < 2 { Some(page + 1) } else { None };
+                Ok((vec![item], next))
+            }
+        });
+        futures::pin_mut!(pages);
+        let first: Option<Dataset> = pages.try_next().await?;
+        assert_eq!(first.unwrap().pk, "c4c0");
+        assert_eq!(fetched.get(), 1);
+        let rest: Vec<Dataset> = pages.try_collect().await?;
+        assert_eq!(rest.len(), 2);
+        assert_eq!(fetched.get(), 3);
+        Ok(())
+    }
+}
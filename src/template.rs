@@ -0,0 +1,318 @@
+use crate::{attr, Condition, DdbError};
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Unexpected, Visitor};
+use serde::{forward_to_deserialize_any, Deserializer, Serialize};
+use serde_json::Value;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Literal(String),
+    Field(String),
+}
+
+/// # Composite key template
+/// A key pattern like `"USER#{user_id}"` or `"ORDER#{date}#{order_id}"` for single table
+/// designs. `format` fills the fields from anything that serializes to a map, e.g. a struct or
+/// a `serde_json::json!` object, and `parse` reads a key back into a struct whose fields are
+/// parsed from the key segments. `prefix` formats the pattern up to the first field that has no
+/// value, for `begins_with` sort key conditions. Two fields have to be separated by a literal,
+/// and a value must not contain the literal that follows its field, otherwise a key could not
+/// be split back into its fields.
+/// ```
+/// # use serde::{Deserialize, Serialize};
+/// # use serde_json::json;
+/// # use ddb_util::*;
+///
+/// #[derive(Debug, Deserialize, Serialize, PartialEq)]
+/// struct OrderKey {
+///     date: String,
+///     order_id: u64,
+/// }
+///
+/// # fn main() -> Result<(), DdbError> {
+/// let order = KeyTemplate::new("ORDER#{date}#{order_id}")?;
+/// let key = OrderKey {
+///     date: "2021-10-01".to_string(),
+///     order_id: 987,
+/// };
+/// assert_eq!(order.format(&key)?, "ORDER#2021-10-01#987");
+/// assert_eq!(order.parse::<OrderKey>("ORDER#2021-10-01#987")?, key);
+/// assert_eq!(order.prefix(&json!({ "date": "2021-10-01" }))?, "ORDER#2021-10-01#");
+/// let orders_of_the_day = order.begins_with("sk", &json!({ "date": "2021-10-01" }))?;
+/// #     Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyTemplate {
+    pattern: String,
+    parts: Vec<Part>,
+}
+
+impl KeyTemplate {
+    pub fn new(pattern: &str) -> Result<KeyTemplate, DdbError> {
+        let invalid = |reason: &str| DdbError::KeyTemplate(format!("{}: {}", pattern, reason));
+        let mut parts = Vec::new();
+        let mut rest = pattern;
+        while !rest.is_empty() {
+            match rest.find('{') {
+                Some(0) => {
+                    let end = rest.find('}').ok_or_else(|| invalid("unclosed {"))?;
+                    let field = &rest[1..end];
+                    if field.is_empty() || field.contains('{') {
+                        return Err(invalid("expected a field name between { and }"));
+                    }
+                    if let Some(Part::Field(_)) = parts.last() {
+                        return Err(invalid("fields have to be separated by a literal"));
+                    }
+                    parts.push(Part::Field(field.to_string()));
+                    rest = &rest[end + 1..];
+                }
+                found => {
+                    let end = found.unwrap_or(rest.len());
+                    if rest[..end].contains('}') {
+                        return Err(invalid("unopened }"));
+                    }
+                    parts.push(Part::Literal(rest[..end].to_string()));
+                    rest = &rest[end..];
+                }
+            }
+        }
+        Ok(KeyTemplate {
+            pattern: pattern.to_string(),
+            parts,
+        })
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Names of the fields in the order they appear in the pattern
+    pub fn fields(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Field(field) => Some(field.as_str()),
+            Part::Literal(_) => None,
+        })
+    }
+
+    /// Formats the key, failing when a field has no value or a value contains the literal that
+    /// follows its field
+    pub fn format<T: Serialize>(&self, values: &T) -> Result<String, DdbError> {
+        let (key, missing) = self.render(values)?;
+        match missing {
+            Some(field) => Err(self.error(format!("no value for {}", field))),
+            None => Ok(key),
+        }
+    }
+
+    /// Formats the key up to the first field without a value, including the literal that
+    /// follows the last field with a value
+    pub fn prefix<T: Serialize>(&self, values: &T) -> Result<String, DdbError> {
+        self.render(values).map(|(prefix, _)| prefix)
+    }
+
+    /// `begins_with(path, prefix)` with the prefix of `values`
+    pub fn begins_with<T: Serialize>(&self, path: &str, values: &T) -> Result<Condition, DdbError> {
+        Ok(attr(path).begins_with(self.prefix(values)?))
+    }
+
+    /// Splits `key` into the fields of the pattern and deserializes them as a map into `T`
+    ///
+    /// Numbers and booleans are parsed from the key segments, every other type gets the
+    /// segment as a string.
+    pub fn parse<T: DeserializeOwned>(&self, key: &str) -> Result<T, DdbError> {
+        let segments = self
+            .split(key)
+            .ok_or_else(|| self.error(format!("{} does not match the pattern", key)))?;
+        let map = de::value::MapDeserializer::new(
+            segments.into_iter().map(|(field, segment)| (field, Segment(segment))),
+        );
+        T::deserialize(map).map_err(|e: de::value::Error| self.error(format!("{}: {}", key, e)))
+    }
+
+    /// The fields of `key` with the segments they matched, `None` when `key` does not match
+    fn split<'k>(&self, key: &'k str) -> Option<Vec<(&str, &'k str)>> {
+        let mut segments = Vec::new();
+        let mut rest = key;
+        let mut parts = self.parts.iter().peekable();
+        while let Some(part) = parts.next() {
+            match part {
+                Part::Literal(literal) => rest = rest.strip_prefix(literal.as_str())?,
+                Part::Field(field) => {
+                    let end = match parts.peek() {
+                        Some(Part::Literal(next)) => rest.find(next.as_str())?,
+                        _ => rest.len(),
+                    };
+                    segments.push((field.as_str(), &rest[..end]));
+                    rest = &rest[end..];
+                }
+            }
+        }
+        if rest.is_empty() {
+            Some(segments)
+        } else {
+            None
+        }
+    }
+
+    /// The key up to the first field without a value, and the name of that field
+    fn render<T: Serialize>(&self, values: &T) -> Result<(String, Option<&str>), DdbError> {
+        let values = match serde_json::to_value(values) {
+            Ok(Value::Object(values)) => values,
+            Ok(_) => return Err(self.error("the values have to serialize to a map".to_string())),
+            Err(e) => return Err(self.error(e.to_string())),
+        };
+        let mut key = String::new();
+        let mut parts = self.parts.iter().peekable();
+        while let Some(part) = parts.next() {
+            match part {
+                Part::Literal(literal) => key.push_str(literal),
+                Part::Field(field) => {
+                    let value = match values.get(field) {
+                        Some(Value::String(s)) => s.clone(),
+                        Some(Value::Number(n)) => n.to_string(),
+                        Some(Value::Bool(b)) => b.to_string(),
+                        Some(Value::Null) | None => return Ok((key, Some(field))),
+                        Some(_) => {
+                            return Err(
+                                self.error(format!("{} is not a string, number or bool", field))
+                            )
+                        }
+                    };
+                    if let Some(Part::Literal(next)) = parts.peek() {
+                        if value.contains(next.as_str()) {
+                            return Err(self.error(format!("{} contains {}", field, next)));
+                        }
+                    }
+                    key.push_str(&value);
+                }
+            }
+        }
+        Ok((key, None))
+    }
+
+    fn error(&self, reason: String) -> DdbError {
+        DdbError::KeyTemplate(format!("{}: {}", self.pattern, reason))
+    }
+}
+
+/// One key segment, deserialized as a string or parsed into a number or bool
+struct Segment<'k>(&'k str);
+
+macro_rules! parse_segment {
+    ($($method:ident => $visit:ident($t:ty)),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                match self.0.parse::<$t>() {
+                    Ok(value) => visitor.$visit(value),
+                    Err(_) => Err(de::Error::invalid_value(Unexpected::Str(self.0), &visitor)),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de, 'k> Deserializer<'de> for Segment<'k> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_str(self.0)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    parse_segment!(
+        deserialize_bool => visit_bool(bool),
+        deserialize_i8 => visit_i8(i8),
+        deserialize_i16 => visit_i16(i16),
+        deserialize_i32 => visit_i32(i32),
+        deserialize_i64 => visit_i64(i64),
+        deserialize_i128 => visit_i128(i128),
+        deserialize_u8 => visit_u8(u8),
+        deserialize_u16 => visit_u16(u16),
+        deserialize_u32 => visit_u32(u32),
+        deserialize_u64 => visit_u64(u64),
+        deserialize_u128 => visit_u128(u128),
+        deserialize_f32 => visit_f32(f32),
+        deserialize_f64 => visit_f64(f64)
+    );
+
+    forward_to_deserialize_any! {
+        char str string bytes byte_buf unit unit_struct newtype_struct seq tuple tuple_struct
+        map struct enum identifier ignored_any
+    }
+}
+
+impl<'de, 'k> IntoDeserializer<'de, de::value::Error> for Segment<'k> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct OrderKey {
+        date: String,
+        order_id: u64,
+        express: Option<bool>,
+    }
+
+    #[test]
+    fn parses_typed_fields_from_keys() {
+        let order = KeyTemplate::new("ORDER#{date}#{order_id}#{express}").unwrap();
+        assert_eq!(order.fields().collect::<Vec<_>>(), vec!["date", "order_id", "express"]);
+        assert_eq!(
+            order.parse::<OrderKey>("ORDER#2021-10-01#987#true").unwrap(),
+            OrderKey {
+                date: "2021-10-01".to_string(),
+                order_id: 987,
+                express: Some(true),
+            }
+        );
+        assert!(order.parse::<OrderKey>("ORDER#2021-10-01#abc#true").is_err());
+        assert!(order.parse::<OrderKey>("USER#2021-10-01#987#true").is_err());
+    }
+
+    #[test]
+    fn formats_keys_and_prefixes() {
+        let order = KeyTemplate::new("ORDER#{date}#{order_id}").unwrap();
+        let values = json!({ "date": "2021-10-01", "order_id": 987 });
+        assert_eq!(order.format(&values).unwrap(), "ORDER#2021-10-01#987");
+        assert!(order.format(&json!({ "date": "2021-10-01" })).is_err());
+        assert_eq!(order.prefix(&json!({})).unwrap(), "ORDER#");
+        let cond = order.begins_with("sk", &json!({ "date": "2021-10-01" })).unwrap();
+        let mut attrs = ExpressionAttributes::default();
+        assert_eq!(cond.render(&mut attrs), "begins_with(#n0, :v0)");
+        assert_eq!(attrs.values[":v0"].s.as_deref(), Some("ORDER#2021-10-01#"));
+    }
+
+    #[test]
+    fn formatted_keys_parse_back() {
+        let order = KeyTemplate::new("ORDER#{date}#{order_id}").unwrap();
+        let values = json!({ "date": "2021-10", "order_id": 987 });
+        let key = order.format(&values).unwrap();
+        let parsed: serde_json::Value = order.parse(&key).unwrap();
+        assert_eq!(parsed, json!({ "date": "2021-10", "order_id": "987" }));
+        let ambiguous = json!({ "date": "2021#10", "order_id": 987 });
+        assert!(matches!(order.format(&ambiguous), Err(DdbError::KeyTemplate(_))));
+        assert!(order.prefix(&json!({ "date": "2021#10" })).is_err());
+        let last = json!({ "date": "2021-10", "order_id": "987#1" });
+        assert_eq!(order.format(&last).unwrap(), "ORDER#2021-10#987#1");
+    }
+
+    #[test]
+    fn rejects_ambiguous_patterns() {
+        assert!(KeyTemplate::new("USER#{user_id}{name}").is_err());
+        assert!(KeyTemplate::new("USER#{user_id").is_err());
+        assert!(KeyTemplate::new("USER#user_id}").is_err());
+        assert!(KeyTemplate::new("USER#{}").is_err());
+    }
+}
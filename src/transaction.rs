@@ -0,0 +1,336 @@
+use crate::{to_item, DdbClient, DdbError, DdbMap, Expression, ExpressionAttributes};
+use rusoto_dynamodb::{
+    ConditionCheck, Delete, Put, TransactWriteItem, TransactWriteItemsInput,
+    TransactWriteItemsOutput, Update,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Most operations DynamoDB accepts in one transaction
+pub(crate) const MAX_TRANSACT_ITEMS: usize = 100;
+
+/// Why one operation of a canceled transaction failed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CancellationReason {
+    ConditionalCheckFailed,
+    ItemCollectionSizeLimitExceeded,
+    TransactionConflict,
+    ProvisionedThroughputExceeded,
+    ThrottlingError,
+    ValidationError,
+    Other(String),
+}
+
+impl CancellationReason {
+    fn from_code(code: &str) -> Option<CancellationReason> {
+        Some(match code {
+            "None" => return None,
+            "ConditionalCheckFailed" => CancellationReason::ConditionalCheckFailed,
+            "ItemCollectionSizeLimitExceeded" => {
+                CancellationReason::ItemCollectionSizeLimitExceeded
+            }
+            "TransactionConflict" => CancellationReason::TransactionConflict,
+            "ProvisionedThroughputExceeded" => CancellationReason::ProvisionedThroughputExceeded,
+            "ThrottlingError" => CancellationReason::ThrottlingError,
+            "ValidationError" => CancellationReason::ValidationError,
+            other => CancellationReason::Other(other.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for CancellationReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CancellationReason::Other(code) => write!(f, "{}", code),
+            reason => write!(f, "{:?}", reason),
+        }
+    }
+}
+
+/// Parses the reasons out of a `TransactionCanceledException` message
+///
+/// rusoto only keeps the message, which ends with one code per operation, e.g.
+/// `... specific reasons [None, ConditionalCheckFailed]`.
+pub(crate) fn cancellation_reasons(message: &str) -> Option<Vec<Option<CancellationReason>>> {
+    let start = message.rfind('[')?;
+    let end = start + message[start..].find(']')?;
+    Some(
+        message[start + 1..end]
+            .split(',')
+            .map(|code| CancellationReason::from_code(code.trim()))
+            .collect(),
+    )
+}
+
+#[derive(Debug)]
+enum Operation {
+    Put(DdbMap),
+    Update(DdbMap, String),
+    Delete(DdbMap),
+    ConditionCheck(DdbMap),
+}
+
+#[derive(Debug)]
+struct TransactOperation {
+    table: String,
+    operation: Operation,
+    condition: Option<String>,
+    attrs: ExpressionAttributes,
+}
+
+impl TransactOperation {
+    fn into_item(self) -> Result<TransactWriteItem, DdbError> {
+        let attrs = self.attrs;
+        let table_name = self.table;
+        Ok(match self.operation {
+            Operation::Put(item) => TransactWriteItem {
+                put: Some(attrs.apply(Put {
+                    table_name,
+                    item,
+                    condition_expression: self.condition,
+                    ..Default::default()
+                })?),
+                ..Default::default()
+            },
+            Operation::Update(key, update_expression) => TransactWriteItem {
+                update: Some(attrs.apply(Update {
+                    table_name,
+                    key,
+                    update_expression,
+                    condition_expression: self.condition,
+                    ..Default::default()
+                })?),
+                ..Default::default()
+            },
+            Operation::Delete(key) => TransactWriteItem {
+                delete: Some(attrs.apply(Delete {
+                    table_name,
+                    key,
+                    condition_expression: self.condition,
+                    ..Default::default()
+                })?),
+                ..Default::default()
+            },
+            Operation::ConditionCheck(key) => TransactWriteItem {
+                condition_check: Some(attrs.apply(ConditionCheck {
+                    table_name,
+                    key,
+                    condition_expression: self.condition.unwrap_or_default(),
+                    ..Default::default()
+                })?),
+                ..Default::default()
+            },
+        })
+    }
+}
+
+/// # Dynamodb transact write builder
+/// Collects Put, Update, Delete and ConditionCheck operations, on one or more tables, that
+/// succeed or fail together. `condition`, `values` and `names` apply to the operation added
+/// last. When the transaction is canceled the error is `DdbError::TransactionCanceled` with one
+/// entry per operation, in the order they were added, `None` for the operations that did not
+/// cause the cancellation. More than 100 operations fail with `DdbError::TransactionTooLarge`
+/// before anything is sent.
+/// ```
+/// # use rusoto_core::Region;
+/// # use rusoto_dynamodb::DynamoDbClient;
+/// # use serde::Serialize;
+/// # use std::collections::HashMap;
+/// # use ddb_util::*;
+///
+/// # #[derive(Debug, Serialize)]
+/// # struct Dataset {
+/// #     pk: String,
+/// #     sk: String,
+/// # }
+///
+/// # #[cfg(feature = "local")]
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// # let local = match LocalDynamoDb::from_env()? {
+/// #     Some(local) => local,
+/// #     None => return Ok(()),
+/// # };
+/// # let relations = local.fixture("fixtures/relations.json").await?;
+/// # let relations = relations.as_str();
+/// let client = DynamoDbClient::new(Region::EuWest1);
+/// # let client = local.client().clone();
+/// let mut old_key: DdbMap = HashMap::new();
+/// set_kv(&mut old_key, "pk".to_string(), "c4c".to_string());
+/// set_kv(&mut old_key, "sk".to_string(), "dataset#1".to_string());
+/// let moved = Dataset {
+///     pk: "c4d".to_string(),
+///     sk: "dataset#1".to_string(),
+/// };
+/// let res = Transaction::new()
+///     .delete(relations, old_key)
+///     .condition(attr("pk").attribute_exists())
+///     .put(relations, &moved)
+///     .condition(attr("pk").attribute_not_exists())
+///     .send(&client)
+///     .await;
+/// if let Err(DdbError::TransactionCanceled(reasons)) = &res {
+///     for (i, reason) in reasons.iter().enumerate() {
+///         if let Some(reason) = reason {
+///             println!("operation {} failed: {}", i, reason);
+///         }
+///     }
+/// }
+/// # local.teardown().await?;
+/// #     Ok(())
+/// # }
+/// # #[cfg(not(feature = "local"))]
+/// # fn main() {}
+/// ```
+#[derive(Debug, Default)]
+pub struct Transaction {
+    operations: Vec<TransactOperation>,
+    client_request_token: Option<String>,
+    error: Option<DdbError>,
+}
+
+impl Transaction {
+    pub fn new() -> Transaction {
+        Transaction::default()
+    }
+
+    fn push(mut self, table: &str, operation: Operation) -> Self {
+        self.operations.push(TransactOperation {
+            table: table.to_string(),
+            operation,
+            condition: None,
+            attrs: ExpressionAttributes::default(),
+        });
+        self
+    }
+
+    /// Puts `item`, a serialization error is returned by `send`
+    pub fn put<T: Serialize>(mut self, table: &str, item: &T) -> Self {
+        match to_item(item) {
+            Ok(item) => self.push(table, Operation::Put(item)),
+            Err(e) => {
+                self.error.get_or_insert(DdbError::Serde(e));
+                self
+            }
+        }
+    }
+
+    pub fn update(mut self, table: &str, key: DdbMap, update_exp: impl Expression) -> Self {
+        let mut attrs = ExpressionAttributes::default();
+        let update_exp = update_exp.render(&mut attrs);
+        self = self.push(table, Operation::Update(key, update_exp));
+        if let Some(op) = self.operations.last_mut() {
+            op.attrs = attrs;
+        }
+        self
+    }
+
+    pub fn delete(self, table: &str, key: DdbMap) -> Self {
+        self.push(table, Operation::Delete(key))
+    }
+
+    /// Checks `condition_exp` on the item with `key` without writing it
+    pub fn condition_check(self, table: &str, key: DdbMap, condition_exp: impl Expression) -> Self {
+        self.push(table, Operation::ConditionCheck(key)).condition(condition_exp)
+    }
+
+    /// Condition of the operation added last
+    pub fn condition(mut self, condition_exp: impl Expression) -> Self {
+        if let Some(op) = self.operations.last_mut() {
+            op.condition = Some(condition_exp.render(&mut op.attrs));
+        }
+        self
+    }
+
+    /// Adds expression attribute values to the operation added last, for hand written
+    /// expressions
+    pub fn values(mut self, exp_attr_vals: DdbMap) -> Self {
+        if let Some(op) = self.operations.last_mut() {
+            op.attrs.add_values(exp_attr_vals);
+        }
+        self
+    }
+
+    /// Adds expression attribute names to the operation added last, for hand written
+    /// expressions
+    pub fn names(mut self, exp_attr_names: HashMap<String, String>) -> Self {
+        if let Some(op) = self.operations.last_mut() {
+            op.attrs.add_names(exp_attr_names);
+        }
+        self
+    }
+
+    /// Makes the transaction idempotent, resending it with the same token within ten minutes
+    /// does not apply it twice
+    pub fn client_request_token(mut self, token: &str) -> Self {
+        self.client_request_token = Some(token.to_string());
+        self
+    }
+
+    pub async fn send(self, client: &impl DdbClient) -> Result<TransactWriteItemsOutput, DdbError> {
+        let input = self.into_input()?;
+        Ok(client.transact_write_items(input).await?)
+    }
+
+    fn into_input(self) -> Result<TransactWriteItemsInput, DdbError> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        if self.operations.len() > MAX_TRANSACT_ITEMS {
+            return Err(DdbError::TransactionTooLarge(self.operations.len()));
+        }
+        Ok(TransactWriteItemsInput {
+            transact_items: self
+                .operations
+                .into_iter()
+                .map(TransactOperation::into_item)
+                .collect::<Result<_, _>>()?,
+            client_request_token: self.client_request_token,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::cancellation_reasons;
+    use crate::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn parses_cancellation_reasons_per_operation() {
+        let message = "Transaction cancelled, please refer cancellation reasons for specific \
+                       reasons [None, ConditionalCheckFailed, SomethingNew]";
+        assert_eq!(
+            cancellation_reasons(message),
+            Some(vec![
+                None,
+                Some(CancellationReason::ConditionalCheckFailed),
+                Some(CancellationReason::Other("SomethingNew".to_string())),
+            ])
+        );
+        assert_eq!(cancellation_reasons("Transaction cancelled"), None);
+    }
+
+    #[test]
+    fn conditions_render_into_their_own_operation() {
+        let mut key: DdbMap = HashMap::new();
+        set_kv(&mut key, "pk".to_string(), "c4c".to_string());
+        let input = Transaction::new()
+            .delete("relations", key.clone())
+            .condition(attr("status").eq("done"))
+            .condition_check("datasets", key, attr("status").eq("open"))
+            .into_input()
+            .unwrap();
+        let delete = input.transact_items[0].delete.as_ref().unwrap();
+        let check = input.transact_items[1].condition_check.as_ref().unwrap();
+        assert_eq!(delete.condition_expression.as_deref(), Some("#n0 = :v0"));
+        assert_eq!(check.condition_expression, "#n0 = :v0");
+        assert_eq!(check.table_name, "datasets");
+        assert_eq!(
+            check.expression_attribute_values.as_ref().unwrap()[":v0"].s.as_deref(),
+            Some("open")
+        );
+    }
+}
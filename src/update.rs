@@ -0,0 +1,167 @@
+use crate::expression::hand_written_attributes;
+use crate::item::from_attributes;
+use crate::{DdbClient, DdbError, DdbMap, Expression, ExpressionAttributes};
+use rusoto_dynamodb::UpdateItemInput;
+use serde::de::DeserializeOwned;
+
+/// # Dynamodb update_item builder
+/// Applies an `UpdateExpression`, or a hand written update expression, to the item with `key`,
+/// optionally guarded by a condition. The attributes chosen with `return_values` are
+/// deserialized into `T`, `send` returns `None` when nothing is returned.
+/// ```
+/// # use rusoto_core::Region;
+/// # use rusoto_dynamodb::DynamoDbClient;
+/// # use serde::Deserialize;
+/// # use std::collections::HashMap;
+/// # use ddb_util::*;
+///
+/// # #[derive(Debug, Deserialize)]
+/// # struct Dataset {
+/// #     pk: String,
+/// #     sk: String,
+/// #     version: u64,
+/// # }
+///
+/// # #[cfg(feature = "local")]
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), DdbError> {
+/// # let local = match LocalDynamoDb::from_env()? {
+/// #     Some(local) => local,
+/// #     None => return Ok(()),
+/// # };
+/// # let relations = local.fixture("fixtures/relations.json").await?;
+/// # let relations = relations.as_str();
+/// let client = DynamoDbClient::new(Region::EuWest1);
+/// # let client = local.client().clone();
+/// let mut key: DdbMap = HashMap::new();
+/// set_kv(&mut key, "pk".to_string(), "c4c".to_string());
+/// set_kv(&mut key, "sk".to_string(), "dataset#1".to_string());
+/// let res = UpdateItem::new(relations, key)
+///     .update(UpdateExpression::new().set("status", "done").increment("version", 1))
+///     .condition(attr("version").eq(3))
+///     .return_values("ALL_NEW")
+///     .send::<Dataset>(&client)
+///     .await;
+/// match res {
+///     Ok(dataset) => println!("{:?}", dataset),
+///     Err(e) if e.is_conditional_check_failed() => println!("updated concurrently"),
+///     Err(e) => return Err(e),
+/// }
+/// # local.teardown().await?;
+/// #     Ok(())
+/// # }
+/// # #[cfg(not(feature = "local"))]
+/// # fn main() {}
+/// ```
+#[derive(Clone, Debug)]
+pub struct UpdateItem {
+    input: UpdateItemInput,
+    attrs: ExpressionAttributes,
+}
+
+impl UpdateItem {
+    pub fn new(table: &str, key: DdbMap) -> UpdateItem {
+        UpdateItem {
+            input: UpdateItemInput {
+                table_name: table.to_string(),
+                key,
+                ..Default::default()
+            },
+            attrs: ExpressionAttributes::default(),
+        }
+    }
+
+    pub fn update(mut self, update_exp: impl Expression) -> Self {
+        self.input.update_expression = Some(update_exp.render(&mut self.attrs));
+        self
+    }
+
+    pub fn condition(mut self, condition_exp: impl Expression) -> Self {
+        self.input.condition_expression = Some(condition_exp.render(&mut self.attrs));
+        self
+    }
+
+    hand_written_attributes!();
+
+    /// One of `NONE`, `ALL_OLD`, `UPDATED_OLD`, `ALL_NEW` or `UPDATED_NEW`
+    pub fn return_values(mut self, return_values: &str) -> Self {
+        self.input.return_values = Some(return_values.to_string());
+        self
+    }
+
+    pub async fn send<T: DeserializeOwned>(
+        self, client: &impl DdbClient,
+    ) -> Result<Option<T>, DdbError> {
+        let input = self.attrs.apply(self.input)?;
+        Ok(from_attributes(client.update_item(input).await?.attributes)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Dataset {
+        pk: String,
+        sk: String,
+        status: Option<String>,
+        version: u64,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Version {
+        version: u64,
+    }
+
+    fn key() -> DdbMap {
+        ddb_map! { "pk" => "c4c", "sk" => "dataset#1" }
+    }
+
+    async fn seeded() -> Result<MemoryDb, DdbError> {
+        let client = MemoryDb::new().table("relations", "pk", Some("sk"));
+        let mut item = key();
+        set_kv(&mut item, "version".to_string(), 3);
+        put_item(&client, "relations", item).await?;
+        Ok(client)
+    }
+
+    fn bump() -> UpdateItem {
+        UpdateItem::new("relations", key())
+            .update(UpdateExpression::new().set("status", "done").increment("version", 1))
+    }
+
+    #[tokio::test]
+    async fn returns_the_attributes_chosen_with_return_values() -> Result<(), DdbError> {
+        let client = seeded().await?;
+        let none: Option<Dataset> = bump().send(&client).await?;
+        assert_eq!(none, None);
+        let old: Option<Version> = bump().return_values("UPDATED_OLD").send(&client).await?;
+        assert_eq!(old, Some(Version { version: 4 }));
+        let new: Option<Dataset> = bump().return_values("ALL_NEW").send(&client).await?;
+        let expected = Dataset {
+            pk: "c4c".to_string(),
+            sk: "dataset#1".to_string(),
+            status: Some("done".to_string()),
+            version: 6,
+        };
+        assert_eq!(new, Some(expected));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failed_conditions_leave_the_item_unchanged() -> Result<(), DdbError> {
+        let client = seeded().await?;
+        let res = bump().condition(attr("version").eq(2)).send::<Dataset>(&client).await;
+        assert!(res.unwrap_err().is_conditional_check_failed());
+        assert_eq!(client.items("relations")[0].get_n::<u64>("version"), Some(3));
+        let new: Option<Version> = bump()
+            .condition(attr("version").eq(3))
+            .return_values("UPDATED_NEW")
+            .send(&client)
+            .await?;
+        assert_eq!(new, Some(Version { version: 4 }));
+        Ok(())
+    }
+}
@@ -0,0 +1,495 @@
+use crate::DdbError;
+use bytes::Bytes;
+use rusoto_dynamodb::AttributeValue;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+/// Conversion of a Rust value into the matching DynamoDB attribute value
+///
+/// Strings become `S`, numbers `N`, booleans `BOOL` and byte vectors and `Bytes` `B`. Other
+/// vectors become `L` lists and maps with string keys `M` maps. Sets of strings, integers and
+/// byte vectors become `SS`, `NS` and `BS`, and `None` becomes `NULL`. With the `chrono`, `uuid`
+/// and `rust_decimal` features, timestamps become RFC 3339 strings in UTC, dates `YYYY-MM-DD`
+/// strings, uuids hyphenated strings and decimals numbers. An `AttributeValue` is passed
+/// through unchanged, so anything not covered here can still be built by hand.
+///
+/// DynamoDB has no empty sets and no `NaN` or infinite numbers, and the conversion cannot fail,
+/// so an empty set and a non-finite float become `NULL`, where `to_item` rejects them. A
+/// `NULL` converts back into an empty set. There is no impl for `u8`, it would make `Vec<u8>`
+/// ambiguous between `B` and a list of numbers; widen single bytes with `u16::from`.
+pub trait IntoAttributeValue {
+    fn into_attribute_value(self) -> AttributeValue;
+}
+
+/// Conversion of a DynamoDB attribute value into a Rust value, the inverse of
+/// `IntoAttributeValue`
+///
+/// Fails with `DdbError::Conversion` when the attribute has another type or the number does
+/// not fit. `Option<T>` is `None` for `NULL`, and is how optional attributes are read with
+/// `DdbMapExt::get_as`.
+/// ```
+/// # use ddb_util::*;
+/// # use std::collections::BTreeSet;
+/// # fn main() -> Result<(), DdbError> {
+/// let versions: BTreeSet<i64> = vec![1, 2].into_iter().collect();
+/// let item = ddb_map! { "pk" => "c4c", "versions" => versions.clone() };
+/// assert_eq!(item.get_as::<BTreeSet<i64>>("versions")?, versions);
+/// assert_eq!(item.get_as::<Option<String>>("owner")?, None);
+/// assert!(item.get_as::<i64>("pk").is_err());
+/// #     Ok(())
+/// # }
+/// ```
+pub trait FromAttributeValue: Sized {
+    fn from_attribute_value(value: AttributeValue) -> Result<Self, DdbError>;
+}
+
+/// The DynamoDB type of `value`, for conversion errors
+fn type_of(value: &AttributeValue) -> &'static str {
+    let types = [
+        ("S", value.s.is_some()),
+        ("N", value.n.is_some()),
+        ("B", value.b.is_some()),
+        ("BOOL", value.bool.is_some()),
+        ("NULL", value.null.is_some()),
+        ("L", value.l.is_some()),
+        ("M", value.m.is_some()),
+        ("SS", value.ss.is_some()),
+        ("NS", value.ns.is_some()),
+        ("BS", value.bs.is_some()),
+    ];
+    types.iter().find(|(_, set)| *set).map_or("no value", |(name, _)| name)
+}
+
+fn mismatch<T>(expected: &str, value: &AttributeValue) -> Result<T, DdbError> {
+    Err(DdbError::Conversion(format!("expected {}, got {}", expected, type_of(value))))
+}
+
+fn parse_number<T: std::str::FromStr>(n: &str) -> Result<T, DdbError> {
+    n.parse().map_err(|_| {
+        DdbError::Conversion(format!("{} is not a valid {}", n, std::any::type_name::<T>()))
+    })
+}
+
+fn null() -> AttributeValue {
+    AttributeValue {
+        null: Some(true),
+        ..Default::default()
+    }
+}
+
+impl IntoAttributeValue for AttributeValue {
+    fn into_attribute_value(self) -> AttributeValue {
+        self
+    }
+}
+
+impl FromAttributeValue for AttributeValue {
+    fn from_attribute_value(value: AttributeValue) -> Result<Self, DdbError> {
+        Ok(value)
+    }
+}
+
+impl IntoAttributeValue for String {
+    fn into_attribute_value(self) -> AttributeValue {
+        AttributeValue {
+            s: Some(self),
+            ..Default::default()
+        }
+    }
+}
+
+impl FromAttributeValue for String {
+    fn from_attribute_value(value: AttributeValue) -> Result<Self, DdbError> {
+        match value.s {
+            Some(s) => Ok(s),
+            None => mismatch("S", &value),
+        }
+    }
+}
+
+impl IntoAttributeValue for &str {
+    fn into_attribute_value(self) -> AttributeValue {
+        self.to_string().into_attribute_value()
+    }
+}
+
+impl IntoAttributeValue for bool {
+    fn into_attribute_value(self) -> AttributeValue {
+        AttributeValue {
+            bool: Some(self),
+            ..Default::default()
+        }
+    }
+}
+
+impl FromAttributeValue for bool {
+    fn from_attribute_value(value: AttributeValue) -> Result<Self, DdbError> {
+        match value.bool {
+            Some(b) => Ok(b),
+            None => mismatch("BOOL", &value),
+        }
+    }
+}
+
+impl IntoAttributeValue for Vec<u8> {
+    fn into_attribute_value(self) -> AttributeValue {
+        Bytes::from(self).into_attribute_value()
+    }
+}
+
+impl FromAttributeValue for Vec<u8> {
+    fn from_attribute_value(value: AttributeValue) -> Result<Self, DdbError> {
+        Bytes::from_attribute_value(value).map(|b| b.to_vec())
+    }
+}
+
+impl IntoAttributeValue for Bytes {
+    fn into_attribute_value(self) -> AttributeValue {
+        AttributeValue {
+            b: Some(self),
+            ..Default::default()
+        }
+    }
+}
+
+impl FromAttributeValue for Bytes {
+    fn from_attribute_value(value: AttributeValue) -> Result<Self, DdbError> {
+        match value.b {
+            Some(b) => Ok(b),
+            None => mismatch("B", &value),
+        }
+    }
+}
+
+macro_rules! number_attribute_value {
+    ($($t:ty),* => $finite:expr) => {
+        $(
+            impl IntoAttributeValue for $t {
+                fn into_attribute_value(self) -> AttributeValue {
+                    if !$finite(self) {
+                        return null();
+                    }
+                    AttributeValue {
+                        n: Some(self.to_string()),
+                        ..Default::default()
+                    }
+                }
+            }
+
+            impl FromAttributeValue for $t {
+                fn from_attribute_value(value: AttributeValue) -> Result<Self, DdbError> {
+                    match &value.n {
+                        Some(n) => parse_number(n),
+                        None => mismatch("N", &value),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+number_attribute_value!(
+    i8, i16, i32, i64, i128, isize, u16, u32, u64, u128, usize => |_| true
+);
+number_attribute_value!(f32 => f32::is_finite);
+number_attribute_value!(f64 => f64::is_finite);
+
+impl<T: IntoAttributeValue> IntoAttributeValue for Option<T> {
+    fn into_attribute_value(self) -> AttributeValue {
+        match self {
+            Some(value) => value.into_attribute_value(),
+            None => null(),
+        }
+    }
+}
+
+impl<T: FromAttributeValue> FromAttributeValue for Option<T> {
+    fn from_attribute_value(value: AttributeValue) -> Result<Self, DdbError> {
+        match value.null {
+            Some(true) => Ok(None),
+            _ => T::from_attribute_value(value).map(Some),
+        }
+    }
+}
+
+impl<T: IntoAttributeValue> IntoAttributeValue for Vec<T> {
+    fn into_attribute_value(self) -> AttributeValue {
+        AttributeValue {
+            l: Some(self.into_iter().map(IntoAttributeValue::into_attribute_value).collect()),
+            ..Default::default()
+        }
+    }
+}
+
+impl<T: FromAttributeValue> FromAttributeValue for Vec<T> {
+    fn from_attribute_value(value: AttributeValue) -> Result<Self, DdbError> {
+        match value.l {
+            Some(l) => l.into_iter().map(T::from_attribute_value).collect(),
+            None => mismatch("L", &value),
+        }
+    }
+}
+
+impl<T: IntoAttributeValue> IntoAttributeValue for HashMap<String, T> {
+    fn into_attribute_value(self) -> AttributeValue {
+        AttributeValue {
+            m: Some(self.into_iter().map(|(k, v)| (k, v.into_attribute_value())).collect()),
+            ..Default::default()
+        }
+    }
+}
+
+impl<T: IntoAttributeValue> IntoAttributeValue for BTreeMap<String, T> {
+    fn into_attribute_value(self) -> AttributeValue {
+        AttributeValue {
+            m: Some(self.into_iter().map(|(k, v)| (k, v.into_attribute_value())).collect()),
+            ..Default::default()
+        }
+    }
+}
+
+macro_rules! map_from_attribute_value {
+    ($($map:ident),*) => {
+        $(
+            impl<T: FromAttributeValue> FromAttributeValue for $map<String, T> {
+                fn from_attribute_value(value: AttributeValue) -> Result<Self, DdbError> {
+                    match value.m {
+                        Some(m) => m
+                            .into_iter()
+                            .map(|(k, v)| Ok((k, T::from_attribute_value(v)?)))
+                            .collect(),
+                        None => mismatch("M", &value),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+map_from_attribute_value!(HashMap, BTreeMap);
+
+macro_rules! set_attribute_value {
+    ($field:ident, $name:literal: $($t:ty),* => $into:expr, $from:expr) => {
+        $(
+            impl IntoAttributeValue for HashSet<$t> {
+                fn into_attribute_value(self) -> AttributeValue {
+                    if self.is_empty() {
+                        return null();
+                    }
+                    AttributeValue {
+                        $field: Some(self.into_iter().map($into).collect()),
+                        ..Default::default()
+                    }
+                }
+            }
+
+            impl IntoAttributeValue for BTreeSet<$t> {
+                fn into_attribute_value(self) -> AttributeValue {
+                    if self.is_empty() {
+                        return null();
+                    }
+                    AttributeValue {
+                        $field: Some(self.into_iter().map($into).collect()),
+                        ..Default::default()
+                    }
+                }
+            }
+
+            impl FromAttributeValue for HashSet<$t> {
+                fn from_attribute_value(value: AttributeValue) -> Result<Self, DdbError> {
+                    match value.$field {
+                        Some(set) => set.into_iter().map($from).collect(),
+                        None if value.null == Some(true) => Ok(Self::new()),
+                        None => mismatch($name, &value),
+                    }
+                }
+            }
+
+            impl FromAttributeValue for BTreeSet<$t> {
+                fn from_attribute_value(value: AttributeValue) -> Result<Self, DdbError> {
+                    match value.$field {
+                        Some(set) => set.into_iter().map($from).collect(),
+                        None if value.null == Some(true) => Ok(Self::new()),
+                        None => mismatch($name, &value),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+set_attribute_value!(ss, "SS": String => std::convert::identity, Ok);
+set_attribute_value!(
+    ns, "NS": i8, i16, i32, i64, i128, isize, u16, u32, u64, u128, usize =>
+    |n| n.to_string(), |n: String| parse_number(&n)
+);
+set_attribute_value!(bs, "BS": Vec<u8> => Bytes::from, |b: Bytes| Ok(b.to_vec()));
+set_attribute_value!(bs, "BS": Bytes => std::convert::identity, Ok);
+
+#[cfg(feature = "chrono")]
+mod chrono_values {
+    use super::{mismatch, FromAttributeValue, IntoAttributeValue};
+    use crate::DdbError;
+    use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+    use rusoto_dynamodb::AttributeValue;
+
+    impl IntoAttributeValue for DateTime<Utc> {
+        fn into_attribute_value(self) -> AttributeValue {
+            self.to_rfc3339_opts(SecondsFormat::AutoSi, true).into_attribute_value()
+        }
+    }
+
+    impl FromAttributeValue for DateTime<Utc> {
+        fn from_attribute_value(value: AttributeValue) -> Result<Self, DdbError> {
+            match &value.s {
+                Some(s) => DateTime::parse_from_rfc3339(s)
+                    .map(|t| t.with_timezone(&Utc))
+                    .map_err(|e| DdbError::Conversion(format!("{}: {}", s, e))),
+                None => mismatch("S", &value),
+            }
+        }
+    }
+
+    impl IntoAttributeValue for NaiveDate {
+        fn into_attribute_value(self) -> AttributeValue {
+            self.format("%Y-%m-%d").to_string().into_attribute_value()
+        }
+    }
+
+    impl FromAttributeValue for NaiveDate {
+        fn from_attribute_value(value: AttributeValue) -> Result<Self, DdbError> {
+            match &value.s {
+                Some(s) => NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                    .map_err(|e| DdbError::Conversion(format!("{}: {}", s, e))),
+                None => mismatch("S", &value),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "uuid")]
+mod uuid_values {
+    use super::{mismatch, FromAttributeValue, IntoAttributeValue};
+    use crate::DdbError;
+    use rusoto_dynamodb::AttributeValue;
+    use uuid::Uuid;
+
+    impl IntoAttributeValue for Uuid {
+        fn into_attribute_value(self) -> AttributeValue {
+            self.hyphenated().to_string().into_attribute_value()
+        }
+    }
+
+    impl FromAttributeValue for Uuid {
+        fn from_attribute_value(value: AttributeValue) -> Result<Self, DdbError> {
+            match &value.s {
+                Some(s) => {
+                    Uuid::parse_str(s).map_err(|e| DdbError::Conversion(format!("{}: {}", s, e)))
+                }
+                None => mismatch("S", &value),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "rust_decimal")]
+mod decimal_values {
+    use super::{mismatch, FromAttributeValue, IntoAttributeValue};
+    use crate::DdbError;
+    use rust_decimal::Decimal;
+    use rusoto_dynamodb::AttributeValue;
+    use std::str::FromStr;
+
+    impl IntoAttributeValue for Decimal {
+        fn into_attribute_value(self) -> AttributeValue {
+            AttributeValue {
+                n: Some(self.normalize().to_string()),
+                ..Default::default()
+            }
+        }
+    }
+
+    impl FromAttributeValue for Decimal {
+        fn from_attribute_value(value: AttributeValue) -> Result<Self, DdbError> {
+            match &value.n {
+                Some(n) => Decimal::from_str(n)
+                    .or_else(|_| Decimal::from_scientific(n))
+                    .map_err(|e| DdbError::Conversion(format!("{}: {}", n, e))),
+                None => mismatch("N", &value),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::collections::{BTreeSet, HashMap, HashSet};
+
+    fn round_trip<T: IntoAttributeValue + FromAttributeValue>(value: T) -> T {
+        T::from_attribute_value(value.into_attribute_value()).unwrap()
+    }
+
+    #[test]
+    fn values_convert_back_into_the_same_type() {
+        assert_eq!(round_trip(-3i64), -3);
+        assert_eq!(round_trip(2.5f64), 2.5);
+        assert_eq!(round_trip("c4c".to_string()), "c4c");
+        assert_eq!(round_trip(vec![0u8, 255]), vec![0u8, 255]);
+        assert_eq!(round_trip(Some(true)), Some(true));
+        assert_eq!(round_trip(None::<u64>), None);
+        let tags: HashSet<String> = vec!["a".to_string(), "b".to_string()].into_iter().collect();
+        assert_eq!(round_trip(tags.clone()), tags);
+        let versions: BTreeSet<i64> = vec![-1, 2].into_iter().collect();
+        assert_eq!(round_trip(versions.clone()), versions);
+        let history: Vec<HashMap<String, u64>> =
+            vec![vec![("version".to_string(), 1)].into_iter().collect()];
+        assert_eq!(round_trip(history.clone()), history);
+    }
+
+    #[test]
+    fn empty_sets_and_non_finite_floats_become_null() {
+        assert_eq!(f64::NAN.into_attribute_value().null, Some(true));
+        assert_eq!(f32::INFINITY.into_attribute_value().null, Some(true));
+        assert_eq!(round_trip(Some(f64::NEG_INFINITY)), None);
+        let tags: HashSet<String> = HashSet::new();
+        assert_eq!(tags.clone().into_attribute_value().null, Some(true));
+        assert_eq!(round_trip(tags.clone()), tags);
+        let versions: BTreeSet<u64> = BTreeSet::new();
+        assert_eq!(round_trip(versions.clone()), versions);
+    }
+
+    #[test]
+    fn mismatched_types_fail_to_convert() {
+        let err = i8::from_attribute_value(300.into_attribute_value()).unwrap_err();
+        assert!(matches!(err, DdbError::Conversion(_)));
+        let err = String::from_attribute_value(1.into_attribute_value()).unwrap_err();
+        assert_eq!(err.to_string(), "attribute conversion failed: expected S, got N");
+    }
+
+    #[test]
+    #[cfg(any(feature = "chrono", feature = "uuid", feature = "rust_decimal"))]
+    fn feature_types_round_trip() {
+        #[cfg(feature = "chrono")]
+        {
+            use chrono::{NaiveDate, TimeZone, Utc};
+            let created = Utc.with_ymd_and_hms(2021, 10, 2, 12, 30, 0).unwrap();
+            assert_eq!(created.into_attribute_value().s.as_deref(), Some("2021-10-02T12:30:00Z"));
+            assert_eq!(round_trip(created), created);
+            let day = NaiveDate::from_ymd_opt(2021, 10, 1).unwrap();
+            assert_eq!(day.into_attribute_value().s.as_deref(), Some("2021-10-01"));
+            assert_eq!(round_trip(day), day);
+        }
+        #[cfg(feature = "uuid")]
+        {
+            let id = uuid::Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap();
+            assert_eq!(round_trip(id), id);
+        }
+        #[cfg(feature = "rust_decimal")]
+        {
+            use std::str::FromStr;
+            let price = rust_decimal::Decimal::from_str("12.50").unwrap();
+            assert_eq!(price.into_attribute_value().n.as_deref(), Some("12.5"));
+            assert_eq!(round_trip(price), price);
+        }
+    }
+}